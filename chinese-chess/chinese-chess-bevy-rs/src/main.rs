@@ -5,7 +5,8 @@ use bevy::sprite::{ MaterialMesh2dBundle, Mesh2dHandle };
 use bevy::render::mesh::PrimitiveTopology;
 
 use std::time::{ Duration, Instant };
-use std::sync::Mutex;
+use std::sync::{ Mutex, OnceLock };
+use std::collections::HashMap;
 
 const CHESS_NORMAL_COLOR: Color = Color::rgb(1., 0.92, 0.63);
 const CHESS_HOVERED_COLOR: Color = Color::rgb(1., 0.96, 0.82);
@@ -14,12 +15,15 @@ const PREVIEW_POINT_COLOR: Color = Color::rgb(0.65, 1., 0.73);
 #[derive(Default, Copy, Clone, Eq, PartialEq, States, Debug, Hash)]
 enum AppState {
     #[default]
+    MainMenu,
+
     Ingame,
 
-    MainMenu,
+    /// 一方被将死、任何一方困毙或局面三次重复时进入，`Some(team)`表示获胜方，`None`表示和棋
+    GameOver(Option<Team>),
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 enum Team {
     Red,
     Black,
@@ -55,6 +59,15 @@ struct HistoryRecord {
     pub from_pos: (i32, i32),
     pub to_pos: (i32, i32),
     pub target_chess: Option<Chess>,
+
+    /// 落子方，用于长将判定时区分周期内哪些招法属于同一队
+    pub mover: Team,
+
+    /// 这一步落子后是否将对方军，长将判定靠它逐步累计
+    pub delivered_check: bool,
+
+    /// 落子后局面的Zobrist签名，供`RepetitionTable`计次，悔棋时据此回退计数
+    pub board_hash: u64,
 }
 
 /// 表示当前实体是一个棋子
@@ -80,6 +93,19 @@ struct CurrentTeam(Team);
 #[derive(Resource, Debug, Deref, DerefMut, Default)]
 struct History(Vec<HistoryRecord>);
 
+/// 每个Zobrist签名出现过的次数，三次重复判和(或长将判负)靠它判断
+#[derive(Resource, Debug, Deref, DerefMut, Default)]
+struct RepetitionTable(HashMap<u64, u8>);
+
+/// 对局是否被暂停；暂停时`game_system`/`transform_animation_system`/`ai_move_system`不再推进
+#[derive(Resource, Default)]
+struct Paused(bool);
+
+/// 复盘游标：`Some(n)`表示正在看开局后第n步的冻结局面，`None`表示当前是实时对局
+/// 复盘期间棋盘由`playback_render_system`接管渲染，`game_system`/`ai_move_system`/`game_over_system`都靠`not_in_playback`暂停
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+struct Playback(Option<usize>);
+
 /// 游戏中的实体
 #[derive(Component)]
 struct Ingame;
@@ -87,6 +113,36 @@ struct Ingame;
 #[derive(Component)]
 struct UndoButton;
 
+/// 存档/读档按钮触发的动作：存档把当前局面写成FEN字符串存到`SAVE_FILE_PATH`，读档反过来重建棋盘
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum SaveLoadAction {
+    Save,
+    Load,
+}
+
+/// 复盘模式的上一步/下一步按钮
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum PlaybackAction {
+    Prev,
+    Next,
+}
+
+/// 标记`GameOver`状态下展示终局结果的文字
+#[derive(Component)]
+struct GameOverText;
+
+/// 主菜单UI的根实体，`OnExit(AppState::MainMenu)`时整体递归销毁
+#[derive(Component)]
+struct MainMenuUi;
+
+/// 主菜单按钮触发的动作
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum MainMenuButton {
+    NewGame,
+    HumanVsAi,
+    Quit,
+}
+
 /// 棋盘实体
 #[derive(Component)]
 struct Chessboard;
@@ -101,6 +157,26 @@ struct ChessButton {
 #[derive(Component)]
 struct TeamSuggestion(Team);
 
+/// 终局结果：`Some(Some(team))`=team获胜，`Some(None)`=和棋，`None`=对局还未结束
+/// `game_over_system`写入，`team_suggestion_system`据此把获胜方的提示三角形永久点亮
+#[derive(Resource, Default, Clone, Copy)]
+struct GameResult(Option<Option<Team>>);
+
+/// 落子相关的音效事件，由`game_system`在对应分支触发，`audio_system`统一播放对应音效
+enum SoundEvent {
+    /// 选中棋子
+    Select,
+
+    /// 不吃子的普通落子
+    Move,
+
+    /// 吃子落子
+    Capture,
+
+    /// 落子后将军
+    Check,
+}
+
 #[derive(Component, Debug)]
 struct TransformAnimation {
     pub begin_time: Instant,
@@ -182,6 +258,246 @@ impl Team {
     }
 }
 
+/// 格子总数，`pos_to_id`/`pos_from_id`的进制
+const POSITION_COUNT: u64 = 90;
+
+/// 被吃子家族编号的进制：0表示未吃子，1..=7对应七种棋子(方向用走子方的对方队伍推出)
+const CAPTURE_ROLE_COUNT: u64 = 8;
+
+/// ICCS坐标：列a-i对应x(0..8)，行数字对应y(0..9)，如`(1, 2)`记作`b2`
+fn pos_to_iccs(pos: (i32, i32))-> String {
+    format!("{}{}", (b'a' + pos.0 as u8) as char, pos.1)
+}
+
+fn pos_from_iccs(s: &str)-> Option<(i32, i32)> {
+    let mut chars = s.chars();
+    let file = chars.next()?;
+    if !('a'..='i').contains(&file) {
+        return None;
+    }
+    let y: i32 = chars.as_str().parse().ok()?;
+    if !(0..=9).contains(&y) {
+        return None;
+    }
+    Some((file as i32 - 'a' as i32, y))
+}
+
+fn move_to_iccs(from: (i32, i32), to: (i32, i32))-> String {
+    format!("{}-{}", pos_to_iccs(from), pos_to_iccs(to))
+}
+
+fn move_from_iccs(mv: &str)-> Option<((i32, i32), (i32, i32))> {
+    let (from_str, to_str) = mv.split_once('-')?;
+    Some((pos_from_iccs(from_str)?, pos_from_iccs(to_str)?))
+}
+
+/// `y*9+x`形式的格子编号，0..89
+fn pos_to_id(pos: (i32, i32))-> u64 {
+    (pos.1 * 9 + pos.0) as u64
+}
+
+fn pos_from_id(id: u64)-> (i32, i32) {
+    ((id % 9) as i32, (id / 9) as i32)
+}
+
+/// 被吃子的家族编号；方向类棋子(炮打过河的兵/相)的方向由吃子方的对方队伍决定，不需要单独编码
+fn captured_role_to_id(role: Option<Role>)-> u64 {
+    match role {
+        None               => 0,
+        Some(Role::King)   => 1,
+        Some(Role::Guard)  => 2,
+        Some(Role::Cannon) => 3,
+        Some(Role::Bishop(_)) => 4,
+        Some(Role::Horse)  => 5,
+        Some(Role::Pawn(_)) => 6,
+        Some(Role::Chariot) => 7,
+    }
+}
+
+/// `captured_role_to_id`的逆过程；`mover_team`是落子方(吃子方)，被吃子属于其对方队伍
+fn captured_role_from_id(mover_team: Team, id: u64)-> Option<Role> {
+    let captured_team = mover_team.opposite();
+    match id {
+        1 => Some(Role::King),
+        2 => Some(Role::Guard),
+        3 => Some(Role::Cannon),
+        4 => Some(Role::Bishop(captured_team == Team::Red)),
+        5 => Some(Role::Horse),
+        6 => Some(Role::Pawn(captured_team == Team::Red)),
+        7 => Some(Role::Chariot),
+        _ => None,
+    }
+}
+
+/// 把一步棋编码为`(from_id * POSITION_COUNT + to_id) * CAPTURE_ROLE_COUNT + captured`形式的整数，便于存档/分享
+fn encode_move(from: (i32, i32), to: (i32, i32), captured: Option<Role>)-> u64 {
+    let from_id = pos_to_id(from);
+    let to_id = pos_to_id(to);
+    let captured_id = captured_role_to_id(captured);
+    (from_id * POSITION_COUNT + to_id) * CAPTURE_ROLE_COUNT + captured_id
+}
+
+fn decode_move(code: u64)-> ((i32, i32), (i32, i32), u64) {
+    let captured_id = code % CAPTURE_ROLE_COUNT;
+    let rest = code / CAPTURE_ROLE_COUNT;
+    let to_id = rest % POSITION_COUNT;
+    let from_id = rest / POSITION_COUNT;
+    (pos_from_id(from_id), pos_from_id(to_id), captured_id)
+}
+
+/// Zobrist表里棋子种类的数量：2个队伍 * 9种子力(相/兵按朝向各算一种)
+const ZOBRIST_PIECE_KINDS: usize = 18;
+
+/// 固定的随机数表，给每个(队伍, 子力种类, 格子)组合分配一个`u64`异或键，外加一个走子方键
+struct ZobristTable {
+    piece_keys: [[u64; POSITION_COUNT as usize]; ZOBRIST_PIECE_KINDS],
+    side_to_move_key: u64,
+}
+
+impl ZobristTable {
+    fn piece_key(&self, team: Team, role: Role, pos: (i32, i32))-> u64 {
+        self.piece_keys[zobrist_piece_index(team, role)][pos_to_id(pos) as usize]
+    }
+}
+
+/// (队伍, 子力种类)在Zobrist表里的下标
+fn zobrist_piece_index(team: Team, role: Role)-> usize {
+    let role_index = match role {
+        Role::King         => 0,
+        Role::Guard        => 1,
+        Role::Cannon       => 2,
+        Role::Bishop(false) => 3,
+        Role::Bishop(true)  => 4,
+        Role::Horse        => 5,
+        Role::Pawn(false)  => 6,
+        Role::Pawn(true)   => 7,
+        Role::Chariot      => 8,
+    };
+    let team_index = match team {
+        Team::Red   => 0,
+        Team::Black => 1,
+    };
+    team_index * 9 + role_index
+}
+
+/// 固定种子的splitmix64，只在首次用到时生成一次，保证同一局棋重复哈希出的值一致
+fn zobrist_table()-> &'static ZobristTable {
+    static TABLE: OnceLock<ZobristTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut state = 0x9E3779B97F4A7C15u64;
+        let mut next_key = || {
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+
+        let mut piece_keys = [[0u64; POSITION_COUNT as usize]; ZOBRIST_PIECE_KINDS];
+        for kind_keys in piece_keys.iter_mut() {
+            for key in kind_keys.iter_mut() {
+                *key = next_key();
+            }
+        }
+
+        ZobristTable {
+            piece_keys,
+            side_to_move_key: next_key(),
+        }
+    })
+}
+
+/// 在造成三次重复的局面里检查是否有一方长将：若某队在重复周期内每一步都将军，判其负而非判和
+/// 只看凑成三次重复的最近一个周期，更早的重复不影响这次判定
+fn perpetual_check_loser(history: &[HistoryRecord], repeating_hash: u64)-> Option<Team> {
+    let occurrences: Vec<usize> = history.iter().enumerate()
+        .filter(|(_, record)| record.board_hash == repeating_hash)
+        .map(|(index, _)| index)
+        .collect();
+    let &[.., a, b, c] = occurrences.as_slice() else { return None; };
+    if b - a != c - b {
+        return None;
+    }
+
+    let cycle = &history[a + 1..=c];
+    [Team::Red, Team::Black].into_iter().find(|&team| {
+        let moves: Vec<&HistoryRecord> = cycle.iter().filter(|record| record.mover == team).collect();
+        !moves.is_empty() && moves.iter().all(|record| record.delivered_check)
+    })
+}
+
+/// 存档文件的路径，存档/读档按钮读写的就是这个文件
+const SAVE_FILE_PATH: &str = "xiangqi_save.fen";
+
+/// 子力对应的FEN字母，沿用国际通行的车(r)/马(n)/象(b)/士(a)/将(k)/炮(c)/卒(p)记法；大小写区分红黑在`BoardState::to_fen`里处理
+fn role_to_fen_letter(role: Role)-> char {
+    match role {
+        Role::Chariot   => 'r',
+        Role::Horse     => 'n',
+        Role::Bishop(_) => 'b',
+        Role::Guard     => 'a',
+        Role::King      => 'k',
+        Role::Cannon    => 'c',
+        Role::Pawn(_)   => 'p',
+    }
+}
+
+/// `role_to_fen_letter`的逆过程；相/兵的朝向由队伍决定，与`BoardState::initial`保持一致(黑方`false`，红方`true`)
+fn fen_letter_to_role(letter: char, team: Team)-> Option<Role> {
+    Some(match letter.to_ascii_lowercase() {
+        'r' => Role::Chariot,
+        'n' => Role::Horse,
+        'b' => Role::Bishop(team == Team::Red),
+        'a' => Role::Guard,
+        'k' => Role::King,
+        'c' => Role::Cannon,
+        'p' => Role::Pawn(team == Team::Red),
+        _ => return None,
+    })
+}
+
+impl History {
+    /// 导出为以空格分隔的ICCS坐标记录，如`b2-e2 h8-h4`
+    fn to_iccs(&self)-> String {
+        self.0.iter()
+            .map(|record| move_to_iccs(record.from_pos, record.to_pos))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// 从ICCS记录在一份全新初始局面上重放，返回重放后的局面
+    fn from_iccs(s: &str)-> Option<BoardState> {
+        let mut board = BoardState::initial();
+        for mv in s.split_whitespace() {
+            let (from, to) = move_from_iccs(mv)?;
+            board = board.apply_move(from, to);
+        }
+        Some(board)
+    }
+
+    /// 导出为每步一个`u64`的紧凑编码，`captured`记录被吃子的棋子家族，便于复盘校验
+    fn to_encoded(&self)-> Vec<u64> {
+        self.0.iter()
+            .map(|record| encode_move(record.from_pos, record.to_pos, record.target_chess.as_ref().map(|c| c.role)))
+            .collect()
+    }
+
+    /// 从整数编码在一份全新初始局面上重放，返回重放后的局面
+    fn from_encoded(codes: &[u64])-> Option<BoardState> {
+        let mut board = BoardState::initial();
+        for &code in codes {
+            let (from, to, captured_id) = decode_move(code);
+            let expected_captured = captured_role_from_id(board.to_move, captured_id);
+            let actual_captured = board.get_chess_at(to).map(|c| c.role);
+            if expected_captured != actual_captured {
+                return None;
+            }
+            board = board.apply_move(from, to);
+        }
+        Some(board)
+    }
+}
+
 impl TransformAnimation {
     pub fn get_progress(&self)-> f32 {
         if self.is_done() {
@@ -274,16 +590,56 @@ fn main() {
         .add_plugins(DefaultPlugins)
 
         .insert_resource(winit_settings)
+        .add_state::<AppState>()
+        .init_resource::<Paused>()
+        .init_resource::<Playback>()
+        .init_resource::<GameResult>()
+        .add_event::<SoundEvent>()
+
+        .add_startup_system(app_setup_system)
+        .add_system(game_setup_system.in_schedule(OnEnter(AppState::Ingame)))
+        .add_system(despawn_ingame_system.in_schedule(OnExit(AppState::Ingame)))
+        .add_system(main_menu_setup_system.in_schedule(OnEnter(AppState::MainMenu)))
+        .add_system(main_menu_despawn_system.in_schedule(OnExit(AppState::MainMenu)))
+        .add_system(main_menu_button_system.run_if(in_state(AppState::MainMenu)))
+        .add_system(pause_toggle_system.run_if(in_state(AppState::Ingame)))
 
-        .add_startup_system(game_setup_system)
         .add_system(window_size_update_system)
         .add_system(team_suggestion_system)
-        .add_system(transform_animation_system)
-        .add_systems((game_system, chessboard_system).after(window_size_update_system))
+        .add_system(chessboard_system.after(window_size_update_system))
+        .add_system(transform_animation_system.run_if(game_is_running))
+        .add_system(game_system.after(window_size_update_system).run_if(in_state(AppState::Ingame)).run_if(game_is_running).run_if(not_in_playback))
+        .add_system(save_load_system.after(window_size_update_system).run_if(in_state(AppState::Ingame)).run_if(not_in_playback))
+        .add_system(ai_move_system.after(game_system).run_if(in_state(AppState::Ingame)).run_if(game_is_running).run_if(not_in_playback))
+        .add_system(game_over_system.after(ai_move_system).run_if(not_in_playback))
+        .add_system(game_over_ui_system.after(game_over_system))
+        .add_system(playback_control_system.after(window_size_update_system).run_if(in_state(AppState::Ingame)))
+        .add_system(playback_render_system.after(playback_control_system).run_if(in_state(AppState::Ingame)).run_if(in_playback_or_just_exited))
+        .add_system(audio_system.after(game_system))
 
         .run()
 }
 
+/// `Paused`的取反，供需要在暂停时冻结的系统做`run_if`条件
+fn game_is_running(paused: Res<Paused>)-> bool {
+    !paused.0
+}
+
+/// 复盘期间棋盘被冻结成历史局面，落子、AI走棋、终局判定都不应该运行
+fn not_in_playback(playback: Res<Playback>)-> bool {
+    playback.0.is_none()
+}
+
+/// 供`playback_render_system`做`run_if`条件：复盘期间每帧都要能刷新棋子摆放，
+/// 复盘刚结束(游标变回`None`)那一帧也要再刷新一次才能回到实时对局的局面；
+/// 此后`Playback`不再变化就不用跑了，不然`history.len()`每步都在涨，会让这系统在实时对局里每步都重建一次棋子
+fn in_playback_or_just_exited(playback: Res<Playback>, mut was_in_playback: Local<bool>)-> bool {
+    let currently_in_playback = playback.0.is_some();
+    let just_exited = !currently_in_playback && *was_in_playback;
+    *was_in_playback = currently_in_playback;
+    currently_in_playback || just_exited
+}
+
 /// 将屏幕坐标系转换为Bevy所使用的中央坐标系
 fn screen_to_bevy(window_size: WindowSize, position: (f32, f32))-> (f32, f32) {
     (position.0 - window_size.0 / 2., window_size.1 / 2. - position.1)
@@ -529,204 +885,228 @@ fn get_team_suggestion_color(team: Team, activated: bool)-> Color {
 }
 
 /// 确定棋子可以走哪几格
+/// 把ECS里的棋子镜像成`BoardState`，交给它做炮打隔山子/送将检测等纯数据上的推演
 /// (实在是想不到用什么名字了(· д ·))
 fn get_where_can_go(target: Entity, world: &World, chess_query: &QueryState<(Entity, &Chess), With<Ingame>>)-> Vec<(i32, i32)> {
-    let mut reachable_points = get_reachable_points(target, world, chess_query);
-
-    let chess = chess_query.get_manual(world, target).unwrap().1;
-
-    if chess.role == Role::King {
-        reachable_points.retain(|&p| {
-            for (entity, iter_chess) in chess_query.iter_manual(world) {
-                if chess.position != iter_chess.position
-                    && get_reachable_points(entity, world, chess_query)
-                        .contains(&p)
-                    && chess.team != iter_chess.team
-                {
-                    return false;
-                }
-            }
-            true
-        });
-    } else if chess.role == Role::Cannon {
-        fn is_invalid(pos: &(i32, i32))-> bool {
-            pos.0 < 0 || pos.0 > 8 || pos.1 < 0 || pos.1 > 9
-        }
-
-        let get_chess_at = |position: (i32, i32)|-> Option<&Chess> {
-            if is_invalid(&position) {
-                None
-            } else {
-                if let Some((_, chess)) = chess_query.iter_manual(world).find(|i| i.1.position == position) {
-                    Some(chess)
-                } else {
-                    None
-                }
-            }
-        };
-
-        let points = &mut reachable_points;
-        let p = &chess.position;
-        points.clear();
-        macro_rules! cannon_search_path {
-            ($loop_name:ident; $update:expr) => {
-                let mut $loop_name = 0;
-                for _ in 1.. {
-                    $loop_name += 1;
-                    let current_point = $update;
-                    if let Some(_) = get_chess_at(current_point) {
-                        break;
-                    } else if is_invalid(&current_point) {
-                        break;
-                    } else {
-                        points.push(current_point);
-                    }
-                }
-                for _ in 0.. {
-                    $loop_name += 1;
-                    let current_point = $update;
-                    if let Some(chess_in_path) = get_chess_at(current_point) {
-                        if chess_in_path.team != chess.team {
-                            points.push(current_point);
-                        }
-                        break;
-                    } else if is_invalid(&current_point) {
-                        break;
-                    }
-                }
-            };
-        }
-
-        cannon_search_path!(i; (p.0 + i, p.1));
-        cannon_search_path!(i; (p.0 - i, p.1));
-        cannon_search_path!(i; (p.0, p.1 + i));
-        cannon_search_path!(i; (p.0, p.1 - i));
+    let mut pieces = HashMap::new();
+    for (_, chess) in chess_query.iter_manual(world) {
+        pieces.insert(chess.position, chess.clone());
     }
+    let target_chess = chess_query.get_manual(world, target).unwrap().1;
+    let board = BoardState { pieces, to_move: target_chess.team };
 
-    // 排除友方棋子
-    reachable_points.retain(|&p| {
-        if let Some((_, chess_at_point)) = chess_query.iter_manual(world).find(|i| i.1.position == p) {
-            if chess_at_point.team == chess.team {
-                return false;
-            }
-        }
-        true
-    });
+    board.get_where_can_go(target_chess.position)
+}
 
-    reachable_points
+/// 单人模式下电脑所控制的一方；为`None`时代表双人对战
+#[derive(Resource, Default)]
+struct AiOpponent(Option<Team>);
+
+/// 黑方初始摆放；红方由`game_setup_system`/`BoardState::initial`按中线镜像生成
+const INITIAL_HALF_BOARD: &[(Role, (i32, i32))] = &[
+    (Role::Chariot,       (0, 0)),
+    (Role::Horse,         (1, 0)),
+    (Role::Bishop(false), (2, 0)),
+    (Role::Guard,         (3, 0)),
+    (Role::King,          (4, 0)),
+    (Role::Guard,         (5, 0)),
+    (Role::Bishop(false), (6, 0)),
+    (Role::Horse,         (7, 0)),
+    (Role::Chariot,       (8, 0)),
+    (Role::Cannon,        (1, 2)),
+    (Role::Cannon,        (7, 2)),
+    (Role::Pawn(false),   (0, 3)),
+    (Role::Pawn(false),   (2, 3)),
+    (Role::Pawn(false),   (4, 3)),
+    (Role::Pawn(false),   (6, 3)),
+    (Role::Pawn(false),   (8, 3)),
+];
+
+/// ECS `World`难以推演假设局面
+/// `BoardState`把棋盘镜像成一份普通数据，让AI可以克隆、落子、递归搜索
+#[derive(Clone)]
+struct BoardState {
+    pieces: HashMap<(i32, i32), Chess>,
+    to_move: Team,
 }
 
-/// 确定棋子可以够到的格子
-fn get_reachable_points(target: Entity, world: &World, chess_query: &QueryState<(Entity, &Chess), With<Ingame>>)-> Vec<(i32, i32)> {
-    let chess = chess_query.get_manual(world, target).unwrap().1;
-    let mut points = Vec::new();
-    let get_chess_at = |position: (i32, i32)|-> Option<&Chess> {
-        if is_invalid(&position) {
+impl BoardState {
+    fn pos_is_invalid(pos: (i32, i32))-> bool {
+        pos.0 < 0 || pos.0 > 8 || pos.1 < 0 || pos.1 > 9
+    }
+
+    fn get_chess_at(&self, position: (i32, i32))-> Option<&Chess> {
+        if Self::pos_is_invalid(position) {
             None
         } else {
-            if let Some((_, chess)) = chess_query.iter_manual(world).find(|i| i.1.position == position) {
-                Some(chess)
-            } else {
-                None
-            }
+            self.pieces.get(&position)
         }
-    };
-
-    fn is_invalid(pos: &(i32, i32))-> bool {
-        pos.0 < 0 || pos.0 > 8 || pos.1 < 0 || pos.1 > 9
     }
 
-    let p = &chess.position;
-    match chess.role {
-        Role::King => {
-            points.push((p.0 - 1, p.1));
-            points.push((p.0 + 1, p.1));
-            points.push((p.0, p.1 - 1));
-            points.push((p.0, p.1 + 1));
-            points.retain(|&p| !(
-                p.0 < 3 || p.0 > 5 ||
-                (2 < p.1 && p.1 < 7)
-            ));
-        },
+    /// 按走法规则算出棋子能到达的格子；结果可能含友方棋子所在格，也不排除送将的招法
+    fn get_reachable_points(&self, pos: (i32, i32))-> Vec<(i32, i32)> {
+        let chess = self.pieces.get(&pos).unwrap();
+        let mut points = Vec::new();
+        let p = &pos;
+        let get_chess_at = |position: (i32, i32)| self.get_chess_at(position);
+
+        match chess.role {
+            Role::King => {
+                points.push((p.0 - 1, p.1));
+                points.push((p.0 + 1, p.1));
+                points.push((p.0, p.1 - 1));
+                points.push((p.0, p.1 + 1));
+                points.retain(|&p| !(
+                    p.0 < 3 || p.0 > 5 ||
+                    (2 < p.1 && p.1 < 7)
+                ));
+            },
 
-        Role::Guard => {
-            points.push((p.0 - 1, p.1 - 1));
-            points.push((p.0 - 1, p.1 + 1));
-            points.push((p.0 + 1, p.1 - 1));
-            points.push((p.0 + 1, p.1 + 1));
-            points.retain(|&p| !(
-                p.0 < 3 || p.0 > 5 ||
-                (2 < p.1 && p.1 < 7)
-            ));
-        },
+            Role::Guard => {
+                points.push((p.0 - 1, p.1 - 1));
+                points.push((p.0 - 1, p.1 + 1));
+                points.push((p.0 + 1, p.1 - 1));
+                points.push((p.0 + 1, p.1 + 1));
+                points.retain(|&p| !(
+                    p.0 < 3 || p.0 > 5 ||
+                    (2 < p.1 && p.1 < 7)
+                ));
+            },
 
-        Role::Bishop(flag) => {
-            if get_chess_at((p.0 - 1, p.1 - 1)).is_none() {
-                points.push((p.0 - 2, p.1 - 2));
-            }
-            if get_chess_at((p.0 - 1, p.1 + 1)).is_none() {
-                points.push((p.0 - 2, p.1 + 2));
-            }
-            if get_chess_at((p.0 + 1, p.1 - 1)).is_none() {
-                points.push((p.0 + 2, p.1 - 2));
-            }
-            if get_chess_at((p.0 + 1, p.1 + 1)).is_none() {
-                points.push((p.0 + 2, p.1 + 2));
-            }
-            if !flag {
-                points.retain(|&p| p.1 <= 4);
-            } else {
-                points.retain(|&p| p.1 >= 5);
-            }
-        },
+            Role::Bishop(flag) => {
+                if get_chess_at((p.0 - 1, p.1 - 1)).is_none() {
+                    points.push((p.0 - 2, p.1 - 2));
+                }
+                if get_chess_at((p.0 - 1, p.1 + 1)).is_none() {
+                    points.push((p.0 - 2, p.1 + 2));
+                }
+                if get_chess_at((p.0 + 1, p.1 - 1)).is_none() {
+                    points.push((p.0 + 2, p.1 - 2));
+                }
+                if get_chess_at((p.0 + 1, p.1 + 1)).is_none() {
+                    points.push((p.0 + 2, p.1 + 2));
+                }
+                if !flag {
+                    points.retain(|&p| p.1 <= 4);
+                } else {
+                    points.retain(|&p| p.1 >= 5);
+                }
+            },
 
-        Role::Horse => {
-            if get_chess_at((p.0, p.1 - 1)).is_none() {
-                points.push((p.0 - 1, p.1 - 2));
-                points.push((p.0 + 1, p.1 - 2));
-            }
-            if get_chess_at((p.0, p.1 + 1)).is_none() {
-                points.push((p.0 - 1, p.1 + 2));
-                points.push((p.0 + 1, p.1 + 2));
-            }
-            if get_chess_at((p.0 - 1, p.1)).is_none() {
-                points.push((p.0 - 2, p.1 - 1));
-                points.push((p.0 - 2, p.1 + 1));
-            }
-            if get_chess_at((p.0 + 1, p.1)).is_none() {
-                points.push((p.0 + 2, p.1 - 1));
-                points.push((p.0 + 2, p.1 + 1));
-            }
-        },
+            Role::Horse => {
+                if get_chess_at((p.0, p.1 - 1)).is_none() {
+                    points.push((p.0 - 1, p.1 - 2));
+                    points.push((p.0 + 1, p.1 - 2));
+                }
+                if get_chess_at((p.0, p.1 + 1)).is_none() {
+                    points.push((p.0 - 1, p.1 + 2));
+                    points.push((p.0 + 1, p.1 + 2));
+                }
+                if get_chess_at((p.0 - 1, p.1)).is_none() {
+                    points.push((p.0 - 2, p.1 - 1));
+                    points.push((p.0 - 2, p.1 + 1));
+                }
+                if get_chess_at((p.0 + 1, p.1)).is_none() {
+                    points.push((p.0 + 2, p.1 - 1));
+                    points.push((p.0 + 2, p.1 + 1));
+                }
+            },
 
-        Role::Chariot => {
-            macro_rules! chariot_search_path {
-                ($loop_name:ident; $update:expr) => {
-                    for $loop_name in 1.. {
-                        let current_point = $update;
-                        if let Some(chess_in_path) = get_chess_at(current_point) {
-                            if chess_in_path.team != chess.team {
+            Role::Chariot => {
+                macro_rules! chariot_search_path {
+                    ($loop_name:ident; $update:expr) => {
+                        for $loop_name in 1.. {
+                            let current_point = $update;
+                            if let Some(chess_in_path) = get_chess_at(current_point) {
+                                if chess_in_path.team != chess.team {
+                                    points.push(current_point);
+                                    break;
+                                } else {
+                                    break;
+                                }
+                            } else if Self::pos_is_invalid(current_point) {
+                                break;
+                            } else {
                                 points.push(current_point);
+                            }
+                        }
+                    };
+                }
+
+                chariot_search_path!(i; (p.0 - i, p.1));
+                chariot_search_path!(i; (p.0 + i, p.1));
+                chariot_search_path!(i; (p.0, p.1 - i));
+                chariot_search_path!(i; (p.0, p.1 + i));
+            },
+
+            Role::Cannon => {
+                macro_rules! cannon_search_path {
+                    ($loop_name:ident; $update:expr) => {
+                        let mut $loop_name = 0;
+                        for _ in 1.. {
+                            $loop_name += 1;
+                            let current_point = $update;
+                            if let Some(_) = get_chess_at(current_point) {
+                                break;
+                            } else if Self::pos_is_invalid(current_point) {
                                 break;
                             } else {
+                                points.push(current_point);
+                            }
+                        }
+                        for _ in 0.. {
+                            $loop_name += 1;
+                            let current_point = $update;
+                            if let Some(chess_in_path) = get_chess_at(current_point) {
+                                if chess_in_path.team != chess.team {
+                                    points.push(current_point);
+                                }
+                                break;
+                            } else if Self::pos_is_invalid(current_point) {
                                 break;
                             }
-                        } else if is_invalid(&current_point) {
-                            break;
-                        } else {
-                            points.push(current_point);
                         }
+                    };
+                }
+
+                cannon_search_path!(i; (p.0 + i, p.1));
+                cannon_search_path!(i; (p.0 - i, p.1));
+                cannon_search_path!(i; (p.0, p.1 + i));
+                cannon_search_path!(i; (p.0, p.1 - i));
+            },
+
+            Role::Pawn(flag) => {
+                if !flag {
+                    points.push((p.0, p.1 + 1));
+                    if p.1 > 4 {
+                        points.push((p.0 - 1, p.1));
+                        points.push((p.0 + 1, p.1));
                     }
-                };
-            }
+                } else {
+                    points.push((p.0, p.1 - 1));
+                    if p.1 < 5 {
+                        points.push((p.0 - 1, p.1));
+                        points.push((p.0 + 1, p.1));
+                    }
+                }
+            },
+        }
 
-            chariot_search_path!(i; (p.0 - i, p.1));
-            chariot_search_path!(i; (p.0 + i, p.1));
-            chariot_search_path!(i; (p.0, p.1 - i));
-            chariot_search_path!(i; (p.0, p.1 + i));
-        },
+        points.retain(|&p| !Self::pos_is_invalid(p));
+
+        points
+    }
 
-        Role::Cannon => {
+    /// `get_where_can_go`的脱离ECS版本：炮打隔山子、排除友方棋子、排除送将(含老将对脸)的招法
+    fn get_where_can_go(&self, pos: (i32, i32))-> Vec<(i32, i32)> {
+        let chess = self.pieces.get(&pos).unwrap().clone();
+        let mut reachable_points = self.get_reachable_points(pos);
+
+        if chess.role == Role::Cannon {
+            let get_chess_at = |position: (i32, i32)| self.get_chess_at(position);
+            let points = &mut reachable_points;
+            let p = &pos;
+            points.clear();
             macro_rules! cannon_search_path {
                 ($loop_name:ident; $update:expr) => {
                     let mut $loop_name = 0;
@@ -735,7 +1115,7 @@ fn get_reachable_points(target: Entity, world: &World, chess_query: &QueryState<
                         let current_point = $update;
                         if let Some(_) = get_chess_at(current_point) {
                             break;
-                        } else if is_invalid(&current_point) {
+                        } else if Self::pos_is_invalid(current_point) {
                             break;
                         } else {
                             points.push(current_point);
@@ -749,10 +1129,8 @@ fn get_reachable_points(target: Entity, world: &World, chess_query: &QueryState<
                                 points.push(current_point);
                             }
                             break;
-                        } else if is_invalid(&current_point) {
+                        } else if Self::pos_is_invalid(current_point) {
                             break;
-                        } else {
-                            points.push(current_point);
                         }
                     }
                 };
@@ -762,39 +1140,428 @@ fn get_reachable_points(target: Entity, world: &World, chess_query: &QueryState<
             cannon_search_path!(i; (p.0 - i, p.1));
             cannon_search_path!(i; (p.0, p.1 + i));
             cannon_search_path!(i; (p.0, p.1 - i));
-        },
+        }
 
-        Role::Pawn(flag) => {
-            if !flag {
-                points.push((p.0, p.1 + 1));
-                if p.1 > 4 {
-                    points.push((p.0 - 1, p.1));
-                    points.push((p.0 + 1, p.1));
+        reachable_points.retain(|&p| {
+            if let Some(chess_at_point) = self.get_chess_at(p) {
+                if chess_at_point.team == chess.team {
+                    return false;
                 }
-            } else {
-                points.push((p.0, p.1 - 1));
-                if p.1 < 5 {
-                    points.push((p.0 - 1, p.1));
-                    points.push((p.0 + 1, p.1));
+            }
+            true
+        });
+
+        // 排除送将的招法：落子后不能让自己的老将被攻击，也不能形成老将对脸
+        reachable_points.retain(|&p| !is_in_check(chess.team, &self.apply_move(pos, p)));
+
+        reachable_points
+    }
+
+    /// `team`的老将所在格
+    fn find_king(&self, team: Team)-> Option<(i32, i32)> {
+        self.pieces.iter()
+            .find(|(_, chess)| chess.team == team && chess.role == Role::King)
+            .map(|(&pos, _)| pos)
+    }
+
+    /// 初始摆放的新局面，红方先行；与`game_setup_system`共用`INITIAL_HALF_BOARD`
+    fn initial()-> BoardState {
+        let mut pieces = HashMap::new();
+        for &(role, black_pos) in INITIAL_HALF_BOARD {
+            pieces.insert(black_pos, Chess {
+                team: Team::Black,
+                role,
+                position: black_pos,
+                redraw_stage: 0,
+            });
+
+            let red_pos = (black_pos.0, 9 - black_pos.1);
+            let red_role = match role {
+                Role::Pawn(flag) => Role::Pawn(!flag),
+                Role::Bishop(flag) => Role::Bishop(!flag),
+                _ => role,
+            };
+            pieces.insert(red_pos, Chess {
+                team: Team::Red,
+                role: red_role,
+                position: red_pos,
+                redraw_stage: 0,
+            });
+        }
+        BoardState { pieces, to_move: Team::Red }
+    }
+
+    /// 落子后返回一份新的局面，顺带切换走子方
+    fn apply_move(&self, from: (i32, i32), to: (i32, i32))-> BoardState {
+        let mut next = self.clone();
+        if let Some(mut chess) = next.pieces.remove(&from) {
+            chess.position = to;
+            next.pieces.insert(to, chess);
+        }
+        next.to_move = next.to_move.opposite();
+        next
+    }
+
+    /// `team`一方当前所有合法招法
+    fn all_moves(&self, team: Team)-> Vec<((i32, i32), (i32, i32))> {
+        let mut moves = Vec::new();
+        for (&pos, chess) in self.pieces.iter() {
+            if chess.team == team {
+                for to in self.get_where_can_go(pos) {
+                    moves.push((pos, to));
                 }
             }
-        },
+        }
+        // 把吃子的招法排在前面，让alpha-beta剪枝更早生效
+        moves.sort_by_key(|&(_, to)| if self.get_chess_at(to).is_some() { 0 } else { 1 });
+        moves
+    }
+
+    /// Xiangqi子力价值，兵过河后价值翻倍
+    fn piece_value(role: Role, position: (i32, i32))-> f32 {
+        match role {
+            Role::King => 10000.,
+            Role::Chariot => 9.,
+            Role::Cannon => 4.5,
+            Role::Horse => 4.,
+            Role::Guard => 2.,
+            Role::Bishop(_) => 2.,
+            Role::Pawn(flag) => {
+                let crossed_river = if !flag { position.1 >= 5 } else { position.1 <= 4 };
+                if crossed_river { 2. } else { 1. }
+            },
+        }
+    }
+
+    /// 以`team`视角评估局面：子力差加少量机动性加成
+    fn evaluate(&self, team: Team)-> f32 {
+        let material: f32 = self.pieces.values()
+            .map(|chess| {
+                let value = Self::piece_value(chess.role, chess.position);
+                if chess.team == team { value } else { -value }
+            })
+            .sum();
+
+        let mobility = self.all_moves(team).len() as f32 - self.all_moves(team.opposite()).len() as f32;
+
+        material + mobility * 0.01
     }
 
-    points.retain(|&p| !is_invalid(&p));
+    /// 局面的Zobrist签名：逐格异或棋子键，再按走子方异或一次，用于判断重复局面
+    fn zobrist_hash(&self)-> u64 {
+        let table = zobrist_table();
+        let mut hash = self.pieces.iter()
+            .fold(0u64, |hash, (&pos, chess)| hash ^ table.piece_key(chess.team, chess.role, pos));
+        if self.to_move == Team::Black {
+            hash ^= table.side_to_move_key;
+        }
+        hash
+    }
 
-    points
+    /// 导出为FEN风格的局面字符串：10行从黑方底线(y=0)到红方底线(y=9)，`/`分隔，数字表示连续空格，末尾附走子方(`w`红`b`黑)
+    fn to_fen(&self)-> String {
+        let mut rows = Vec::with_capacity(10);
+        for y in 0..10 {
+            let mut row = String::new();
+            let mut empty_run = 0u32;
+            for x in 0..9 {
+                match self.get_chess_at((x, y)) {
+                    Some(chess) => {
+                        if empty_run > 0 {
+                            row.push_str(&empty_run.to_string());
+                            empty_run = 0;
+                        }
+                        let letter = role_to_fen_letter(chess.role);
+                        row.push(if chess.team == Team::Red { letter.to_ascii_uppercase() } else { letter });
+                    },
+                    None => empty_run += 1,
+                }
+            }
+            if empty_run > 0 {
+                row.push_str(&empty_run.to_string());
+            }
+            rows.push(row);
+        }
+
+        format!("{} {}", rows.join("/"), if self.to_move == Team::Red { 'w' } else { 'b' })
+    }
+
+    /// `to_fen`的逆过程
+    fn from_fen(s: &str)-> Option<BoardState> {
+        let (board_str, side_str) = s.trim().split_once(' ')?;
+        let to_move = match side_str.trim() {
+            "w" => Team::Red,
+            "b" => Team::Black,
+            _ => return None,
+        };
+
+        let rows: Vec<&str> = board_str.split('/').collect();
+        if rows.len() != 10 {
+            return None;
+        }
+
+        let mut pieces = HashMap::new();
+        for (y, row) in rows.into_iter().enumerate() {
+            let mut x = 0i32;
+            for c in row.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    x += skip as i32;
+                    continue;
+                }
+                if x > 8 {
+                    return None;
+                }
+                let team = if c.is_ascii_uppercase() { Team::Red } else { Team::Black };
+                let role = fen_letter_to_role(c, team)?;
+                let position = (x, y as i32);
+                pieces.insert(position, Chess { team, role, position, redraw_stage: 0 });
+                x += 1;
+            }
+        }
+
+        Some(BoardState { pieces, to_move })
+    }
+}
+
+/// `team`一方是否正被将军，包含老将对脸的情况
+/// 老将对脸时视为两个老将都被对方"将"住，走招后若仍对脸则该招非法
+fn is_in_check(team: Team, board: &BoardState)-> bool {
+    let Some(king_pos) = board.find_king(team) else { return false; };
+
+    if let Some(enemy_king_pos) = board.find_king(team.opposite()) {
+        if king_pos.0 == enemy_king_pos.0 {
+            let (y_min, y_max) = if king_pos.1 < enemy_king_pos.1 {
+                (king_pos.1, enemy_king_pos.1)
+            } else {
+                (enemy_king_pos.1, king_pos.1)
+            };
+            let blocked = board.pieces.values()
+                .any(|chess| chess.position.0 == king_pos.0 && y_min < chess.position.1 && chess.position.1 < y_max);
+            if !blocked {
+                return true;
+            }
+        }
+    }
+
+    board.pieces.iter().any(|(&pos, chess)| {
+        chess.team != team && board.get_reachable_points(pos).contains(&king_pos)
+    })
 }
 
+/// AI默认搜索深度，3~4层在不阻塞UI的前提下已经能下出像样的招法
+const AI_SEARCH_DEPTH: u8 = 4;
 
+/// 带alpha-beta剪枝的negamax搜索，返回以`board.to_move`视角的评分
+/// 不变量：`alpha < beta`；当某个招法的评分`>= beta`时直接剪枝
+fn negamax(board: &BoardState, depth: u8, mut alpha: f32, beta: f32)-> f32 {
+    if depth == 0 {
+        return board.evaluate(board.to_move);
+    }
 
+    let moves = board.all_moves(board.to_move);
+    if moves.is_empty() {
+        // 无棋可走：被将死或困毙，对走子方而言是极差的结果
+        return -100000.;
+    }
 
+    let mut best = f32::NEG_INFINITY;
+    for (from, to) in moves {
+        let next = board.apply_move(from, to);
+        let score = -negamax(&next, depth - 1, -beta, -alpha);
+        if score > best {
+            best = score;
+        }
+        if best > alpha {
+            alpha = best;
+        }
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
 
-fn game_setup_system(
+/// 为`board.to_move`一方在固定深度下搜索最佳招法
+fn find_best_move(board: &BoardState, depth: u8)-> Option<((i32, i32), (i32, i32))> {
+    let moves = board.all_moves(board.to_move);
+    let (mut alpha, beta) = (f32::NEG_INFINITY, f32::INFINITY);
+    let mut best_move = None;
+    let mut best_score = f32::NEG_INFINITY;
+
+    for (from, to) in moves {
+        let next = board.apply_move(from, to);
+        let score = -negamax(&next, depth.saturating_sub(1), -beta, -alpha);
+        if score > best_score {
+            best_score = score;
+            best_move = Some((from, to));
+        }
+        if best_score > alpha {
+            alpha = best_score;
+        }
+    }
+
+    best_move
+}
+
+/// 如果当前走子方交给了电脑，搜索并落子，复用与人类点击相同的历史记录/换边逻辑
+/// 这样AI的落子也会进入既有的动画流水线(`chessboard_system`根据`redraw_stage`播放移动动画)
+fn ai_move_system(
+    mut commands: Commands,
+    ai_opponent: Res<AiOpponent>,
+    mut history: ResMut<History>,
+    mut repetition_table: ResMut<RepetitionTable>,
+    mut current_team: ResMut<CurrentTeam>,
+    children_query: Query<&Children>,
+    chess_query: Query<(Entity, &Chess), With<Ingame>>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    let Some(ai_team) = ai_opponent.0 else { return; };
+    if current_team.0 != ai_team {
+        return;
+    }
+
+    let mut pieces = HashMap::new();
+    for (_, chess) in chess_query.iter() {
+        pieces.insert(chess.position, chess.clone());
+    }
+    let board = BoardState { pieces, to_move: ai_team };
+
+    let Some((from, to)) = find_best_move(&board, AI_SEARCH_DEPTH) else { return; };
+
+    let moving_entity = chess_query.iter().find(|(_, chess)| chess.position == from).map(|(e, _)| e);
+    let Some(moving_entity) = moving_entity else { return; };
+
+    let mut target_chess = None;
+    for (entity, chess) in chess_query.iter() {
+        if chess.position == to {
+            target_chess = Some(chess.clone());
+            commands.entity(entity).despawn();
+            children_query.iter_descendants(entity)
+                .for_each(|child| commands.entity(child).despawn());
+        }
+    }
+
+    let next_board = board.apply_move(from, to);
+    let delivered_check = is_in_check(ai_team.opposite(), &next_board);
+    let board_hash = next_board.zobrist_hash();
+    *repetition_table.entry(board_hash).or_insert(0) += 1;
+
+    sound_events.send(if target_chess.is_some() { SoundEvent::Capture } else { SoundEvent::Move });
+    if delivered_check {
+        sound_events.send(SoundEvent::Check);
+    }
+
+    history.push(HistoryRecord {
+        from_pos: from,
+        to_pos: to,
+        target_chess,
+        mover: ai_team,
+        delivered_check,
+        board_hash,
+    });
+
+    commands.add(move |world: &mut World| {
+        if let Some(mut chess) = world.get_mut::<Chess>(moving_entity) {
+            chess.position = to;
+            chess.redraw_stage = 1;
+        }
+    });
+
+    current_team.0 = current_team.0.opposite();
+}
+
+/// 按下P键切换暂停；只在对局进行中生效
+fn pause_toggle_system(
+    mut paused: ResMut<Paused>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::P) {
+        paused.0 = !paused.0;
+    }
+}
+
+/// 每帧检查走子方是否已无棋可走：被将军则对方获胜(绝杀)，否则困毙判和；
+/// 局面三次重复也在这里判定：长将一方判负，否则判和
+/// 从ECS镜像出`BoardState`复用`is_in_check`/`all_moves`，与落子逻辑保持一致
+fn game_over_system(
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut game_result: ResMut<GameResult>,
+    current_team: Res<CurrentTeam>,
+    history: Res<History>,
+    repetition_table: Res<RepetitionTable>,
+    chess_query: Query<&Chess, With<Ingame>>,
+) {
+    if *state.get() != AppState::Ingame {
+        return;
+    }
+
+    let mut pieces = HashMap::new();
+    for chess in chess_query.iter() {
+        pieces.insert(chess.position, chess.clone());
+    }
+    let board = BoardState { pieces, to_move: current_team.0 };
+
+    if board.all_moves(current_team.0).is_empty() {
+        let winner = if is_in_check(current_team.0, &board) {
+            Some(current_team.0.opposite())
+        } else {
+            None
+        };
+        game_result.0 = Some(winner);
+        next_state.set(AppState::GameOver(winner));
+        return;
+    }
+
+    if let Some(last_record) = history.last() {
+        if repetition_table.get(&last_record.board_hash).copied().unwrap_or(0) >= 3 {
+            let winner = perpetual_check_loser(&history[..], last_record.board_hash).map(|team| team.opposite());
+            game_result.0 = Some(winner);
+            next_state.set(AppState::GameOver(winner));
+        }
+    }
+}
+
+/// 进入`GameOver`状态后展示终局结果
+fn game_over_ui_system(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    asset_server: Res<AssetServer>,
+    window_size: Res<WindowSize>,
+    existing_text_query: Query<Entity, With<GameOverText>>,
+) {
+    let AppState::GameOver(winner) = *state.get() else { return; };
+    if !existing_text_query.is_empty() {
+        return;
+    }
+
+    let text = match winner {
+        Some(Team::Red)   => "红方胜",
+        Some(Team::Black) => "黑方胜",
+        None              => "和棋",
+    };
+
+    commands.spawn((
+        GameOverText,
+        Text2dBundle {
+            text: Text::from_section(text, TextStyle {
+                color: Color::rgb(0.85, 0.1, 0.1),
+                font_size: window_size.0 * 0.18,
+                font: asset_server.load("LXGWWenKai-subset.ttf"),
+            }).with_alignment(TextAlignment::Center),
+            transform: Transform::from_xyz(0., 0., 10.),
+            ..Default::default()
+        },
+    ));
+}
+
+
+
+
+/// 仅运行一次：窗口、摄像机等与对局局数无关的全局设置
+fn app_setup_system(
     mut commands: Commands,
     mut window_query: Query<&mut Window, With<PrimaryWindow>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut color_materials: ResMut<Assets<ColorMaterial>>,
 ) {
     let window_size = WindowSize(400., 700.);
 
@@ -812,7 +1579,20 @@ fn game_setup_system(
         window.title = "Chinese Chess Game".to_string();
     }
 
-    commands.insert_resource(window_size.clone());
+    commands.insert_resource(window_size);
+    commands.init_resource::<AiOpponent>();
+}
+
+/// 进入`AppState::Ingame`时铺设棋盘UI、棋子、重置对局资源
+/// `AiOpponent`由主菜单在切换到`Ingame`之前设置，这里不重置
+fn game_setup_system(
+    mut commands: Commands,
+    window_size: Res<WindowSize>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let window_size = *window_size;
 
     let padding = Val::Percent(6.);
     commands.spawn((
@@ -876,37 +1656,15 @@ fn game_setup_system(
         Ingame,
         Chessboard,
         MaterialMesh2dBundle {
-            mesh: Mesh2dHandle(meshes.add(create_chessboard_mesh(window_size.clone()))),
+            mesh: Mesh2dHandle(meshes.add(create_chessboard_mesh(window_size))),
             material: color_materials.add(ColorMaterial::from(Color::rgb(0., 0., 0.))),
             visibility: Visibility::Visible,
             ..Default::default()
         }
     ));
 
-    use once_cell::sync::OnceCell;
-    static CHESSES: OnceCell<Vec<(Role, (i32, i32))>> = OnceCell::new();
-    if CHESSES.get().is_none() {
-        CHESSES.set(vec![
-            (Role::Chariot,       (0, 0)),
-            (Role::Horse,         (1, 0)),
-            (Role::Bishop(false), (2, 0)),
-            (Role::Guard,         (3, 0)),
-            (Role::King,          (4, 0)),
-            (Role::Guard,         (5, 0)),
-            (Role::Bishop(false), (6, 0)),
-            (Role::Horse,         (7, 0)),
-            (Role::Chariot,       (8, 0)),
-            (Role::Cannon,        (1, 2)),
-            (Role::Cannon,        (7, 2)),
-            (Role::Pawn(false),   (0, 3)),
-            (Role::Pawn(false),   (2, 3)),
-            (Role::Pawn(false),   (4, 3)),
-            (Role::Pawn(false),   (6, 3)),
-            (Role::Pawn(false),   (8, 3)),
-        ]).unwrap();
-    }
     // 生成棋子
-    for info in CHESSES.wait().iter() {
+    for info in INITIAL_HALF_BOARD.iter() {
         commands.spawn((
             Ingame,
             Chess {
@@ -934,6 +1692,13 @@ fn game_setup_system(
 
     commands.init_resource::<CurrentTeam>();
     commands.init_resource::<History>();
+    commands.insert_resource(Paused::default());
+    commands.insert_resource(Playback::default());
+    commands.insert_resource(GameResult::default());
+
+    let mut repetition_table = RepetitionTable::default();
+    repetition_table.insert(BoardState::initial().zobrist_hash(), 1);
+    commands.insert_resource(repetition_table);
 
     let t = Transform::from_xyz(0., window_size.1 / 2. - window_size.0 * 1.250, 0.7);
     commands.spawn((
@@ -956,11 +1721,199 @@ fn game_setup_system(
             ..Default::default()
         },
     ));
+
+    commands.spawn((
+        Ingame,
+        UndoButton,
+        ButtonBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(10.),
+                    right: Val::Px(10.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(80.), Val::Px(36.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(CHESS_NORMAL_COLOR),
+            ..Default::default()
+        }
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("悔棋", TextStyle {
+            color: Color::rgb(0.1, 0.1, 0.1),
+            font_size: 20.,
+            font: asset_server.load("LXGWWenKai-subset.ttf"),
+        }));
+    });
+
+    for (index, (label, action)) in [
+        ("存档", SaveLoadAction::Save),
+        ("读档", SaveLoadAction::Load),
+    ].into_iter().enumerate() {
+        commands.spawn((
+            Ingame,
+            action,
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        bottom: Val::Px(10.),
+                        right: Val::Px(10. + 90. * (index as f32 + 1.)),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Px(80.), Val::Px(36.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor::from(CHESS_NORMAL_COLOR),
+                ..Default::default()
+            }
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, TextStyle {
+                color: Color::rgb(0.1, 0.1, 0.1),
+                font_size: 20.,
+                font: asset_server.load("LXGWWenKai-subset.ttf"),
+            }));
+        });
+    }
+
+    for (index, (label, action)) in [
+        ("上一步", PlaybackAction::Prev),
+        ("下一步", PlaybackAction::Next),
+    ].into_iter().enumerate() {
+        commands.spawn((
+            Ingame,
+            action,
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        bottom: Val::Px(54.),
+                        right: Val::Px(10. + 90. * index as f32),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Px(80.), Val::Px(36.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor::from(CHESS_NORMAL_COLOR),
+                ..Default::default()
+            }
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, TextStyle {
+                color: Color::rgb(0.1, 0.1, 0.1),
+                font_size: 20.,
+                font: asset_server.load("LXGWWenKai-subset.ttf"),
+            }));
+        });
+    }
+}
+
+/// 退出`AppState::Ingame`时清场，下次进入能从一个干净的棋盘重新铺设
+/// 只需对无父实体的`Ingame`根实体调用`despawn_recursive`，UI层级里的子实体会一并销毁
+fn despawn_ingame_system(
+    mut commands: Commands,
+    root_query: Query<Entity, (With<Ingame>, Without<Parent>)>,
+) {
+    for entity in root_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// 主菜单：新对局/人机对战/退出
+fn main_menu_setup_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load("LXGWWenKai-subset.ttf");
+
+    commands.spawn((
+        MainMenuUi,
+        NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                gap: Size::new(Val::Px(0.), Val::Px(20.)),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    )).with_children(|parent| {
+        for (label, action) in [
+            ("新对局",   MainMenuButton::NewGame),
+            ("人机对战", MainMenuButton::HumanVsAi),
+            ("退出",     MainMenuButton::Quit),
+        ] {
+            parent.spawn((
+                action,
+                ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(220.), Val::Px(60.)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor::from(CHESS_NORMAL_COLOR),
+                    ..Default::default()
+                }
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, TextStyle {
+                    color: Color::rgb(0.1, 0.1, 0.1),
+                    font_size: 28.,
+                    font: font.clone(),
+                }));
+            });
+        }
+    });
+}
+
+fn main_menu_despawn_system(
+    mut commands: Commands,
+    query: Query<Entity, With<MainMenuUi>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn main_menu_button_system(
+    mut interaction_query: Query<(&Interaction, &MainMenuButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut ai_opponent: ResMut<AiOpponent>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    for (interaction, button, mut background_color) in interaction_query.iter_mut() {
+        match interaction {
+            Interaction::Clicked => match button {
+                MainMenuButton::NewGame => {
+                    ai_opponent.0 = None;
+                    next_state.set(AppState::Ingame);
+                },
+                MainMenuButton::HumanVsAi => {
+                    ai_opponent.0 = Some(Team::Black);
+                    next_state.set(AppState::Ingame);
+                },
+                MainMenuButton::Quit => {
+                    app_exit_events.send(AppExit);
+                },
+            },
+            Interaction::Hovered => *background_color = BackgroundColor::from(CHESS_HOVERED_COLOR),
+            Interaction::None     => *background_color = BackgroundColor::from(CHESS_NORMAL_COLOR),
+        }
+    }
 }
 
 fn game_system(
     mut commands: Commands,
     mut history: ResMut<History>,
+    mut repetition_table: ResMut<RepetitionTable>,
     mut color_materials: ResMut<Assets<ColorMaterial>>,
     mut current_team: ResMut<CurrentTeam>,
     children_query: Query<&Children>,
@@ -974,6 +1927,7 @@ fn game_system(
         Query<(Entity, &Selected, &mut Chess, &mut Transform, &mut Handle<ColorMaterial>), With<Ingame>>,
         Query<(Entity, &mut Chess, &mut Handle<ColorMaterial>), With<Ingame>>,
     )>,
+    mut sound_events: EventWriter<SoundEvent>,
 ) {
     for (button, interaction) in button_set.p0().iter() {
         let new_material = if *interaction == Interaction::Hovered {
@@ -996,6 +1950,7 @@ fn game_system(
                             // 选中
                             commands.entity(i.0).insert(Selected);
                             i.1.redraw_stage = 1;
+                            sound_events.send(SoundEvent::Select);
 
                             commands.add(move |world: &mut World| {
                                 let chess_query = world.query_filtered::<(Entity, &Chess), With<Ingame>>();
@@ -1026,6 +1981,16 @@ fn game_system(
                 } else if preview_query.iter().find(|&p| p.0 == button.x && p.1 == button.y).is_some() {
                     current_team.0 = current_team.0.opposite();
 
+                    let selected = set.p0().single().2.clone();
+                    let pieces: HashMap<(i32, i32), Chess> = set.p1().iter()
+                        .map(|i| (i.1.position, i.1.clone()))
+                        .collect();
+                    let board = BoardState { pieces, to_move: selected.team };
+                    let next_board = board.apply_move(selected.position, (button.x, button.y));
+                    let delivered_check = is_in_check(selected.team.opposite(), &next_board);
+                    let board_hash = next_board.zobrist_hash();
+                    *repetition_table.entry(board_hash).or_insert(0) += 1;
+
                     let mut target_chess = None::<Chess>;
                     // 清除目标格子的棋子和字
                     set.p1().iter().for_each(|i| {
@@ -1036,6 +2001,11 @@ fn game_system(
                                 .for_each(|child| commands.entity(child).despawn());
                         }
                     });
+                    sound_events.send(if target_chess.is_some() { SoundEvent::Capture } else { SoundEvent::Move });
+                    if delivered_check {
+                        sound_events.send(SoundEvent::Check);
+                    }
+
                     let mut p0 = set.p0();
                     let query_result = p0.single_mut();
                     let mut selected_chess = query_result.2;
@@ -1044,6 +2014,9 @@ fn game_system(
                         from_pos: selected_chess.position,
                         to_pos: (button.x, button.y),
                         target_chess: target_chess,
+                        mover: selected.team,
+                        delivered_check,
+                        board_hash,
                     });
                     // 将当前棋子移动到目标位置，清除选中
                     selected_chess.position = (button.x, button.y);
@@ -1057,11 +2030,174 @@ fn game_system(
         }
     }
 
+    // 悔棋：弹出最后一条历史记录，把棋子挪回去，有吃子则原地复活
     for interaction in button_set.p1().iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let Some(record) = history.pop() else { continue; };
+
+        if let Some(count) = repetition_table.get_mut(&record.board_hash) {
+            *count -= 1;
+            if *count == 0 {
+                repetition_table.remove(&record.board_hash);
+            }
+        }
+
+        for mut i in set.p1().iter_mut() {
+            if i.1.position == record.to_pos {
+                commands.entity(i.0).remove::<Selected>();
+                i.1.position = record.from_pos;
+                i.1.redraw_stage = 1;
+            }
+        }
+
+        if let Some(target_chess) = record.target_chess {
+            commands.spawn((
+                Ingame,
+                Chess { redraw_stage: 0, ..target_chess },
+            ));
+        }
+
+        // 清除选中和预览点
+        set.p0().iter_mut().for_each(|mut i| {
+            if i.2.redraw_stage == 0 {
+                commands.entity(i.0).remove::<Selected>();
+                i.2.redraw_stage = 1;
+            }
+        });
+        preview_entity_query.iter().for_each(|e| commands.entity(e).despawn());
+
+        current_team.0 = current_team.0.opposite();
     }
 }
 
+/// 存档/读档按钮：存档把当前局面写成FEN字符串落盘，读档清空棋子按FEN重建，历史和重复计数一并重置
+fn save_load_system(
+    mut commands: Commands,
+    mut history: ResMut<History>,
+    mut repetition_table: ResMut<RepetitionTable>,
+    mut current_team: ResMut<CurrentTeam>,
+    children_query: Query<&Children>,
+    button_query: Query<(&Interaction, &SaveLoadAction), (Changed<Interaction>, With<Button>, With<Ingame>)>,
+    chess_query: Query<(Entity, &Chess), With<Ingame>>,
+    preview_entity_query: Query<Entity, With<PreviewPoint>>,
+) {
+    for (interaction, action) in button_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match action {
+            SaveLoadAction::Save => {
+                let mut pieces = HashMap::new();
+                for (_, chess) in chess_query.iter() {
+                    pieces.insert(chess.position, chess.clone());
+                }
+                let board = BoardState { pieces, to_move: current_team.0 };
+                let _ = std::fs::write(SAVE_FILE_PATH, board.to_fen());
+            },
+
+            SaveLoadAction::Load => {
+                let Ok(fen) = std::fs::read_to_string(SAVE_FILE_PATH) else { continue; };
+                let Some(board) = BoardState::from_fen(&fen) else { continue; };
+
+                for (entity, _) in chess_query.iter() {
+                    commands.entity(entity).despawn();
+                    children_query.iter_descendants(entity)
+                        .for_each(|child| commands.entity(child).despawn());
+                }
+                preview_entity_query.iter().for_each(|e| commands.entity(e).despawn());
 
+                let new_hash = board.zobrist_hash();
+                for chess in board.pieces.into_values() {
+                    commands.spawn((Ingame, Chess { redraw_stage: 0, ..chess }));
+                }
+
+                current_team.0 = board.to_move;
+                history.clear();
+                repetition_table.clear();
+                repetition_table.insert(new_hash, 1);
+            },
+        }
+    }
+}
+
+/// 上一步/下一步按钮：移动复盘游标，游标追上实时对局的最后一步时自动退出复盘
+fn playback_control_system(
+    mut playback: ResMut<Playback>,
+    history: Res<History>,
+    button_query: Query<(&Interaction, &PlaybackAction), (Changed<Interaction>, With<Button>, With<Ingame>)>,
+) {
+    for (interaction, action) in button_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match action {
+            PlaybackAction::Prev => {
+                let current = playback.0.unwrap_or(history.len());
+                playback.0 = Some(current.saturating_sub(1));
+            },
+
+            PlaybackAction::Next => {
+                let next = playback.0.map_or(history.len(), |n| n + 1);
+                playback.0 = if next >= history.len() { None } else { Some(next) };
+            },
+        }
+    }
+}
+
+/// 复盘游标变化时，从开局重放`History`到游标位置，把棋子实体重建成那一刻的局面
+/// 退出复盘(游标回到`None`)同理重放到最后一步，也就是重建回实时对局的局面
+///
+/// 靠`playback`是否真的被写过(而不是缓存上一次算出来的`steps`)来判断要不要重建，
+/// 不然退出复盘后`history.len()`还在涨，缓存的旧`steps`值迟早会跟后来重新进入复盘算出的`steps`撞上，
+/// 撞上就会错过本该有的重建
+fn playback_render_system(
+    mut commands: Commands,
+    playback: Res<Playback>,
+    history: Res<History>,
+    children_query: Query<&Children>,
+    chess_query: Query<(Entity, &Chess), With<Ingame>>,
+) {
+    if !playback.is_changed() {
+        return;
+    }
+
+    let steps = playback.0.unwrap_or(history.len());
+    let mut board = BoardState::initial();
+    for record in history.iter().take(steps) {
+        board = board.apply_move(record.from_pos, record.to_pos);
+    }
+
+    for (entity, _) in chess_query.iter() {
+        commands.entity(entity).despawn();
+        children_query.iter_descendants(entity)
+            .for_each(|child| commands.entity(child).despawn());
+    }
+    for chess in board.pieces.into_values() {
+        commands.spawn((Ingame, Chess { redraw_stage: 0, ..chess }));
+    }
+}
+
+/// 消费`game_system`发出的`SoundEvent`，按类型加载并播放对应音效文件
+fn audio_system(
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    mut sound_events: EventReader<SoundEvent>,
+) {
+    for event in sound_events.iter() {
+        let clip_path = match event {
+            SoundEvent::Select  => "select.ogg",
+            SoundEvent::Move    => "move.ogg",
+            SoundEvent::Capture => "capture.ogg",
+            SoundEvent::Check   => "check.ogg",
+        };
+        audio.play(asset_server.load(clip_path));
+    }
+}
 
 fn window_size_update_system(
     mut window_size: ResMut<WindowSize>,
@@ -1239,15 +2375,20 @@ fn chessboard_system(
 
 fn team_suggestion_system(
     current_team: Res<CurrentTeam>,
+    game_result: Res<GameResult>,
     window_size: Res<WindowSize>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut color_materials: ResMut<Assets<ColorMaterial>>,
     mut team_suggestion_query: Query<(&TeamSuggestion, &mut Transform, &mut Mesh2dHandle, &mut Handle<ColorMaterial>)>,
 ) {
     for (team_suggestion, mut transform, mut mesh, mut color) in team_suggestion_query.iter_mut() {
-        if current_team.is_changed() || window_size.is_changed() {
+        if current_team.is_changed() || window_size.is_changed() || game_result.is_changed() {
             let team = team_suggestion.0;
-            let activated = current_team.0 == team_suggestion.0;
+            // 对局结束后不再跟随`current_team`切换，改为永久点亮获胜方(和棋则都熄灭)
+            let activated = match game_result.0 {
+                Some(winner) => winner == Some(team_suggestion.0),
+                None => current_team.0 == team_suggestion.0,
+            };
 
             *mesh = Mesh2dHandle(meshes.add(create_team_suggestion_mesh(*window_size, team_suggestion.0, activated)));
             let mut t = &mut transform.translation;
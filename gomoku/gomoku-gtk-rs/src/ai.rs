@@ -0,0 +1,186 @@
+// 单机人机对战用的五子棋引擎：候选点限制在已有棋子半径2格以内，
+// 用已有的scan_line/score_line打分表做局面评估，外面套depth层minimax+alpha-beta
+
+use crate::{ChessboardGrid, Team};
+
+/// 人机难度，直接映射到minimax的搜索深度
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    fn depth(&self)-> u32 {
+        match self {
+            Difficulty::Easy => 2,
+            Difficulty::Medium => 4,
+            Difficulty::Hard => 6,
+        }
+    }
+}
+
+const WIN_SCORE: i64 = 10_000_000;
+
+/// 给AI选一步棋：在`difficulty`对应的深度里跑minimax，返回算出来最好的`(x, y)`
+pub fn choose_move(grid: &ChessboardGrid, me: Team, difficulty: Difficulty)-> (isize, isize) {
+    let depth = difficulty.depth();
+    let candidates = candidate_moves(grid);
+
+    let mut best_score = i64::MIN;
+    let mut best_pos = candidates.first().copied().unwrap_or((7, 7));
+
+    for (x, y) in candidates {
+        let mut board = grid.clone();
+        board.at_mut(x, y).unwrap().chess = Some(me);
+
+        let score = if board.check_win() == Some(me) {
+            WIN_SCORE
+        } else {
+            minimax(&board, depth.saturating_sub(1), i64::MIN, i64::MAX, false, me)
+        };
+
+        if score > best_score {
+            best_score = score;
+            best_pos = (x, y);
+        }
+    }
+
+    best_pos
+}
+
+fn minimax(grid: &ChessboardGrid, depth: u32, mut alpha: i64, mut beta: i64, maximizing: bool, me: Team)-> i64 {
+    if depth == 0 {
+        return evaluate(grid, me);
+    }
+
+    let candidates = candidate_moves(grid);
+    if candidates.is_empty() {
+        return evaluate(grid, me);
+    }
+
+    let mover = if maximizing { me } else { me.get_opposite() };
+    let candidates = order_candidates(grid, candidates, mover, me);
+
+    if maximizing {
+        let mut value = i64::MIN;
+        for (x, y) in candidates {
+            let mut board = grid.clone();
+            board.at_mut(x, y).unwrap().chess = Some(mover);
+
+            let child = if board.check_win() == Some(mover) {
+                WIN_SCORE - depth as i64
+            } else {
+                minimax(&board, depth - 1, alpha, beta, false, me)
+            };
+
+            value = value.max(child);
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+        value
+    } else {
+        let mut value = i64::MAX;
+        for (x, y) in candidates {
+            let mut board = grid.clone();
+            board.at_mut(x, y).unwrap().chess = Some(mover);
+
+            let child = if board.check_win() == Some(mover) {
+                -WIN_SCORE + depth as i64
+            } else {
+                minimax(&board, depth - 1, alpha, beta, true, me)
+            };
+
+            value = value.min(child);
+            beta = beta.min(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+        value
+    }
+}
+
+// 按下完这一步之后的单层局面分把候选点先排个序，好的走法排前面，alpha-beta才能早点剪枝；
+// 轮到`me`走就降序(先试好棋)，轮到对面走就升序(先试对`me`最不利的棋)
+fn order_candidates(grid: &ChessboardGrid, candidates: Vec<(isize, isize)>, mover: Team, me: Team)-> Vec<(isize, isize)> {
+    let mut scored: Vec<_> = candidates.into_iter()
+        .map(|(x, y)| {
+            let mut board = grid.clone();
+            board.at_mut(x, y).unwrap().chess = Some(mover);
+            (evaluate(&board, me), (x, y))
+        })
+        .collect();
+
+    if mover == me {
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
+    } else {
+        scored.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    scored.into_iter().map(|(_, pos)| pos).collect()
+}
+
+/// 给整个局面打分，`me`这边的棋型加分，对面的棋型减分
+fn evaluate(grid: &ChessboardGrid, me: Team)-> i64 {
+    const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+    let opponent = me.get_opposite();
+
+    let mut score = 0i64;
+    for y in 0..15 {
+        for x in 0..15 {
+            let Some(color) = grid.at(x, y).and_then(|cell| cell.chess) else {
+                continue;
+            };
+
+            let line_score: i64 = DIRECTIONS.iter()
+                .map(|&dir| crate::score_line(&crate::scan_line(grid, x, y, dir, color)))
+                .sum();
+
+            if color == me {
+                score += line_score;
+            } else {
+                debug_assert_eq!(color, opponent);
+                score -= line_score;
+            }
+        }
+    }
+
+    score
+}
+
+/// 候选落子点：空棋盘就下天元，否则只考虑已有棋子半径2格以内的空位，不然搜索树根本展不开
+fn candidate_moves(grid: &ChessboardGrid)-> Vec<(isize, isize)> {
+    let mut candidates = Vec::new();
+    let mut board_is_empty = true;
+
+    for y in 0..15 {
+        for x in 0..15 {
+            if grid.at(x, y).and_then(|cell| cell.chess).is_none() {
+                continue;
+            }
+
+            board_is_empty = false;
+
+            for dy in -2..=2 {
+                for dx in -2..=2 {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if let Some(cell) = grid.at(nx, ny) {
+                        if cell.chess.is_none() && !candidates.contains(&(nx, ny)) {
+                            candidates.push((nx, ny));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if board_is_empty {
+        return vec![(7, 7)];
+    }
+
+    candidates
+}
@@ -1,3 +1,5 @@
+mod ai;
+
 use gtk4 as gtk;
 use gtk::prelude::*;
 use gtk::{ Application, ApplicationWindow };
@@ -14,8 +16,32 @@ use std::time::{ Instant, SystemTime, Duration, UNIX_EPOCH };
 
 static PADDING_RATIO: f64 = 0.1;
 
+// 联机协议版本，EnterRoom里带着，房主发现跟自己的对不上就会拒绝加入
+static PROTOCOL_VERSION: u32 = 1;
+
+// 单个网络包的payload最大能有多大，对面声明的长度超过这个数就直接断开，防止被一个离谱的长度前缀把内存撑爆
+static MAX_FRAME: usize = 1024 * 1024;
+
+// 房间码用的字母表，把容易认错的0/O、1/l/I都去掉了，方便口头或者打字报码
+static ROOM_CODE_ALPHABET: &[u8] = b"23456789abcdefghijkmnpqrstuvwxyz";
+
+/// 生成一个6位房间码；中转配对服务器还没做，这个函数先准备着，目前UI上展示出来的码暂时还连不上别人
+fn generate_room_code()-> String {
+    let mut rng = rand::thread_rng();
+    (0..6)
+        .map(|_| ROOM_CODE_ALPHABET[rng.gen_range(0..ROOM_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
 static STATUS_BAR_INITIAL_TEXT: &'static str = "这里是状态栏\\(￣3￣)/";
 
+// 棋钟配置的默认值，创建房间的页面上可以调，这两个常量只是给输入框定个初始值
+static CLOCK_BASE_MS: u64 = 5 * 60 * 1000;
+static CLOCK_INCREMENT_MS: u64 = 5 * 1000;
+
+// 预设表情，按下对应的按钮就把下标发给对面
+static EMOTES: [&str; 4] = ["👍", "👎", "😂", "😡"];
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum DiscoverState {
     Pause,
@@ -37,6 +63,7 @@ enum ConnectStage {
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum Role {
+    /// 对应的数值为2
     Owner,
 
     Invalid,
@@ -53,6 +80,7 @@ impl Role {
         match *self {
             Self::Player => 0,
             Self::Visitor => 1,
+            Self::Owner => 2,
             _ => panic!(),
         }
     }
@@ -63,11 +91,21 @@ impl From<u8> for Role {
         match val {
             0 => Self::Player,
             1 => Self::Visitor,
+            2 => Self::Owner,
             _ => Self::Invalid,
         }
     }
 }
 
+/// `NetworkEvent::RoomList`里每一条的摘要信息，用来在`connection_list`里列一行
+#[derive(Clone, Debug)]
+struct RoomInfo {
+    id: u32,
+    owner_name: String,
+    player_count: u8,
+    prepared: bool,
+}
+
 /// 每个网络包都以一个u32开头，这个u32就是MessageType
 /// 接下来就是数据包的具体内容了
 #[derive(Debug)]
@@ -83,9 +121,13 @@ enum NetworkEvent {
     /// Role: u8
     /// NameLength: u8
     /// NameString: NameLength个字节长的字符串
+    /// ProtocolVersion: u32  跟房主的PROTOCOL_VERSION对不上就会被拒
+    /// RoomId: u32  目前还只支持单房间，房主这边收到了就忽略，填0就行
     EnterRoom {
         name: String,
         role: Role,
+        protocol_version: u32,
+        room_id: u32,
     },
 
     /// 准许以role描述的身份进入房间
@@ -130,7 +172,12 @@ enum NetworkEvent {
 
     /// 五子棋，启动！
     /// MessageType: 8
-    StartGame,
+    /// BaseMs: u64  每方的基础棋钟时间(毫秒)
+    /// IncrementMs: u64  每走一步加回的时间(毫秒)
+    StartGame {
+        base_ms: u64,
+        increment_ms: u64,
+    },
 
     /// 下棋
     /// MessageType: 9
@@ -163,10 +210,70 @@ enum NetworkEvent {
     /// 逃跑
     /// MessageType: 14
     Escape,
+
+    /// 观战者进入房间后，房主借此把棋盘的历史记录一次性同步给TA
+    /// MessageType: 15
+    /// MoveCount: u16
+    /// Moves: MoveCount个(u8 X坐标, u8 Y坐标)对
+    BoardSnapshot {
+        moves: Vec<(u8, u8)>,
+    },
+
+    /// 棋钟同步，房主权威算出双方剩余时间之后发给所有人，收到的人照着显示就行
+    /// MessageType: 16
+    /// RemainingMsOwner: u64
+    /// RemainingMsPlayer: u64
+    TimeSync {
+        remaining_ms_owner: u64,
+        remaining_ms_player: u64,
+    },
+
+    /// 某一方的棋钟走完了，这里宣布输家
+    /// MessageType: 17
+    /// Loser: u8
+    Timeout {
+        loser: Role,
+    },
+
+    /// 和棋请求
+    /// MessageType: 18
+    DrawRequest,
+
+    /// 表情，省得打字，就发个编号，对面自己查表显示成啥样
+    /// MessageType: 19
+    /// EmoteId: u8
+    Emote(u8),
+
+    /// 房主发现加入者的协议版本跟自己的对不上，拒绝放行，告诉它两边各自是什么版本
+    /// MessageType: 20
+    /// Server: u32
+    /// Client: u32
+    VersionMismatch {
+        server: u32,
+        client: u32,
+    },
+
+    /// 查询目前能加入的房间有哪些
+    /// MessageType: 21
+    ListRooms,
+
+    /// 对`ListRooms`的回复
+    /// MessageType: 22
+    /// RoomCount: u8
+    /// 每条房间信息: Id(u32) OwnerNameLength(u8) OwnerNameString PlayerCount(u8) Prepared(u8)
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+
+    /// 对`DrawRequest`的回应
+    /// MessageType: 23
+    /// Allowed: u8 -> bool    是否同意和棋
+    DrawReply(bool),
 }
 
 impl NetworkEvent {
-    pub fn from_buffer(buf: &[u8])-> Option<(Self, usize)> {
+    /// 解析不带长度前缀的payload本体，给`from_buffer`内部用
+    fn from_buffer_payload(buf: &[u8])-> Option<(Self, usize)> {
         let bytes_available = buf.len();
         let mut bytes_read = 0usize;
  
@@ -231,8 +338,10 @@ impl NetworkEvent {
             0 => {
                 let role = read_role!();
                 let name = read_string_u8len!();
+                let protocol_version = u32::from_be_bytes(read_to_slice!(4).try_into().unwrap());
+                let room_id = u32::from_be_bytes(read_to_slice!(4).try_into().unwrap());
 
-                NetworkEvent::EnterRoom { role, name }
+                NetworkEvent::EnterRoom { role, name, protocol_version, room_id }
             },
 
             1 => {
@@ -272,7 +381,10 @@ impl NetworkEvent {
             },
 
             8 => {
-                NetworkEvent::StartGame
+                let base_ms = u64::from_be_bytes(read_to_slice!(8).try_into().unwrap());
+                let increment_ms = u64::from_be_bytes(read_to_slice!(8).try_into().unwrap());
+
+                NetworkEvent::StartGame { base_ms, increment_ms }
             },
 
             9 => {
@@ -307,6 +419,76 @@ impl NetworkEvent {
                 NetworkEvent::Escape
             },
 
+            15 => {
+                let move_count = u16::from_be_bytes(read_to_slice!(2).try_into().unwrap()) as usize;
+                let mut moves = Vec::with_capacity(move_count);
+                for _ in 0..move_count {
+                    let x = read_u8!();
+                    let y = read_u8!();
+                    moves.push((x, y));
+                }
+
+                NetworkEvent::BoardSnapshot { moves }
+            },
+
+            16 => {
+                let remaining_ms_owner = u64::from_be_bytes(read_to_slice!(8).try_into().unwrap());
+                let remaining_ms_player = u64::from_be_bytes(read_to_slice!(8).try_into().unwrap());
+
+                NetworkEvent::TimeSync { remaining_ms_owner, remaining_ms_player }
+            },
+
+            17 => {
+                let loser = read_role!();
+
+                NetworkEvent::Timeout { loser }
+            },
+
+            18 => {
+                NetworkEvent::DrawRequest
+            },
+
+            19 => {
+                let emote_id = read_u8!();
+
+                NetworkEvent::Emote(emote_id)
+            },
+
+            20 => {
+                let server = u32::from_be_bytes(read_to_slice!(4).try_into().unwrap());
+                let client = u32::from_be_bytes(read_to_slice!(4).try_into().unwrap());
+
+                NetworkEvent::VersionMismatch { server, client }
+            },
+
+            21 => {
+                NetworkEvent::ListRooms
+            },
+
+            22 => {
+                let room_count = read_u8!() as usize;
+                let mut rooms = Vec::with_capacity(room_count);
+                for _ in 0..room_count {
+                    let id = u32::from_be_bytes(read_to_slice!(4).try_into().unwrap());
+                    let owner_name = read_string_u8len!();
+                    let player_count = read_u8!();
+                    let prepared = read_u8!() != 0;
+                    rooms.push(RoomInfo { id, owner_name, player_count, prepared });
+                }
+
+                NetworkEvent::RoomList { rooms }
+            },
+
+            23 => {
+                let v = read_u8!();
+                let v = match v {
+                    0 => false,
+                    _ => true,
+                };
+
+                NetworkEvent::DrawReply(v)
+            },
+
             114514 => {
                 NetworkEvent::Error(read_string_u8len!())
             },
@@ -319,7 +501,8 @@ impl NetworkEvent {
         Some((event, bytes_read))
     }
 
-    pub fn to_u8_vec(&self)-> Vec<u8> {
+    /// 组装不带长度前缀的payload本体，给`to_u8_vec`内部用
+    fn to_u8_vec_payload(&self)-> Vec<u8> {
         let mut buf = Vec::new();
 
         macro_rules! push_int {
@@ -352,10 +535,12 @@ impl NetworkEvent {
                 push_string_u8len!(msg);
             },
 
-            Self::EnterRoom { ref role, ref name } => {
+            Self::EnterRoom { ref role, ref name, ref protocol_version, ref room_id } => {
                 push_int!(0u32);
                 buf.push(role.to_u8().to_be());
                 push_string_u8len!(name);
+                push_int!(*protocol_version);
+                push_int!(*room_id);
             },
 
             Self::EnterPermitted { ref role, ref name } => {
@@ -394,8 +579,10 @@ impl NetworkEvent {
                 }
             },
 
-            &Self::StartGame => {
+            Self::StartGame { ref base_ms, ref increment_ms } => {
                 push_int!(8u32);
+                push_int!(*base_ms);
+                push_int!(*increment_ms);
             },
 
             Self::PutChess { ref x, ref y } => {
@@ -428,10 +615,95 @@ impl NetworkEvent {
             &Self::Escape => {
                 push_int!(14u32);
             },
+
+            Self::BoardSnapshot { ref moves } => {
+                push_int!(15u32);
+                push_int!(moves.len() as u16);
+                for &(x, y) in moves {
+                    push_int!(x);
+                    push_int!(y);
+                }
+            },
+
+            Self::TimeSync { ref remaining_ms_owner, ref remaining_ms_player } => {
+                push_int!(16u32);
+                push_int!(*remaining_ms_owner);
+                push_int!(*remaining_ms_player);
+            },
+
+            Self::Timeout { ref loser } => {
+                push_int!(17u32);
+                buf.push(loser.to_u8().to_be());
+            },
+
+            &Self::DrawRequest => {
+                push_int!(18u32);
+            },
+
+            Self::Emote(ref id) => {
+                push_int!(19u32);
+                buf.push(id.to_be());
+            },
+
+            Self::VersionMismatch { ref server, ref client } => {
+                push_int!(20u32);
+                push_int!(*server);
+                push_int!(*client);
+            },
+
+            &Self::ListRooms => {
+                push_int!(21u32);
+            },
+
+            Self::RoomList { ref rooms } => {
+                push_int!(22u32);
+                push_int!(rooms.len() as u8);
+                for room in rooms {
+                    push_int!(room.id);
+                    push_string_u8len!(room.owner_name);
+                    push_int!(room.player_count);
+                    buf.push(room.prepared as u8);
+                }
+            },
+
+            Self::DrawReply(ref v) => {
+                push_int!(23u32);
+                match *v {
+                    true => buf.push(1u8.to_be()),
+                    false => buf.push(0u8.to_be()),
+                }
+            },
         }
 
         buf
     }
+
+    /// 带长度前缀的编码：前面4字节是后面payload的字节数(大端u32)，
+    /// 这样读的一方不用自己去猜这条消息有没有收全，也不用再拿固定大小的缓冲区硬接
+    pub fn to_u8_vec(&self)-> Vec<u8> {
+        let payload = self.to_u8_vec_payload();
+        let mut buf = Vec::with_capacity(4 + payload.len());
+        buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&payload);
+        buf
+    }
+
+    /// 配合`to_u8_vec`用的解码：`buf`里长度前缀声明的字节数不够就返回`None`，等下一轮TCP读到更多数据再试，
+    /// 不会因为包被截断就越界panic
+    pub fn from_buffer(buf: &[u8])-> Option<(Self, usize)> {
+        if buf.len() < 4 {
+            return None;
+        }
+
+        let payload_len = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize;
+        let total_len = 4 + payload_len;
+        if buf.len() < total_len {
+            return None;
+        }
+
+        let (event, _) = Self::from_buffer_payload(&buf[4..total_len])?;
+        Some((event, total_len))
+    }
 }
 
 fn main()-> glib::ExitCode {
@@ -479,6 +751,12 @@ fn build_ui(app: &Application) {
     let last_pong = Arc::new(Mutex::new(Instant::now()));
     let last_ping = Arc::new(Mutex::new(Instant::now()));
 
+    // 房主这边当前挂着多少个观战连接，房主那个工具栏拿来显示给自己看
+    let spectator_count = Arc::new(Mutex::new(0usize));
+
+    // 聊天里/name命令改的昵称，None就还是用name_input里填的那个
+    let chat_nickname = Arc::new(RefCell::new(None::<String>));
+
     // 用于触发事件处理函数
     let (event_sender, event_receiver) = MainContext::channel(Priority::default());
 
@@ -486,6 +764,9 @@ fn build_ui(app: &Application) {
     let (cl_sender, cl_receiver) = std::sync::mpsc::channel();
     let cl_receiver = Arc::new(Mutex::new(cl_receiver));
 
+    // AI算出的落子结果从这个channel送回主线程，避免AI思考卡住界面
+    let (ai_sender, ai_receiver) = MainContext::channel(Priority::default());
+
 
 
 
@@ -608,6 +889,7 @@ fn build_ui(app: &Application) {
     @strong grid,
     @strong state,
     @strong cl_sender,
+    @strong ai_sender,
     @weak chessboard_area,
     @weak win,
     @weak status_bar,
@@ -627,6 +909,7 @@ fn build_ui(app: &Application) {
             grid_ref.at_mut(x, y).unwrap().chess = Some(state_ref.current_team);
             state_ref.current_team.set_opposite();
             state_ref.history.push((x, y));
+            state_ref.move_times.push(SystemTime::now());
 
             update_status_bar(state_ref.current_team);
 
@@ -650,25 +933,77 @@ fn build_ui(app: &Application) {
                     .build();
                 msgbox.present();
             }
-        } else if state_ref.mode.is_multiple_player() {
-            if let Mode::MultiplePlayer { my_team, .. } = &state_ref.mode {
-                if *my_team == state_ref.current_team {
-                    cl_sender.send(NetworkEvent::PutChess {x: x as u8, y: y as u8}).unwrap();
+        } else if let Mode::SinglePlayerVsAi { my_team, difficulty } = &state_ref.mode {
+            let my_team = *my_team;
+            let difficulty = *difficulty;
+            if my_team != state_ref.current_team {
+                return;
+            }
 
-                    grid_ref.at_mut(x, y).unwrap().chess = Some(*my_team);
+            grid_ref.at_mut(x, y).unwrap().chess = Some(my_team);
+            state_ref.current_team.set_opposite();
+            state_ref.history.push((x, y));
+            state_ref.move_times.push(SystemTime::now());
 
-                    state_ref.history.push((x, y));
+            update_status_bar(state_ref.current_team);
 
-                    state_ref.current_team.set_opposite();
+            chessboard_area.queue_draw();
 
-                    update_status_bar(state_ref.current_team);
+            if let Some(team_win) = grid_ref.check_win() {
+                state_ref.frozen = true;
 
-                    chessboard_area.queue_draw();
-                }
+                let team_str = team_win.as_str();
+                let adj = get_a_good_adj();
+
+                status_bar.set_label(&format!("{team_str} {adj}"));
+
+                let msgbox = gtk::MessageDialog::builder()
+                    .text(format!("{team_str} 赢了"))
+                    .buttons(gtk::ButtonsType::Ok)
+                    .message_type(gtk::MessageType::Info)
+                    .transient_for(&win)
+                    .modal(true)
+                    .build();
+                msgbox.present();
             } else {
-                unreachable!();
+                // 冻结棋盘防止AI还没下完人类就抢跑，落子脱离GTK主线程以免界面卡死
+                state_ref.frozen = true;
+
+                let ai_team = state_ref.current_team;
+                let grid_snapshot = grid_ref.clone();
+                let ai_sender = ai_sender.clone();
+                thread::spawn(move || {
+                    let pos = ai::choose_move(&grid_snapshot, ai_team, difficulty);
+                    let _ = ai_sender.send(pos);
+                });
+            }
+        } else if let Mode::MultiplePlayer { my_team, .. } = &state_ref.mode {
+            let my_team = *my_team;
+            if my_team == state_ref.current_team {
+                cl_sender.send(NetworkEvent::PutChess {x: x as u8, y: y as u8}).unwrap();
+
+                grid_ref.at_mut(x, y).unwrap().chess = Some(my_team);
+
+                state_ref.history.push((x, y));
+                state_ref.move_times.push(SystemTime::now());
+
+                state_ref.current_team.set_opposite();
+
+                update_status_bar(state_ref.current_team);
+
+                chessboard_area.queue_draw();
+
+                // 房主权威地给自己刚走的这步加回时间，再把最新的棋钟广播出去
+                if my_team == Team::Black {
+                    state_ref.remaining_ms_owner += state_ref.increment_ms;
+                    cl_sender.send(NetworkEvent::TimeSync {
+                        remaining_ms_owner: state_ref.remaining_ms_owner,
+                        remaining_ms_player: state_ref.remaining_ms_player,
+                    }).unwrap();
+                }
             }
         }
+        // Mode::Spectating: 观战者只看不下，这里什么都不做
     }
     );
 
@@ -704,6 +1039,111 @@ fn build_ui(app: &Application) {
         .valign(gtk::Align::Center)
         .build();
 
+    // 联机对局的棋钟，单机/人机模式下用不着，就让它一直是空的
+    let clock_label = gtk::Label::builder()
+        .label("")
+        .hexpand(true)
+        .halign(gtk::Align::Center)
+        .valign(gtk::Align::Center)
+        .build();
+
+    // 收到Emote之后在棋盘上飘一下用的浮层标签，平时藏着
+    let emote_popup = gtk::Label::builder()
+        .label("")
+        .halign(gtk::Align::Center)
+        .valign(gtk::Align::Center)
+        .visible(false)
+        .build();
+
+    // 聊天记录，只读，套个滚动条防止越长越高
+    let chat_view = gtk::TextView::builder()
+        .editable(false)
+        .cursor_visible(false)
+        .wrap_mode(gtk::WrapMode::WordChar)
+        .build();
+
+    let chat_scroller = gtk::ScrolledWindow::builder()
+        .hexpand(true)
+        .min_content_height(120)
+        .child(&chat_view)
+        .build();
+
+    let chat_entry = gtk::Entry::builder()
+        .hexpand(true)
+        .placeholder_text("聊天内容，/help看命令")
+        .build();
+
+    // 表情按钮，点一下就往对面脸上糊一个
+    let emote_bar = gtk::Box::builder()
+        .hexpand(true)
+        .orientation(gtk::Orientation::Horizontal)
+        .build();
+    // 按钮之间共享一个冷却时间戳，两次发表情至少间隔EMOTE_COOLDOWN，点得再急也不会连发
+    let last_emote_sent: Rc<RefCell<Option<Instant>>> = Rc::new(RefCell::new(None));
+    const EMOTE_COOLDOWN: Duration = Duration::from_millis(500);
+    for (i, emote) in EMOTES.iter().enumerate() {
+        let button = gtk::Button::with_label(emote);
+        button.connect_clicked(clone!(
+        @strong cl_sender,
+        @strong last_emote_sent,
+        => move |_| {
+            let mut last_sent = last_emote_sent.borrow_mut();
+            if last_sent.is_some_and(|t| t.elapsed() < EMOTE_COOLDOWN) {
+                return;
+            }
+            *last_sent = Some(Instant::now());
+            drop(last_sent);
+
+            cl_sender.send(NetworkEvent::Emote(i as u8)).unwrap();
+        }
+        ));
+        emote_bar.append(&button);
+    }
+
+    let chat_entry_bar = gtk::Box::builder()
+        .hexpand(true)
+        .orientation(gtk::Orientation::Horizontal)
+        .build();
+    chat_entry_bar.append(&chat_entry);
+    chat_entry_bar.append(&emote_bar);
+
+    let chat_box = gtk::Box::builder()
+        .hexpand(true)
+        .orientation(gtk::Orientation::Vertical)
+        .build();
+    chat_box.append(&chat_scroller);
+    chat_box.append(&chat_entry_bar);
+
+    // 保存当前对局的棋谱，单机/联机工具栏各摆一个按钮，点的都是这同一个闭包
+    let do_save_record = clone!(
+    @weak win,
+    @strong state,
+    => move |_: &gtk::Button| {
+        let content = export_record(&*state.lock().unwrap());
+
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("保存棋谱")
+            .transient_for(&win)
+            .modal(true)
+            .action(gtk::FileChooserAction::Save)
+            .build();
+        dialog.add_button("保存", gtk::ResponseType::Accept);
+        dialog.add_button("取消", gtk::ResponseType::Cancel);
+        dialog.set_current_name("game.gmkrec");
+
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    let _ = std::fs::write(path, &content);
+                }
+            }
+            dialog.destroy();
+        });
+
+        dialog.present();
+    }
+    );
+
     // 下方工具栏(单机模式)
     let tool_bar_single_player = gtk::Box::builder()
         .hexpand(true)
@@ -723,6 +1163,7 @@ fn build_ui(app: &Application) {
         }
 
         if let Some(pos) = state_ref.history.pop() {
+            state_ref.move_times.pop();
             state_ref.current_team.set_opposite();
             grid_ref.at_mut(pos.0, pos.1).unwrap().chess = None;
 
@@ -749,6 +1190,7 @@ fn build_ui(app: &Application) {
         grid_ref.clear();
 
         state_ref.history.clear();
+        state_ref.move_times.clear();
         state_ref.current_team = Team::Black;
         state_ref.frozen = false;
 
@@ -764,8 +1206,13 @@ fn build_ui(app: &Application) {
     }
     ));
 
+    // 保存棋谱按钮(单机模式)
+    let button_save_record = gtk::Button::with_label("保存棋谱");
+    button_save_record.connect_clicked(do_save_record.clone());
+
     tool_bar_single_player.append(&button_undo);
     tool_bar_single_player.append(&button_reset);
+    tool_bar_single_player.append(&button_save_record);
     tool_bar_single_player.append(&button_exit);
 
 
@@ -779,18 +1226,46 @@ fn build_ui(app: &Application) {
     let update_status_bar = upsb.clone();
     let button_undo = gtk::Button::with_label("悔棋");
     button_undo.connect_clicked(clone!(
-    @strong grid, @strong state, @weak chessboard_area => move |_| {
+    @strong state, @strong cl_sender, @weak status_bar => move |_| {
+        let state_ref = state.lock().unwrap();
+        if state_ref.frozen || state_ref.history.is_empty() {
+            return;
+        }
+        drop(state_ref);
+
+        cl_sender.send(NetworkEvent::UndoRequest).unwrap();
+        status_bar.set_label("已发送悔棋请求，等待对方回应");
     }
     ));
 
     // 请求和棋按钮(联机模式)
     let button_surrender = gtk::Button::with_label("请求和棋");
-    button_reset.connect_clicked(clone!(
-    @weak status_bar,
-    @strong grid,
-    @strong state,
-    @weak chessboard_area,
-    => move |_| {
+    button_surrender.connect_clicked(clone!(
+    @strong state, @strong cl_sender, @weak status_bar => move |_| {
+        let state_ref = state.lock().unwrap();
+        if state_ref.frozen {
+            return;
+        }
+        drop(state_ref);
+
+        cl_sender.send(NetworkEvent::DrawRequest).unwrap();
+        status_bar.set_label("已发送和棋请求，等待对方回应");
+    }
+    ));
+
+    // 认输按钮(联机模式)
+    let button_resign = gtk::Button::with_label("认输");
+    button_resign.connect_clicked(clone!(
+    @strong state, @strong cl_sender, @weak status_bar => move |_| {
+        let mut state_ref = state.lock().unwrap();
+        if state_ref.frozen {
+            return;
+        }
+        state_ref.frozen = true;
+        drop(state_ref);
+
+        cl_sender.send(NetworkEvent::Escape).unwrap();
+        status_bar.set_label("已经认输了");
     }
     ));
 
@@ -811,10 +1286,108 @@ fn build_ui(app: &Application) {
     }
     ));
 
+    // 保存棋谱按钮(联机模式)
+    let button_save_record = gtk::Button::with_label("保存棋谱");
+    button_save_record.connect_clicked(do_save_record.clone());
+
+    // 观战人数(只有房主这边会有观战连接，玩家那边看到的永远是0)
+    let spectator_count_label = gtk::Label::new(Some("观战: 0"));
+    glib::timeout_add_local(Duration::from_millis(500), clone!(
+    @strong spectator_count,
+    @weak spectator_count_label,
+    => @default-return glib::Continue(true),
+    move || {
+        let count = *spectator_count.lock().unwrap();
+        spectator_count_label.set_label(&format!("观战: {count}"));
+        glib::Continue(true)
+    }
+    ));
+
     tool_bar_multiple_player.append(&button_undo);
     tool_bar_multiple_player.append(&button_surrender);
+    tool_bar_multiple_player.append(&button_resign);
+    tool_bar_multiple_player.append(&button_save_record);
+    tool_bar_multiple_player.append(&spectator_count_label);
     tool_bar_multiple_player.append(&button_exit);
 
+    // 下方工具栏(棋谱回放模式)
+    let tool_bar_replay = gtk::Box::builder()
+        .hexpand(true)
+        .orientation(gtk::Orientation::Horizontal)
+        .visible(false)
+        .build();
+
+    let replay_update_status = clone!(
+    @weak status_bar => move |cursor: usize, total: usize| {
+        status_bar.set_label(&format!("棋谱回放: 第{cursor}/{total}手"));
+    }
+    );
+
+    let button_replay_start = gtk::Button::with_label("回到开局");
+    button_replay_start.connect_clicked(clone!(
+    @strong grid, @strong state, @weak chessboard_area,
+    @strong replay_update_status,
+    => move |_| {
+        let mut state_ref = state.lock().unwrap();
+        if let Mode::Replaying { ref moves, ref mut cursor } = state_ref.mode {
+            *cursor = 0;
+            let team = apply_moves_up_to(&mut grid.borrow_mut(), moves, *cursor);
+            replay_update_status(*cursor, moves.len());
+            drop(state_ref);
+            state.lock().unwrap().current_team = team;
+        }
+        chessboard_area.queue_draw();
+    }
+    ));
+
+    let button_replay_prev = gtk::Button::with_label("上一步");
+    button_replay_prev.connect_clicked(clone!(
+    @strong grid, @strong state, @weak chessboard_area,
+    @strong replay_update_status,
+    => move |_| {
+        let mut state_ref = state.lock().unwrap();
+        if let Mode::Replaying { ref moves, ref mut cursor } = state_ref.mode {
+            *cursor = cursor.saturating_sub(1);
+            let team = apply_moves_up_to(&mut grid.borrow_mut(), moves, *cursor);
+            replay_update_status(*cursor, moves.len());
+            drop(state_ref);
+            state.lock().unwrap().current_team = team;
+        }
+        chessboard_area.queue_draw();
+    }
+    ));
+
+    let button_replay_next = gtk::Button::with_label("下一步");
+    button_replay_next.connect_clicked(clone!(
+    @strong grid, @strong state, @weak chessboard_area,
+    @strong replay_update_status,
+    => move |_| {
+        let mut state_ref = state.lock().unwrap();
+        if let Mode::Replaying { ref moves, ref mut cursor } = state_ref.mode {
+            if *cursor < moves.len() {
+                *cursor += 1;
+            }
+            let team = apply_moves_up_to(&mut grid.borrow_mut(), moves, *cursor);
+            replay_update_status(*cursor, moves.len());
+            drop(state_ref);
+            state.lock().unwrap().current_team = team;
+        }
+        chessboard_area.queue_draw();
+    }
+    ));
+
+    let button_replay_exit = gtk::Button::with_label("返回主界面");
+    button_replay_exit.connect_clicked(clone!(
+    @weak stack => move |_| {
+        stack.set_visible_child_name("title");
+    }
+    ));
+
+    tool_bar_replay.append(&button_replay_start);
+    tool_bar_replay.append(&button_replay_prev);
+    tool_bar_replay.append(&button_replay_next);
+    tool_bar_replay.append(&button_replay_exit);
+
     chessboard_area.add_controller(click_reactor);
 
     let undo_bar = gtk::Box::builder()
@@ -828,11 +1401,15 @@ fn build_ui(app: &Application) {
     let switch_tool_bar = clone!(
     @weak tool_bar_single_player,
     @weak tool_bar_multiple_player,
+    @weak tool_bar_replay,
     @weak team_suggestion,
+    @weak chat_box,
     @weam undo_bar,
     @strong state,
     => move |is_single_player| {
         undo_bar.set_visible(false);
+        tool_bar_replay.set_visible(false);
+        chat_box.set_visible(!is_single_player);
         if is_single_player {
             tool_bar_single_player.set_visible(true);
             tool_bar_multiple_player.set_visible(false);
@@ -865,14 +1442,23 @@ fn build_ui(app: &Application) {
         .orientation(gtk::Orientation::Vertical)
         .build();
 
-    box_up.append(&chessboard_area);
+    // 棋盘套个Overlay，好在上面飘Emote
+    let chessboard_overlay = gtk::Overlay::builder()
+        .child(&chessboard_area)
+        .build();
+    chessboard_overlay.add_overlay(&emote_popup);
+
+    box_up.append(&chessboard_overlay);
     box_up.append(&tool_bar_single_player);
     box_up.append(&tool_bar_multiple_player);
+    box_up.append(&tool_bar_replay);
     box_up.append(&undo_bar);
 
     game_page.append(&box_up);
     game_page.append(&status_bar);
     game_page.append(&team_suggestion);
+    game_page.append(&clock_label);
+    game_page.append(&chat_box);
 
 
 
@@ -902,9 +1488,12 @@ fn build_ui(app: &Application) {
 
     let room_owner_label = gtk::Label::new(Some(""));
     let room_player_label = gtk::Label::new(Some(""));
+    // 房间码，只在创建房间的时候生成出来显示一下；配对服务器还没做，所以这个码暂时只能看不能用
+    let room_code_label = gtk::Label::new(Some(""));
 
     box_up.append(&room_owner_label);
     box_up.append(&room_player_label);
+    box_up.append(&room_code_label);
 
     let tools_wait = gtk::CenterBox::builder()
         .orientation(gtk::Orientation::Horizontal)
@@ -959,11 +1548,17 @@ fn build_ui(app: &Application) {
     @strong cl_sender,
     @strong state,
     @strong grid,
+    @weak name_input,
+    @weak clock_base_minutes_input,
+    @weak clock_increment_seconds_input,
     => move |button_prepare| {
         let mut connect_stage_ref = connect_stage.lock().unwrap();
         if let ConnectStage::Waiting { ref mut prepared, role, .. } = (*connect_stage_ref).clone() {
             if role == Role::Owner && *prepared {
-                cl_sender.send(NetworkEvent::StartGame).unwrap();
+                let base_ms = clock_base_minutes_input.value() as u64 * 60_000;
+                let increment_ms = clock_increment_seconds_input.value() as u64 * 1_000;
+
+                cl_sender.send(NetworkEvent::StartGame { base_ms, increment_ms }).unwrap();
 
                 // 开始游戏
                 room_owner_label.set_label("");
@@ -971,10 +1566,15 @@ fn build_ui(app: &Application) {
 
                 let mut state_ref = state.lock().unwrap();
                 state_ref.history.clear();
+                state_ref.move_times.clear();
                 state_ref.frozen = false;
                 state_ref.current_team = Team::Black;
+                state_ref.remaining_ms_owner = base_ms;
+                state_ref.remaining_ms_player = base_ms;
+                state_ref.increment_ms = increment_ms;
                 state_ref.mode = Mode::MultiplePlayer {
                     my_team: Team::Black,
+                    own_name: name_input.buffer().text().as_str().to_string(),
                     peer_name: if let ConnectStage::Waiting { ref opponent_name, .. } = *connect_stage_ref {
                         opponent_name.as_ref().unwrap().clone()
                     } else {
@@ -1012,8 +1612,28 @@ fn build_ui(app: &Application) {
     tools_wait.set_start_widget(Some(&button_exit));
     tools_wait.set_end_widget(Some(&button_prepare));
 
+    // 等待房间里也能聊两句，没有/help那些命令，就单纯发消息
+    let room_chat_view = gtk::TextView::builder()
+        .editable(false)
+        .cursor_visible(false)
+        .wrap_mode(gtk::WrapMode::WordChar)
+        .build();
+
+    let room_chat_scroller = gtk::ScrolledWindow::builder()
+        .hexpand(true)
+        .min_content_height(120)
+        .child(&room_chat_view)
+        .build();
+
+    let room_chat_entry = gtk::Entry::builder()
+        .hexpand(true)
+        .placeholder_text("聊天内容")
+        .build();
+
     room_page.append(&box_up);
     room_page.append(&tools_wait);
+    room_page.append(&room_chat_scroller);
+    room_page.append(&room_chat_entry);
 
 
 
@@ -1041,6 +1661,11 @@ fn build_ui(app: &Application) {
         .show_separators(true)
         .build();
 
+    // 上一次刷新房间列表拿到的数据，下标跟connection_list里的行一一对应
+    let room_list_store: Rc<RefCell<Vec<RoomInfo>>> = Rc::new(RefCell::new(Vec::new()));
+    // 那次刷新问的是哪个地址，选中某一行按"连接"的时候就连回这个地址
+    let room_list_address: Rc<RefCell<Option<SocketAddr>>> = Rc::new(RefCell::new(None));
+
     // 地址输入框
     let address_input = gtk::Text::builder()
         .hexpand(true)
@@ -1058,8 +1683,97 @@ fn build_ui(app: &Application) {
         .placeholder_text("君の名は")
         .build();
 
-    /*let conn_status_bar = gtk::Label::builder()
-        .label(STATUS_BAR_INITIAL_TEXT)
+    // 勾上就只围观不参战
+    let spectate_checkbox = gtk::CheckButton::with_label("以观战身份加入");
+
+    // 房间码输入框，留给以后接中转配对服务器用；现在还没有服务器可以问，填了也连不上
+    let room_code_input = gtk::Text::builder()
+        .hexpand(true)
+        .editable(true)
+        .sensitive(false)
+        .placeholder_text("房间码(配对服务器还没做，暂不可用)")
+        .build();
+
+    // 棋钟设置，只有创建房间的人能调，加入房间的一方用房主通过StartGame发来的数值
+    let clock_base_minutes_input = gtk::SpinButton::with_range(1.0, 60.0, 1.0);
+    clock_base_minutes_input.set_value((CLOCK_BASE_MS / 60_000) as f64);
+    let clock_increment_seconds_input = gtk::SpinButton::with_range(0.0, 60.0, 1.0);
+    clock_increment_seconds_input.set_value((CLOCK_INCREMENT_MS / 1_000) as f64);
+
+    let box_clock_settings = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .hexpand(true)
+        .spacing(5)
+        .build();
+    box_clock_settings.append(&gtk::Label::new(Some("每方总时间(分钟):")));
+    box_clock_settings.append(&clock_base_minutes_input);
+    box_clock_settings.append(&gtk::Label::new(Some("每步加时(秒):")));
+    box_clock_settings.append(&clock_increment_seconds_input);
+
+    chat_entry.connect_activate(clone!(
+    @weak chat_view,
+    @strong cl_sender,
+    @strong chat_nickname,
+    @weak name_input,
+    @strong state,
+    => move |entry| {
+        let text = entry.buffer().text().as_str().to_string();
+        entry.buffer().set_text("");
+
+        if text.is_empty() {
+            return;
+        }
+
+        if text == "/help" {
+            append_chat_line(&chat_view, "[系统] 可用命令: /help  /name <新昵称>  /draw  /undo  /resign");
+        } else if let Some(new_name) = text.strip_prefix("/name ") {
+            let new_name = new_name.trim().to_string();
+            append_chat_line(&chat_view, &format!("[系统] 昵称已改为 {new_name}"));
+            *chat_nickname.borrow_mut() = Some(new_name);
+        } else if text == "/draw" {
+            cl_sender.send(NetworkEvent::DrawRequest).unwrap();
+            append_chat_line(&chat_view, "[系统] 已发送和棋请求");
+        } else if text == "/undo" {
+            cl_sender.send(NetworkEvent::UndoRequest).unwrap();
+            append_chat_line(&chat_view, "[系统] 已发送悔棋请求");
+        } else if text == "/resign" {
+            state.lock().unwrap().frozen = true;
+            cl_sender.send(NetworkEvent::Escape).unwrap();
+            append_chat_line(&chat_view, "[系统] 已经认输了");
+        } else {
+            let name = chat_nickname.borrow().clone()
+                .unwrap_or_else(|| name_input.buffer().text().as_str().to_string());
+            let full_msg = format!("{name}: {text}");
+
+            append_chat_line(&chat_view, &full_msg);
+            cl_sender.send(NetworkEvent::ChatMessage(full_msg)).unwrap();
+        }
+    }
+    ));
+
+    // 等待房间里的聊天框，没有/help那些命令，就单纯发消息
+    room_chat_entry.connect_activate(clone!(
+    @weak room_chat_view,
+    @strong cl_sender,
+    @weak name_input,
+    => move |entry| {
+        let text = entry.buffer().text().as_str().to_string();
+        entry.buffer().set_text("");
+
+        if text.is_empty() {
+            return;
+        }
+
+        let name = name_input.buffer().text().as_str().to_string();
+        let full_msg = format!("{name}: {text}");
+
+        append_chat_line(&room_chat_view, &full_msg);
+        cl_sender.send(NetworkEvent::ChatMessage(full_msg)).unwrap();
+    }
+    ));
+
+    /*let conn_status_bar = gtk::Label::builder()
+        .label(STATUS_BAR_INITIAL_TEXT)
         .hexpand(true)
         .halign(gtk::Align::Center)
         .valign(gtk::Align::Center)
@@ -1074,10 +1788,15 @@ fn build_ui(app: &Application) {
     @weak connect_page,
     @weak room_owner_label,
     @weak room_player_label,
+    @weak chat_view,
+    @weak room_chat_view,
+    @weak emote_popup,
     @weak win,
     @weak stack,
     @weak name_input,
     @weak button_prepare,
+    @weak connection_list,
+    @strong room_list_store,
     @strong last_pong,
     @strong connect_stage,
     @strong event_sender,
@@ -1111,6 +1830,37 @@ fn build_ui(app: &Application) {
                     *daemon_running.lock().unwrap() = false;
                 },
 
+                NetworkEvent::VersionMismatch { server, client } => {
+                    conn_status_bar.set_label(&format!("版本不兼容(房主 {server} / 自己 {client})"));
+                    connect_page.set_sensitive(true);
+                    *connect_stage_ref = ConnectStage::No;
+                    *discover.lock().unwrap() = DiscoverState::Continue;
+                    *daemon_running.lock().unwrap() = false;
+                },
+
+                NetworkEvent::EnterPermitted { name, role: Role::Visitor } => {
+                    *discover.lock().unwrap() = DiscoverState::Stop;
+                    conn_status_bar.set_label("成功连接");
+                    connect_page.set_sensitive(true);
+
+                    let mut state_ref = state.lock().unwrap();
+                    state_ref.history.clear();
+                    state_ref.move_times.clear();
+                    state_ref.frozen = true;
+                    state_ref.current_team = Team::Black;
+                    state_ref.mode = Mode::Spectating { peer_name: name.clone() };
+                    drop(state_ref);
+
+                    grid.borrow_mut().clear();
+                    status_bar.set_label(&format!("正在观战 {name} 的对局"));
+
+                    switch_tool_bar_copy(false);
+
+                    *connect_stage_ref = ConnectStage::Gaming;
+
+                    stack.set_visible_child_name("game");
+                },
+
                 NetworkEvent::EnterPermitted { name, role } => {
                     stack.set_visible_child_name("room");
                     *discover.lock().unwrap() = DiscoverState::Stop;
@@ -1136,6 +1886,25 @@ fn build_ui(app: &Application) {
                     connect_page.set_sensitive(true);
                 },
 
+                NetworkEvent::RoomList { rooms } => {
+                    while let Some(row) = connection_list.row_at_index(0) {
+                        connection_list.remove(&row);
+                    }
+
+                    for room in &rooms {
+                        let label = gtk::Label::new(Some(&format!(
+                            "房间{} · 房主 {} · {}人 · {}",
+                            room.id, room.owner_name, room.player_count,
+                            if room.prepared { "游戏中" } else { "等待中" },
+                        )));
+                        label.set_halign(gtk::Align::Start);
+                        connection_list.append(&label);
+                    }
+
+                    *room_list_store.borrow_mut() = rooms;
+                    conn_status_bar.set_label("房间列表已刷新");
+                },
+
                 _ => {}
             }
         } else if let ConnectStage::Waiting { role, .. } = (*connect_stage_ref).clone() {
@@ -1181,19 +1950,26 @@ fn build_ui(app: &Application) {
                     room_player_label.set_label("等待加入...");
                 },
 
-                NetworkEvent::EnterRoom { name, .. } if role == Role::Owner => {
-                    cl_sender.send(NetworkEvent::EnterPermitted {
-                        role: Role::Player,
-                        name: String::clone(&myname),
-                    }).unwrap();
-
-                    if let ConnectStage::Waiting { ref mut opponent_name, .. } = *connect_stage_ref {
-                        *opponent_name = Some(String::clone(&name));
+                NetworkEvent::EnterRoom { name, protocol_version, .. } if role == Role::Owner => {
+                    if protocol_version != PROTOCOL_VERSION {
+                        cl_sender.send(NetworkEvent::VersionMismatch {
+                            server: PROTOCOL_VERSION,
+                            client: protocol_version,
+                        }).unwrap();
                     } else {
-                        unreachable!();
-                    }
+                        cl_sender.send(NetworkEvent::EnterPermitted {
+                            role: Role::Player,
+                            name: String::clone(&myname),
+                        }).unwrap();
 
-                    room_player_label.set_label(&format!("玩家(白方)    {name}"));
+                        if let ConnectStage::Waiting { ref mut opponent_name, .. } = *connect_stage_ref {
+                            *opponent_name = Some(String::clone(&name));
+                        } else {
+                            unreachable!();
+                        }
+
+                        room_player_label.set_label(&format!("玩家(白方)    {name}"));
+                    }
                 },
 
                 NetworkEvent::SetPrepared(val) if role == Role::Owner => {
@@ -1211,17 +1987,22 @@ fn build_ui(app: &Application) {
                     }
                 },
 
-                NetworkEvent::StartGame if role == Role::Player => {
+                NetworkEvent::StartGame { base_ms, increment_ms } if role == Role::Player => {
                     room_owner_label.set_label("");
                     room_player_label.set_label("");
                     status_bar.set_label(STATUS_BAR_INITIAL_TEXT);
 
                     let mut state_ref = state.lock().unwrap();
                     state_ref.history.clear();
+                    state_ref.move_times.clear();
                     state_ref.frozen = false;
                     state_ref.current_team = Team::Black;
+                    state_ref.remaining_ms_owner = base_ms;
+                    state_ref.remaining_ms_player = base_ms;
+                    state_ref.increment_ms = increment_ms;
                     state_ref.mode = Mode::MultiplePlayer {
                         my_team: Team::White,
+                        own_name: myname.clone(),
                         peer_name: if let ConnectStage::Waiting { ref opponent_name, .. } = *connect_stage_ref {
                             opponent_name.as_ref().unwrap().clone()
                         } else {
@@ -1239,6 +2020,10 @@ fn build_ui(app: &Application) {
                     stack.set_visible_child_name("game");
                 },
 
+                NetworkEvent::ChatMessage(msg) => {
+                    append_chat_line(&room_chat_view, &msg);
+                },
+
                 _ => {},
             }
         } else if *connect_stage_ref == ConnectStage::Gaming {
@@ -1267,11 +2052,23 @@ fn build_ui(app: &Application) {
                             let mut target_opt = grid_ref.at_mut(x as isize, y as isize);
                             if let Some(ref mut target) = target_opt {
                                 if target.chess.is_none() {
+                                    let mover = state_ref.current_team;
+
                                     state_ref.history.push((x as isize, y as isize));
-                                    target.chess = Some(state_ref.current_team);
+                                    state_ref.move_times.push(SystemTime::now());
+                                    target.chess = Some(mover);
                                     state_ref.current_team.set_opposite();
                                     update_status_bar(state_ref.current_team);
                                     chessboard_area.queue_draw();
+
+                                    // 房主权威地给对方刚走的这步加回时间，再把最新的棋钟广播出去
+                                    if *my_team == Team::Black {
+                                        state_ref.remaining_ms_player += state_ref.increment_ms;
+                                        cl_sender.send(NetworkEvent::TimeSync {
+                                            remaining_ms_owner: state_ref.remaining_ms_owner,
+                                            remaining_ms_player: state_ref.remaining_ms_player,
+                                        }).unwrap();
+                                    }
                                 } else {
                                     state_ref.frozen = true;
                                     status_bar.set_label("错误: 对方下棋，但那里已经有棋了");
@@ -1290,12 +2087,192 @@ fn build_ui(app: &Application) {
                             *daemon_running.lock().unwrap() = false;
                             cl_sender.send(NetworkEvent::Error("You were trying to put a chess while it was not your round".to_owned())).unwrap();
                         }
+                    } else if let Mode::Spectating { .. } = state_ref.mode {
+                        let mut grid_ref = grid.borrow_mut();
+                        if let Some(target) = grid_ref.at_mut(x as isize, y as isize) {
+                            target.chess = Some(state_ref.current_team);
+                        }
+                        state_ref.history.push((x as isize, y as isize));
+                        state_ref.move_times.push(SystemTime::now());
+                        state_ref.current_team.set_opposite();
+                        update_status_bar(state_ref.current_team);
+                        chessboard_area.queue_draw();
                     } else {
                         unreachable!();
                     }
                 },
 
+                NetworkEvent::BoardSnapshot { moves } => {
+                    let mut grid_ref = grid.borrow_mut();
+                    let mut state_ref = state.lock().unwrap();
+
+                    grid_ref.clear();
+                    state_ref.history.clear();
+                    state_ref.move_times.clear();
+
+                    let mut team = Team::Black;
+                    for (x, y) in moves {
+                        if let Some(target) = grid_ref.at_mut(x as isize, y as isize) {
+                            target.chess = Some(team);
+                        }
+                        state_ref.history.push((x as isize, y as isize));
+                        state_ref.move_times.push(SystemTime::now());
+                        team.set_opposite();
+                    }
+                    state_ref.current_team = team;
+                    update_status_bar(state_ref.current_team);
+
+                    chessboard_area.queue_draw();
+                },
+
+                NetworkEvent::TimeSync { remaining_ms_owner, remaining_ms_player } => {
+                    let mut state_ref = state.lock().unwrap();
+                    state_ref.remaining_ms_owner = remaining_ms_owner;
+                    state_ref.remaining_ms_player = remaining_ms_player;
+                },
+
+                NetworkEvent::Timeout { loser } => {
+                    state.lock().unwrap().frozen = true;
+
+                    let loser_str = match loser {
+                        Role::Owner => "房主(黑方)",
+                        _ => "玩家(白方)",
+                    };
+                    status_bar.set_label(&format!("时间到！{loser_str} 超时了"));
+                },
+
                 NetworkEvent::UndoRequest => {
+                    let state_ref = state.lock().unwrap();
+                    let frozen = state_ref.frozen;
+                    let empty = state_ref.history.is_empty();
+                    let my_team = if let Mode::MultiplePlayer { my_team, .. } = state_ref.mode {
+                        my_team
+                    } else {
+                        unreachable!();
+                    };
+                    drop(state_ref);
+
+                    if frozen || empty {
+                        cl_sender.send(NetworkEvent::UndoReply(false)).unwrap();
+                    } else {
+                        let msgbox = gtk::MessageDialog::builder()
+                            .text("对方请求悔棋，是否同意？")
+                            .buttons(gtk::ButtonsType::YesNo)
+                            .message_type(gtk::MessageType::Question)
+                            .transient_for(&win)
+                            .modal(true)
+                            .build();
+
+                        let update_status_bar = upsb.clone();
+                        msgbox.connect_response(clone!(
+                        @strong grid, @strong state, @weak chessboard_area, @weak status_bar,
+                        @strong cl_sender, @strong update_status_bar,
+                        => move |dialog, response| {
+                            let accepted = response == gtk::ResponseType::Yes;
+
+                            if accepted {
+                                let mut state_ref = state.lock().unwrap();
+                                let requester_team = my_team.get_opposite();
+                                let plies = if state_ref.current_team == requester_team { 2 } else { 1 };
+                                pop_undo_plies(&mut grid.borrow_mut(), &mut state_ref, plies);
+                                update_status_bar(state_ref.current_team);
+                                chessboard_area.queue_draw();
+                            }
+
+                            cl_sender.send(NetworkEvent::UndoReply(accepted)).unwrap();
+                            dialog.destroy();
+                        }
+                        ));
+
+                        msgbox.present();
+                    }
+                },
+
+                NetworkEvent::UndoReply(accepted) => {
+                    if accepted {
+                        let mut state_ref = state.lock().unwrap();
+                        let my_team = if let Mode::MultiplePlayer { my_team, .. } = state_ref.mode {
+                            my_team
+                        } else {
+                            unreachable!();
+                        };
+                        let plies = if state_ref.current_team == my_team { 2 } else { 1 };
+                        pop_undo_plies(&mut grid.borrow_mut(), &mut state_ref, plies);
+                        update_status_bar(state_ref.current_team);
+                        chessboard_area.queue_draw();
+                        status_bar.set_label("对方同意了悔棋请求");
+                    } else {
+                        status_bar.set_label("对方拒绝了悔棋请求");
+                    }
+                },
+
+                NetworkEvent::ChatMessage(msg) => {
+                    append_chat_line(&chat_view, &msg);
+                },
+
+                NetworkEvent::DrawRequest => {
+                    let frozen = state.lock().unwrap().frozen;
+
+                    if frozen {
+                        cl_sender.send(NetworkEvent::DrawReply(false)).unwrap();
+                    } else {
+                        let msgbox = gtk::MessageDialog::builder()
+                            .text("对方请求和棋，是否同意？")
+                            .buttons(gtk::ButtonsType::YesNo)
+                            .message_type(gtk::MessageType::Question)
+                            .transient_for(&win)
+                            .modal(true)
+                            .build();
+
+                        msgbox.connect_response(clone!(
+                        @strong state, @weak status_bar, @strong cl_sender,
+                        => move |dialog, response| {
+                            let accepted = response == gtk::ResponseType::Yes;
+
+                            if accepted {
+                                state.lock().unwrap().frozen = true;
+                                status_bar.set_label("双方同意和棋，对局结束");
+                            }
+
+                            cl_sender.send(NetworkEvent::DrawReply(accepted)).unwrap();
+                            dialog.destroy();
+                        }
+                        ));
+
+                        msgbox.present();
+                    }
+                },
+
+                NetworkEvent::DrawReply(accepted) => {
+                    if accepted {
+                        state.lock().unwrap().frozen = true;
+                        status_bar.set_label("对方同意和棋，对局结束");
+                        append_chat_line(&chat_view, "[系统] 对方同意和棋");
+                    } else {
+                        status_bar.set_label("对方拒绝了和棋请求");
+                        append_chat_line(&chat_view, "[系统] 对方拒绝了和棋请求");
+                    }
+                },
+
+                NetworkEvent::Escape => {
+                    state.lock().unwrap().frozen = true;
+                    status_bar.set_label("对方投降了");
+                    append_chat_line(&chat_view, "[系统] 对方认输了");
+                },
+
+                NetworkEvent::Emote(id) => {
+                    let text = EMOTES.get(id as usize).copied().unwrap_or("?");
+                    emote_popup.set_label(text);
+                    emote_popup.set_visible(true);
+
+                    glib::timeout_add_local(Duration::from_millis(1500), clone!(
+                    @weak emote_popup,
+                    => @default-return glib::Continue(false),
+                    move || {
+                        emote_popup.set_visible(false);
+                        glib::Continue(false)
+                    }
+                    ));
                 },
 
                 _ => {},
@@ -1305,6 +2282,97 @@ fn build_ui(app: &Application) {
     }
     ));
 
+    let update_status_bar = upsb.clone();
+    // 处理AI算出的落子结果
+    ai_receiver.attach(None, clone!(
+    @strong grid,
+    @strong state,
+    @weak chessboard_area,
+    @weak win,
+    @weak status_bar,
+    => @default-return glib::Continue(true),
+    move |(x, y)| {
+        let mut state_ref = state.lock().unwrap();
+        let mut grid_ref = grid.borrow_mut();
+
+        if let Mode::SinglePlayerVsAi { my_team, .. } = &state_ref.mode {
+            let ai_team = my_team.get_opposite();
+
+            grid_ref.at_mut(x, y).unwrap().chess = Some(ai_team);
+            state_ref.history.push((x, y));
+            state_ref.move_times.push(SystemTime::now());
+            state_ref.current_team.set_opposite();
+            state_ref.frozen = false;
+
+            update_status_bar(state_ref.current_team);
+
+            chessboard_area.queue_draw();
+
+            if let Some(team_win) = grid_ref.check_win() {
+                state_ref.frozen = true;
+
+                let team_str = team_win.as_str();
+                let adj = get_a_good_adj();
+
+                status_bar.set_label(&format!("{team_str} {adj}"));
+
+                let msgbox = gtk::MessageDialog::builder()
+                    .text(format!("{team_str} 赢了"))
+                    .buttons(gtk::ButtonsType::Ok)
+                    .message_type(gtk::MessageType::Info)
+                    .transient_for(&win)
+                    .modal(true)
+                    .build();
+                msgbox.present();
+            }
+        }
+
+        glib::Continue(true)
+    }
+    ));
+
+    // 每0.5秒倒计时一次棋钟，顺带刷新棋钟显示；谁自己的棋钟走完了，谁就把Timeout发出去
+    glib::timeout_add_local(Duration::from_millis(500), clone!(
+    @strong state,
+    @strong cl_sender,
+    @weak clock_label,
+    @weak status_bar,
+    => @default-return glib::Continue(true),
+    move || {
+        let mut state_ref = state.lock().unwrap();
+
+        if let Mode::MultiplePlayer { my_team, .. } = &state_ref.mode {
+            let my_team = *my_team;
+
+            if !state_ref.frozen {
+                match state_ref.current_team {
+                    Team::Black => state_ref.remaining_ms_owner = state_ref.remaining_ms_owner.saturating_sub(500),
+                    Team::White => state_ref.remaining_ms_player = state_ref.remaining_ms_player.saturating_sub(500),
+                }
+
+                let my_clock_ran_out = my_team == state_ref.current_team && match my_team {
+                    Team::Black => state_ref.remaining_ms_owner == 0,
+                    Team::White => state_ref.remaining_ms_player == 0,
+                };
+
+                if my_clock_ran_out {
+                    state_ref.frozen = true;
+                    status_bar.set_label("时间到，你输了");
+                    cl_sender.send(NetworkEvent::Timeout {
+                        loser: if my_team == Team::Black { Role::Owner } else { Role::Player },
+                    }).unwrap();
+                }
+            }
+
+            format_clock_label(&clock_label, state_ref.remaining_ms_owner, state_ref.remaining_ms_player);
+        } else if let Mode::Spectating { .. } = &state_ref.mode {
+            format_clock_label(&clock_label, state_ref.remaining_ms_owner, state_ref.remaining_ms_player);
+        }
+
+        glib::Continue(true)
+    }
+    ));
+
     // 对地址进行连接。该闭包会召唤新线程处理连接
     let do_connect = clone!(
     @weak conn_status_bar,
@@ -1318,7 +2386,7 @@ fn build_ui(app: &Application) {
     @strong cl_receiver,
     @weak connect_page,
     @weak name_input,
-    => move |address: SocketAddr| {
+    => move |address: SocketAddr, role: Role, room_id: u32| {
         conn_status_bar.set_label(&format!("正在连接到{}", address));
         *discover.lock().unwrap() = DiscoverState::Pause;
         *daemon_running.lock().unwrap() = true;
@@ -1344,8 +2412,8 @@ fn build_ui(app: &Application) {
             // 连接成功后持续接收网络数据
             // 后续应用层的消息处理将由event_receiver完成
             let mut stream = result.unwrap();
-            let mut buf = [0u8; 2048];
-            let mut bytes_available = 0usize;
+            let mut read_chunk = [0u8; 2048];
+            let mut buf: Vec<u8> = Vec::new();
 
             stream.set_read_timeout(Some(Duration::from_millis(80))).unwrap();
 
@@ -1360,7 +2428,7 @@ fn build_ui(app: &Application) {
             }
 
             // 发送请求加入的消息
-            let packet = NetworkEvent::EnterRoom { role: Role::Player, name: myname }.to_u8_vec();
+            let packet = NetworkEvent::EnterRoom { role, name: myname, protocol_version: PROTOCOL_VERSION, room_id }.to_u8_vec();
             _unwrap!(stream.write(packet.as_slice()));
 
             while *daemon_running.lock().unwrap() == true {
@@ -1388,11 +2456,7 @@ fn build_ui(app: &Application) {
                     _unwrap!(stream.write(packet.as_slice()));
                 }
 
-                if bytes_available >= 2048 {
-                    event_sender.send(NetworkEvent::Error("Buffer is overflowing".to_owned())).unwrap();
-                }
-
-                let result = stream.read(&mut buf[bytes_available..]);
+                let result = stream.read(&mut read_chunk);
                 let bytes_new = match result {
                     Ok(b) => b,
                     Err(err) => {
@@ -1409,22 +2473,24 @@ fn build_ui(app: &Application) {
                     },
                 };
 
-                bytes_available += bytes_new;
+                buf.extend_from_slice(&read_chunk[0..bytes_new]);
 
-                while let Some((event, length)) = NetworkEvent::from_buffer(&buf[0..bytes_available]) {
+                // 长度前缀已经收到了就可以先看看对方是不是声明了一个离谱的长度
+                if buf.len() >= 4 {
+                    let declared_len = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize;
+                    if declared_len > MAX_FRAME {
+                        event_sender.send(NetworkEvent::Error(format!("Frame too large: {declared_len} bytes"))).unwrap();
+                        let _ = stream.shutdown(Shutdown::Both);
+                        return;
+                    }
+                }
+
+                while let Some((event, length)) = NetworkEvent::from_buffer(&buf) {
                     // 发送接收到的数据
                     event_sender.send(event).unwrap();
 
                     // 将已解析的数据忽略
-                    if length == bytes_available {
-                        bytes_available = 0;
-                    } else if length == 0 {
-                        break;
-                    } else {
-                        let rest_data = buf[length..bytes_available].to_owned();
-                        buf[0..(bytes_available - length)].clone_from_slice(rest_data.as_slice());
-                        bytes_available -= length;
-                    }
+                    buf.drain(0..length);
                 }
 
                 use std::sync::mpsc::RecvTimeoutError;
@@ -1450,12 +2516,13 @@ fn build_ui(app: &Application) {
 
     let button_connect_address = gtk::Button::with_label("连接到这个地址");
     button_connect_address.connect_clicked(clone!(
-    @weak address_input, @weak conn_status_bar => move |_| {
+    @weak address_input, @weak conn_status_bar, @weak spectate_checkbox, @strong do_connect, => move |_| {
         let tmp = address_input.buffer().text();
         let address_str = tmp.as_str();
+        let role = if spectate_checkbox.is_active() { Role::Visitor } else { Role::Player };
         match address_str.parse::<SocketAddr>() {
             Ok(address) => {
-                do_connect.clone()(address);
+                do_connect.clone()(address, role, 0);
             },
             Err(err) => {
                 conn_status_bar.set_label(&format!("地址格式错误:{}", err));
@@ -1464,6 +2531,57 @@ fn build_ui(app: &Application) {
     }
     ));
 
+    // 刷新房间列表按钮：去问一下那个地址上目前开着的房间有哪些，填进connection_list里
+    let button_list_rooms = gtk::Button::with_label("刷新房间列表");
+    button_list_rooms.connect_clicked(clone!(
+    @weak address_input, @weak conn_status_bar,
+    @strong event_sender, @strong room_list_address,
+    => move |_| {
+        let tmp = address_input.buffer().text();
+        let address = match tmp.as_str().parse::<SocketAddr>() {
+            Ok(address) => address,
+            Err(err) => {
+                conn_status_bar.set_label(&format!("地址格式错误:{}", err));
+                return;
+            },
+        };
+
+        *room_list_address.borrow_mut() = Some(address);
+        conn_status_bar.set_label(&format!("正在查询{}的房间列表", address));
+
+        thread::spawn(clone!(
+        @strong event_sender,
+        => move || {
+            let result = TcpStream::connect_timeout(&address, Duration::from_millis(5000));
+            let mut stream = match result {
+                Ok(s) => s,
+                Err(err) => {
+                    event_sender.send(NetworkEvent::Error(err.to_string())).unwrap();
+                    return;
+                },
+            };
+            stream.set_read_timeout(Some(Duration::from_millis(2000))).unwrap();
+
+            if let Err(err) = stream.write(NetworkEvent::ListRooms.to_u8_vec().as_slice()) {
+                event_sender.send(NetworkEvent::Error(err.to_string())).unwrap();
+                return;
+            }
+
+            let mut buf = [0u8; 2048];
+            let bytes_read = stream.read(&mut buf).unwrap_or(0);
+            match NetworkEvent::from_buffer(&buf[0..bytes_read]) {
+                Some((event @ NetworkEvent::RoomList {..}, _)) => {
+                    event_sender.send(event).unwrap();
+                },
+                _ => {
+                    event_sender.send(NetworkEvent::Error("对方没有正确回复房间列表".to_string())).unwrap();
+                },
+            }
+        }
+        ));
+    }
+    ));
+
     // 返回主页面按钮
     let button_exit = gtk::Button::with_label("返回主页面");
     button_exit.connect_clicked(clone!(
@@ -1480,6 +2598,7 @@ fn build_ui(app: &Application) {
     @weak stack,
     @weak room_owner_label,
     @weak room_player_label,
+    @weak room_code_label,
     @weak name_input,
     @weak button_prepare,
     @strong daemon_running,
@@ -1489,6 +2608,8 @@ fn build_ui(app: &Application) {
     @strong connect_stage,
     @strong discover,
     @strong cl_receiver,
+    @strong state,
+    @strong spectator_count,
     => move |_| {
         *discover.lock().unwrap() = DiscoverState::Stop;
         *connect_stage.lock().unwrap() = ConnectStage::Waiting {
@@ -1499,6 +2620,7 @@ fn build_ui(app: &Application) {
         *daemon_running.lock().unwrap() = true;
         *last_pong.lock().unwrap() = Instant::now();
         *last_ping.lock().unwrap() = Instant::now();
+        *spectator_count.lock().unwrap() = 0;
 
         button_prepare.set_label("开始游戏");
 
@@ -1510,6 +2632,8 @@ fn build_ui(app: &Application) {
 
         room_owner_label.set_label(&format!("房主(黑方):    {myname}"));
         room_player_label.set_label("等待加入...");
+        // 中转配对服务器还没做，这个码目前只能拿给对方抄IP地址用，不能直接输进去连
+        room_code_label.set_label(&format!("房间码(仅供参考，配对服务器未接入): {}", generate_room_code()));
 
         // 召唤新线程处理连接
         thread::spawn(clone!(
@@ -1519,6 +2643,8 @@ fn build_ui(app: &Application) {
         @strong last_pong,
         @strong event_sender,
         @strong daemon_running,
+        @strong state,
+        @strong spectator_count,
         => move || {
             // 监听端口
             let listener = match TcpListener::bind("[::]:12001".parse::<SocketAddr>().unwrap()) {
@@ -1529,8 +2655,20 @@ fn build_ui(app: &Application) {
                 },
             };
 
-            let mut buf = [0u8; 2048];
-            let mut bytes_available = 0usize;
+            let mut read_chunk = [0u8; 2048];
+            let mut buf: Vec<u8> = Vec::new();
+            let mut visitors: Vec<TcpStream> = Vec::new();
+
+            // 把会改变棋盘/对局状态的事件也转发给所有观战者，下棋/悔棋通过/开始游戏/逃跑/散伙/棋钟同步/超时/聊天/和棋请求/和棋通过/表情才广播，Ping之类的没必要
+            macro_rules! broadcast_to_visitors {
+                ($event:expr) => {
+                    if matches!($event, NetworkEvent::PutChess {..} | NetworkEvent::UndoReply(true) | NetworkEvent::StartGame {..} | NetworkEvent::Escape | NetworkEvent::TimeSync {..} | NetworkEvent::Timeout {..} | NetworkEvent::ChatMessage(..) | NetworkEvent::DrawRequest | NetworkEvent::DrawReply(true) | NetworkEvent::Emote(..) | NetworkEvent::RoomDisbanded) {
+                        let packet = $event.to_u8_vec();
+                        visitors.retain_mut(|v| v.write(packet.as_slice()).is_ok());
+                        *spectator_count.lock().unwrap() = visitors.len();
+                    }
+                }
+            }
 
             macro_rules! _unwrap {
                 ($result:expr) => {{
@@ -1597,8 +2735,78 @@ fn build_ui(app: &Application) {
                 }
 
                 if let Ok((mut stream_tmp, _)) = listener.accept() {
-                    let _ = stream_tmp.write(NetworkEvent::RoomIsFull.to_u8_vec().as_slice());
-                    let _ = stream_tmp.shutdown(Shutdown::Both);
+                    // 先等一下看看对方是不是想以观战身份进来的，或者只是过来问问这个房间的情况
+                    stream_tmp.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+                    let mut probe_buf = [0u8; 256];
+                    let probe_len = stream_tmp.read(&mut probe_buf).unwrap_or(0);
+                    let probe_event = if probe_len > 0 {
+                        NetworkEvent::from_buffer(&probe_buf[0..probe_len]).map(|(e, _)| e)
+                    } else {
+                        None
+                    };
+                    let visitor_join = matches!(probe_event, Some(NetworkEvent::EnterRoom { role: Role::Visitor, .. }));
+                    // 观战者走的是probe这条近路，跟正常玩家的EnterRoom分开处理，版本号也得单独在这里查一遍
+                    let visitor_version_mismatch = matches!(
+                        probe_event,
+                        Some(NetworkEvent::EnterRoom { role: Role::Visitor, protocol_version, .. }) if protocol_version != PROTOCOL_VERSION
+                    );
+
+                    if visitor_version_mismatch {
+                        let client_version = if let Some(NetworkEvent::EnterRoom { protocol_version, .. }) = probe_event {
+                            protocol_version
+                        } else {
+                            unreachable!();
+                        };
+                        let mismatch = NetworkEvent::VersionMismatch {
+                            server: PROTOCOL_VERSION,
+                            client: client_version,
+                        }.to_u8_vec();
+                        let _ = stream_tmp.write(mismatch.as_slice());
+                        let _ = stream_tmp.shutdown(Shutdown::Both);
+                    } else if matches!(probe_event, Some(NetworkEvent::ListRooms)) {
+                        // 目前还只有这一个房间，没有真正的房间表，能给的就只有自己这一间的信息
+                        let opponent_name = if let ConnectStage::Waiting { ref opponent_name, .. } = *connect_stage_ref {
+                            opponent_name.clone()
+                        } else {
+                            None
+                        };
+                        let prepared = if let ConnectStage::Waiting { prepared, .. } = *connect_stage_ref {
+                            prepared
+                        } else {
+                            false
+                        };
+
+                        let room_list = NetworkEvent::RoomList {
+                            rooms: vec![RoomInfo {
+                                id: 0,
+                                owner_name: myname.clone(),
+                                player_count: if opponent_name.is_some() { 2 } else { 1 },
+                                prepared,
+                            }],
+                        }.to_u8_vec();
+
+                        let _ = stream_tmp.write(room_list.as_slice());
+                        let _ = stream_tmp.shutdown(Shutdown::Both);
+                    } else if visitor_join {
+                        let permitted = NetworkEvent::EnterPermitted {
+                            role: Role::Visitor,
+                            name: myname.clone(),
+                        }.to_u8_vec();
+                        let snapshot = NetworkEvent::BoardSnapshot {
+                            moves: state.lock().unwrap().history.iter().map(|&(x, y)| (x as u8, y as u8)).collect(),
+                        }.to_u8_vec();
+
+                        if stream_tmp.write(permitted.as_slice()).is_ok()
+                            && stream_tmp.write(snapshot.as_slice()).is_ok()
+                        {
+                            stream_tmp.set_read_timeout(Some(Duration::from_millis(80))).unwrap();
+                            visitors.push(stream_tmp);
+                            *spectator_count.lock().unwrap() = visitors.len();
+                        }
+                    } else {
+                        let _ = stream_tmp.write(NetworkEvent::RoomIsFull.to_u8_vec().as_slice());
+                        let _ = stream_tmp.shutdown(Shutdown::Both);
+                    }
                 }
 
                 // 检测超时
@@ -1632,11 +2840,7 @@ fn build_ui(app: &Application) {
                     }
                 }
 
-                if bytes_available >= 2048 {
-                    event_sender.send(NetworkEvent::Error("Buffer is overflowing".to_owned())).unwrap();
-                }
-
-                let result = stream.read(&mut buf[bytes_available..]);
+                let result = stream.read(&mut read_chunk);
                 let bytes_new = match result {
                     Ok(b) => b,
                     Err(err) => {
@@ -1659,22 +2863,26 @@ fn build_ui(app: &Application) {
                 };
                 error_just_now = false;
 
-                bytes_available += bytes_new;
+                buf.extend_from_slice(&read_chunk[0..bytes_new]);
+
+                // 长度前缀已经收到了就可以先看看对方是不是声明了一个离谱的长度
+                if buf.len() >= 4 {
+                    let declared_len = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize;
+                    if declared_len > MAX_FRAME {
+                        event_sender.send(NetworkEvent::Error(format!("Frame too large: {declared_len} bytes"))).unwrap();
+                        let _ = stream.shutdown(Shutdown::Both);
+                        return;
+                    }
+                }
+
+                while let Some((event, length)) = NetworkEvent::from_buffer(&buf) {
+                    broadcast_to_visitors!(event);
 
-                while let Some((event, length)) = NetworkEvent::from_buffer(&buf[0..bytes_available]) {
                     // 发送接收到的数据
                     event_sender.send(event).unwrap();
 
                     // 将已解析的数据忽略
-                    if length == bytes_available {
-                        bytes_available = 0;
-                    } else if length == 0 {
-                        break;
-                    } else {
-                        let rest_data = buf[length..bytes_available].to_owned();
-                        buf[0..(bytes_available - length)].clone_from_slice(rest_data.as_slice());
-                        bytes_available -= length;
-                    }
+                    buf.drain(0..length);
                 }
 
                 use std::sync::mpsc::RecvTimeoutError;
@@ -1682,6 +2890,7 @@ fn build_ui(app: &Application) {
                 match r.recv_timeout(Duration::from_millis(50)) {
                     Ok(event) => {
                         println!("Send event {event:?} to peer");
+                        broadcast_to_visitors!(event);
                         _unwrap!(stream.write(event.to_u8_vec().as_slice()));
                     },
 
@@ -1700,12 +2909,24 @@ fn build_ui(app: &Application) {
     }
     ));
 
-    // 尝试连接按钮
+    // 尝试连接按钮：连到上次刷新房间列表的那个地址，房间id取选中那行对应的房间
     let button_connect = gtk::Button::with_label("连接");
     button_connect.connect_clicked(clone!(
-    @weak connection_list => move |_| {
-        //let row = connection_list.selected_row();
-        //thread::spawn();
+    @weak connection_list, @weak conn_status_bar, @weak spectate_checkbox,
+    @strong room_list_store, @strong room_list_address, @strong do_connect,
+    => move |_| {
+        let Some(row) = connection_list.selected_row() else { return; };
+        let Some(address) = *room_list_address.borrow() else {
+            conn_status_bar.set_label("还没刷新过房间列表");
+            return;
+        };
+        let Some(room) = room_list_store.borrow().get(row.index() as usize).cloned() else {
+            conn_status_bar.set_label("房间列表已经过期，重新刷新一下");
+            return;
+        };
+
+        let role = if spectate_checkbox.is_active() { Role::Visitor } else { Role::Player };
+        do_connect.clone()(address, role, room.id);
     }
     ));
 
@@ -1740,9 +2961,13 @@ fn build_ui(app: &Application) {
 
     box_custom_addr.append(&address_input);
     box_custom_addr.append(&button_connect_address);
+    box_custom_addr.append(&button_list_rooms);
+    box_custom_addr.append(&room_code_input);
 
     box1.append(&connection_list);
     box1.append(&name_input);
+    box1.append(&spectate_checkbox);
+    box1.append(&box_clock_settings);
     box1.append(&box_custom_addr);
     box1.append(&box2);
 
@@ -1779,6 +3004,7 @@ fn build_ui(app: &Application) {
         let mut state_ref = state.lock().unwrap();
         state_ref.current_team = Team::Black;
         state_ref.history.clear();
+        state_ref.move_times.clear();
         state_ref.mode = Mode::Singleplayer;
         state_ref.frozen = false;
 
@@ -1792,6 +3018,44 @@ fn build_ui(app: &Application) {
     }
     ));
 
+    // 人机对战按钮按难度分了三个，点的都是这同一个闭包
+    let switch_tool_bar_copy = switch_tool_bar.clone();
+    let start_vs_ai = clone!(
+    @weak stack, @strong state, @strong grid, @weak status_bar,
+    @strong switch_tool_bar_copy,
+    => move |difficulty: ai::Difficulty| {
+        let mut state_ref = state.lock().unwrap();
+        state_ref.current_team = Team::Black;
+        state_ref.history.clear();
+        state_ref.move_times.clear();
+        state_ref.mode = Mode::SinglePlayerVsAi { my_team: Team::Black, difficulty };
+        state_ref.frozen = false;
+
+        switch_tool_bar_copy(true);
+
+        status_bar.set_label(STATUS_BAR_INITIAL_TEXT);
+
+        grid.borrow_mut().clear();
+
+        stack.set_visible_child_name("game");
+    }
+    );
+
+    let button_vs_ai_easy = gtk::Button::with_label("人机对战(简单)");
+    button_vs_ai_easy.connect_clicked(clone!(
+    @strong start_vs_ai => move |_| start_vs_ai(ai::Difficulty::Easy)
+    ));
+
+    let button_vs_ai_medium = gtk::Button::with_label("人机对战(中等)");
+    button_vs_ai_medium.connect_clicked(clone!(
+    @strong start_vs_ai => move |_| start_vs_ai(ai::Difficulty::Medium)
+    ));
+
+    let button_vs_ai_hard = gtk::Button::with_label("人机对战(困难)");
+    button_vs_ai_hard.connect_clicked(clone!(
+    @strong start_vs_ai => move |_| start_vs_ai(ai::Difficulty::Hard)
+    ));
+
     let button_multiple_player = gtk::Button::with_label("联机游玩");
 
     button_multiple_player.connect_clicked(clone!(
@@ -1809,9 +3073,89 @@ fn build_ui(app: &Application) {
     }
     ));
 
+    let button_replay = gtk::Button::with_label("观战回放");
+    button_replay.connect_clicked(clone!(
+    @weak win,
+    @weak stack,
+    @strong grid,
+    @strong state,
+    @weak chessboard_area,
+    @weak status_bar,
+    @weak tool_bar_single_player,
+    @weak tool_bar_multiple_player,
+    @weak tool_bar_replay,
+    @weak team_suggestion,
+    @weak chat_box,
+    => move |_| {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("打开棋谱")
+            .transient_for(&win)
+            .modal(true)
+            .action(gtk::FileChooserAction::Open)
+            .build();
+        dialog.add_button("打开", gtk::ResponseType::Accept);
+        dialog.add_button("取消", gtk::ResponseType::Cancel);
+
+        dialog.connect_response(clone!(
+        @weak stack,
+        @strong grid,
+        @strong state,
+        @weak chessboard_area,
+        @weak status_bar,
+        @weak tool_bar_single_player,
+        @weak tool_bar_multiple_player,
+        @weak tool_bar_replay,
+        @weak team_suggestion,
+        @weak chat_box,
+        => move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    match std::fs::read_to_string(&path).map_err(|e| ParseError(e.to_string())).and_then(|s| import_record(&s)) {
+                        Ok(record) => {
+                            let RecordData { moves, black_name, white_name } = record;
+
+                            let mut state_ref = state.lock().unwrap();
+                            let team = apply_moves_up_to(&mut grid.borrow_mut(), &moves, moves.len());
+
+                            state_ref.history = moves.iter().map(|&(x, y)| (x as isize, y as isize)).collect();
+                            state_ref.move_times.clear();
+                            state_ref.current_team = team;
+                            state_ref.frozen = true;
+                            state_ref.mode = Mode::Replaying { cursor: moves.len(), moves };
+                            drop(state_ref);
+
+                            tool_bar_single_player.set_visible(false);
+                            tool_bar_multiple_player.set_visible(false);
+                            team_suggestion.set_visible(false);
+                            chat_box.set_visible(false);
+                            tool_bar_replay.set_visible(true);
+
+                            status_bar.set_label(&format!("棋谱回放: 黑方 {black_name}    白方 {white_name}"));
+                            chessboard_area.queue_draw();
+
+                            stack.set_visible_child_name("game");
+                        },
+                        Err(err) => {
+                            status_bar.set_label(&format!("棋谱加载失败: {err}"));
+                        },
+                    }
+                }
+            }
+            dialog.destroy();
+        }
+        ));
+
+        dialog.present();
+    }
+    ));
+
     title_page.append(&title);
     title_page.append(&button_single_player);
+    title_page.append(&button_vs_ai_easy);
+    title_page.append(&button_vs_ai_medium);
+    title_page.append(&button_vs_ai_hard);
     title_page.append(&button_multiple_player);
+    title_page.append(&button_replay);
 
 
     stack.add_named(&title_page, Some("title"));
@@ -1849,6 +3193,172 @@ fn get_a_good_adj()-> &'static str {
     adjs[rng.gen_range(0..adjs.len())]
 }
 
+// 把毫秒数格式化成mm:ss显示在棋钟标签上
+fn format_clock_label(label: &gtk::Label, remaining_ms_owner: u64, remaining_ms_player: u64) {
+    let fmt = |ms: u64| format!("{:02}:{:02}", ms / 60_000, (ms / 1000) % 60);
+    label.set_label(&format!(
+        "黑方(房主) {}    白方(玩家) {}",
+        fmt(remaining_ms_owner),
+        fmt(remaining_ms_player),
+    ));
+}
+
+/// 棋谱解析失败的原因
+#[derive(Debug)]
+struct ParseError(String);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter)-> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// 从棋谱文件里读出来的东西：落子序列(摆棋盘用)，以及黑白双方的名字(纯展示用，不参与摆棋盘)
+struct RecordData {
+    moves: Vec<(u8, u8)>,
+    black_name: String,
+    white_name: String,
+}
+
+// 黑白双方分别该署名什么：联机对局是真实的昵称，人机就写死"我"和"AI"，单机两边都是"我"，观战只知道房主的名字，另一方写"未知"
+fn player_names(state: &State)-> (String, String) {
+    match &state.mode {
+        Mode::MultiplePlayer { my_team, own_name, peer_name } => match my_team {
+            Team::Black => (own_name.clone(), peer_name.clone()),
+            Team::White => (peer_name.clone(), own_name.clone()),
+        },
+        Mode::SinglePlayerVsAi { my_team, .. } => match my_team {
+            Team::Black => ("我".to_string(), "AI".to_string()),
+            Team::White => ("AI".to_string(), "我".to_string()),
+        },
+        Mode::Spectating { peer_name } => (peer_name.clone(), "未知".to_string()),
+        Mode::Singleplayer | Mode::Replaying {..} => ("我".to_string(), "我".to_string()),
+    }
+}
+
+// 棋谱文本格式：第一行是结果头(暂时先写死，以后有输赢判定了再填真实结果)，
+// 然后是SIZE(棋盘边长，目前棋盘写死15x15)、BLACK:/WHITE:两行署名，
+// 后面每行一步棋，格式是"x,y,下棋时刻(unix毫秒)"，从黑方开始轮流下
+fn export_record(state: &State)-> String {
+    let (black_name, white_name) = player_names(state);
+
+    let mut out = String::new();
+    out.push_str("RESULT:未知结果\n");
+    out.push_str("SIZE:15\n");
+    out.push_str(&format!("BLACK:{black_name}\n"));
+    out.push_str(&format!("WHITE:{white_name}\n"));
+
+    for (i, &(x, y)) in state.history.iter().enumerate() {
+        let millis = state.move_times.get(i)
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        out.push_str(&format!("{x},{y},{millis}\n"));
+    }
+    out
+}
+
+fn import_record(text: &str)-> Result<RecordData, ParseError> {
+    let mut lines = text.lines();
+    let header = lines.next().ok_or_else(|| ParseError("棋谱是空的".to_string()))?;
+    if !header.starts_with("RESULT:") {
+        return Err(ParseError("不是合法的棋谱文件(缺少RESULT头)".to_string()));
+    }
+
+    let mut black_name = "未知".to_string();
+    let mut white_name = "未知".to_string();
+    let mut moves = Vec::new();
+
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(size) = line.strip_prefix("SIZE:") {
+            // 棋盘目前就固定15x15，别的尺寸的棋谱读不了
+            if size != "15" {
+                return Err(ParseError(format!("棋盘大小不支持: {size}")));
+            }
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("BLACK:") {
+            black_name = name.to_string();
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("WHITE:") {
+            white_name = name.to_string();
+            continue;
+        }
+
+        // 时间戳那一列是后来才加的，老棋谱没有也能读，走子照摆就是了
+        let mut parts = line.splitn(3, ',');
+        let x = parts.next()
+            .and_then(|s| s.parse::<u8>().ok())
+            .ok_or_else(|| ParseError(format!("这一行看不懂: {line}")))?;
+        let y = parts.next()
+            .and_then(|s| s.parse::<u8>().ok())
+            .ok_or_else(|| ParseError(format!("这一行看不懂: {line}")))?;
+
+        moves.push((x, y));
+    }
+
+    Ok(RecordData { moves, black_name, white_name })
+}
+
+// 清空棋盘，从头把moves里的前cursor步重新摆上去，返回摆完之后轮到谁走
+fn apply_moves_up_to(grid: &mut ChessboardGrid, moves: &[(u8, u8)], cursor: usize)-> Team {
+    grid.clear();
+
+    let mut team = Team::Black;
+    for &(x, y) in moves.iter().take(cursor) {
+        if let Some(cell) = grid.at_mut(x as isize, y as isize) {
+            cell.chess = Some(team);
+        }
+        team.set_opposite();
+    }
+    team
+}
+
+// 联机悔棋协商成功之后双方各自摆的那几步退掉：从history尾部弹`plies`步，
+// 棋盘对应格子清空，current_team跟着往回翻
+fn pop_undo_plies(grid: &mut ChessboardGrid, state_ref: &mut State, plies: usize) {
+    for _ in 0..plies {
+        match state_ref.history.pop() {
+            Some(pos) => {
+                state_ref.move_times.pop();
+                if let Some(cell) = grid.at_mut(pos.0, pos.1) {
+                    cell.chess = None;
+                }
+                state_ref.current_team.set_opposite();
+            },
+            None => break,
+        }
+    }
+}
+
+// 往聊天记录里追加一行，顺带滚到底部
+// 聊天记录最多保留这么多行，免得对局时间长了缓冲区占用的内存和滚动条长度一直涨
+const CHAT_BACKLOG_LINES: i32 = 50;
+
+fn append_chat_line(view: &gtk::TextView, line: &str) {
+    let buf = view.buffer();
+    let mut end = buf.end_iter();
+
+    if end.offset() > 0 {
+        buf.insert(&mut end, "\n");
+    }
+    buf.insert(&mut end, line);
+
+    if buf.line_count() > CHAT_BACKLOG_LINES {
+        let mut start = buf.start_iter();
+        let mut cutoff = buf.iter_at_line(buf.line_count() - CHAT_BACKLOG_LINES).unwrap_or_else(|| buf.start_iter());
+        buf.delete(&mut start, &mut cutoff);
+    }
+
+    view.scroll_to_iter(&mut buf.end_iter(), 0.0, false, 0.0, 0.0);
+}
+
 
 
 #[derive(Clone, Copy, PartialEq, Eq, Default)]
@@ -1890,6 +3400,7 @@ struct Cell {
 
 
 
+#[derive(Clone)]
 struct ChessboardGrid {
     m_vec: Vec<Cell>,
 }
@@ -2036,12 +3547,76 @@ impl Default for ChessboardGrid {
 
 
 
+/// 提取穿过`(x, y)`、沿`dir`方向的整条线(横/纵/两条斜线之一)，`O`表示`color`的棋子，`+`表示空位，`x`表示对方的棋子(出界同样视为`x`，即挡死)
+fn scan_line(grid: &ChessboardGrid, x: isize, y: isize, dir: (isize, isize), color: Team)-> Vec<char> {
+    let (dx, dy) = dir;
+
+    let mut sx = x;
+    let mut sy = y;
+    while grid.at(sx - dx, sy - dy).is_some() {
+        sx -= dx;
+        sy -= dy;
+    }
+
+    let mut line = Vec::new();
+    let (mut cx, mut cy) = (sx, sy);
+    while let Some(cell) = grid.at(cx, cy) {
+        line.push(match cell.chess {
+            Some(c) if c == color => 'O',
+            Some(_) => 'x',
+            None => '+',
+        });
+        cx += dx;
+        cy += dy;
+    }
+
+    line
+}
+
+/// 经典的五子棋棋型打分表，窗口在`line`里滑动匹配，每命中一次就累加对应的分值
+fn score_line(line: &[char])-> i64 {
+    const PATTERNS: &[(&str, i64)] = &[
+        ("OOOOO", 50000),
+        ("+OOOO+", 4320),
+        ("OOOO+", 1000),
+        ("+OOOO", 1000),
+        ("+OOO++", 720),
+        ("++OOO+", 720),
+        ("+OO+O+", 120),
+        ("+O+OO+", 120),
+        ("++OO++", 20),
+    ];
+
+    let mut score = 0;
+    for &(pattern, weight) in PATTERNS {
+        let plen = pattern.len();
+        if line.len() < plen {
+            continue;
+        }
+
+        for start in 0..=(line.len() - plen) {
+            if line[start..(start + plen)].iter().copied().eq(pattern.chars()) {
+                score += weight;
+            }
+        }
+    }
+
+    score
+}
+
 #[derive(Default)]
 struct State {
     pub current_team: Team,
     pub history: Vec<(isize, isize)>,
+    // 跟history一一对应，每步棋落下的时刻，存棋谱的时候会一起写进去
+    pub move_times: Vec<SystemTime>,
     pub mode: Mode,
     pub frozen: bool,
+
+    // 棋钟，只有联机对局才会用到，单位毫秒；房主权威，其他人靠TimeSync同步
+    pub remaining_ms_owner: u64,
+    pub remaining_ms_player: u64,
+    pub increment_ms: u64,
 }
 
 #[derive(Default)]
@@ -2049,17 +3624,37 @@ enum Mode {
     #[default]
     Singleplayer,
 
+    SinglePlayerVsAi {
+        my_team: Team,
+        difficulty: ai::Difficulty,
+    },
+
     MultiplePlayer {
         peer_name: String,
+        own_name: String,
         my_team: Team,
     },
+
+    /// 观战模式，do_click对这个模式什么都不做，棋盘完全靠BoardSnapshot和对局事件的转发来更新
+    Spectating {
+        peer_name: String,
+    },
+
+    /// 棋谱回放，do_click也是什么都不做，全靠回放面板的按钮挪cursor
+    Replaying {
+        moves: Vec<(u8, u8)>,
+        cursor: usize,
+    },
 }
 
 impl Mode {
     pub fn is_single_player(&self)-> bool {
         match self {
             &Self::Singleplayer => true,
+            &Self::SinglePlayerVsAi {..} => false,
             &Self::MultiplePlayer {..} => false,
+            &Self::Spectating {..} => false,
+            &Self::Replaying {..} => true,
         }
     }
 
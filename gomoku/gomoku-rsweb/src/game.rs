@@ -1,12 +1,14 @@
 use std::vec::Vec;
 use std::ops::*;
 use std::mem::swap;
+use std::fmt;
 use std::time::{Duration, Instant};
 use std::sync::Mutex;
 
 use uuid::Uuid;
+use serde::{ Serialize, Deserialize };
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum Cell {
     Empty,
     Black,
@@ -25,54 +27,349 @@ impl Cell {
     pub fn reverse(&mut self) {
         *self = self.get_reverse();
     }
+
+    fn to_bits(&self)-> u64 {
+        match *self {
+            Cell::Empty => 0b00,
+            Cell::Black => 0b01,
+            Cell::White => 0b10,
+        }
+    }
+
+    fn from_bits(bits: u64)-> Self {
+        match bits {
+            0b01 => Cell::Black,
+            0b10 => Cell::White,
+            _ => Cell::Empty,
+        }
+    }
 }
 
+/// `Grid::is_forbidden`扫描窗口时用的格子分类：黑子、空着的格子、堵死的格子(白子或者出界)
+#[derive(Clone, Copy, PartialEq)]
+enum Slot {
+    Black,
+    Open,
+    Blocked,
+}
 
+/// 每格只占2 bit(Empty=00, Black=01, White=10)，打包存进`Vec<u64>`，
+/// 省下`GameSession`在内存里常驻时的大头开销
+const BITS_PER_CELL: usize = 2;
+const CELLS_PER_WORD: usize = u64::BITS as usize / BITS_PER_CELL;
 
+#[derive(Serialize, Deserialize)]
 pub struct Grid {
     m_size: (usize, usize),
-    m_vec: Vec<Cell>,
+    m_vec: Vec<u64>,
 }
 
 impl Grid {
     pub fn new(size: (usize, usize))-> Self {
-        let vec_len = size.0 * size.1;
         Self {
             m_size: size.clone(),
-            m_vec: vec![Cell::Empty; vec_len],
+            m_vec: vec![0u64; Self::word_count(size.0 * size.1)],
         }
     }
 
+    fn word_count(cell_count: usize)-> usize {
+        (cell_count + CELLS_PER_WORD - 1) / CELLS_PER_WORD
+    }
+
     pub fn resize(&mut self, size: (usize, usize)) {
-        self.m_vec.clone_from(&vec![Cell::Empty; size.0 * size.1]);
+        self.m_vec.clone_from(&vec![0u64; Self::word_count(size.0 * size.1)]);
     }
 
-    pub fn get(&self, pos: (usize, usize))-> Result<&Cell, String> {
+    pub fn get(&self, pos: (usize, usize))-> Result<Cell, String> {
+        let index = self.index_of(pos)?;
+        let shift = (index % CELLS_PER_WORD) * BITS_PER_CELL;
+        let bits = (self.m_vec[index / CELLS_PER_WORD] >> shift) & 0b11;
+        Ok(Cell::from_bits(bits))
+    }
+
+    pub fn set(&mut self, pos: (usize, usize), cell: Cell)-> Result<(), String> {
+        let index = self.index_of(pos)?;
+        let shift = (index % CELLS_PER_WORD) * BITS_PER_CELL;
+        let mask = 0b11u64 << shift;
+        let word = &mut self.m_vec[index / CELLS_PER_WORD];
+        *word = (*word & !mask) | (cell.to_bits() << shift);
+        Ok(())
+    }
+
+    fn index_of(&self, pos: (usize, usize))-> Result<usize, String> {
         if pos.0 >= self.m_size.0 || pos.1 >= self.m_size.1 {
             Err(format!("Position ({},{}) is out of range", pos.0, pos.1))
         } else {
-            Ok(self.m_vec.index(pos.1 * self.m_size.0 + pos.0))
+            Ok(pos.1 * self.m_size.0 + pos.0)
         }
     }
 
-    pub fn get_mut(&mut self, pos: (usize, usize))-> Result<&mut Cell, String> {
-        if pos.0 >= self.m_size.0 || pos.1 >= self.m_size.1 {
-            Err(format!("Position ({},{}) is out of range", pos.0, pos.1))
-        } else {
-            Ok(self.m_vec.index_mut(pos.1 * self.m_size.0 + pos.0))
+    /// 按行优先顺序拍平整个棋盘，供Websocket协议把局面序列化成`StateUpdate`发给客户端
+    pub fn as_flat(&self)-> Vec<Cell> {
+        (0..self.m_size.0 * self.m_size.1)
+            .map(|index| {
+                let shift = (index % CELLS_PER_WORD) * BITS_PER_CELL;
+                let bits = (self.m_vec[index / CELLS_PER_WORD] >> shift) & 0b11;
+                Cell::from_bits(bits)
+            })
+            .collect()
+    }
+
+    pub fn get_size(&self)-> (usize, usize) {
+        self.m_size
+    }
+
+    /// 给定最后落子的`pos`，判断那一步的颜色是否已经连成五子(或更多)；`pos`是空格直接返回`None`
+    /// 沿横、竖、两条斜线四个方向分别往正负两边走，数连续同色格子，加上`pos`自己凑够5就算赢
+    pub fn check_win(&self, pos: (usize, usize))-> Option<Cell> {
+        let color = match self.get(pos) {
+            Ok(cell) if cell != Cell::Empty => cell,
+            _ => return None,
+        };
+
+        const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+        for &dir in DIRECTIONS.iter() {
+            let count = 1 + self.count_run(pos, dir, &color) + self.count_run(pos, (-dir.0, -dir.1), &color);
+            if count >= 5 {
+                return Some(color);
+            }
         }
+
+        None
+    }
+
+    /// 从`pos`沿`dir`方向数连续同色格子数(不含`pos`自己)，出界或者颜色不一样就停下
+    fn count_run(&self, pos: (usize, usize), dir: (isize, isize), color: &Cell)-> usize {
+        let mut count = 0;
+        let mut x = pos.0 as isize;
+        let mut y = pos.1 as isize;
+
+        loop {
+            x += dir.0;
+            y += dir.1;
+
+            if x < 0 || y < 0 {
+                break;
+            }
+
+            match self.get((x as usize, y as usize)) {
+                Ok(cell) if cell == *color => count += 1,
+                _ => break,
+            }
+        }
+
+        count
     }
 
+    /// 按行解析文本棋盘(`.`=空 `X`=黑 `O`=白)，行数超过`height`或某一行长度超过`width`都算格式错误；
+    /// 给日志/回归测试里的已知局面、预设开局用的，不走serde那套
+    pub fn from_str(width: usize, height: usize, s: &str)-> Result<Self, String> {
+        let lines: Vec<&str> = s.lines().collect();
+        if lines.len() > height {
+            return Err(format!("Expected at most {} lines, got {}", height, lines.len()));
+        }
+
+        let mut grid = Self::new((width, height));
+        for (y, line) in lines.iter().enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.len() > width {
+                return Err(format!("Line {} has {} characters, expected at most {}", y, chars.len(), width));
+            }
+
+            for (x, ch) in chars.iter().enumerate() {
+                let cell = match ch {
+                    '.' => Cell::Empty,
+                    'X' => Cell::Black,
+                    'O' => Cell::White,
+                    _ => return Err(format!("Unrecognized character '{}' at ({},{})", ch, x, y)),
+                };
+                grid.set((x, y), cell)?;
+            }
+        }
+
+        Ok(grid)
+    }
+
+    /// Renju规则下，判断假如在`pos`落下`color`算不算"禁手"；只对黑棋生效，白棋永远不会被禁
+    ///
+    /// 三大类禁手，命中任意一类就算禁：
+    ///   - 长连(overline)：这步棋让某条线上连续黑子数达到6颗或以上
+    ///   - 双三(double-three)：这步棋同时做出两条或以上的"活三"
+    ///   - 双四(double-four)：这步棋同时做出两条或以上的"四"(活四、冲四都算)
+    ///
+    /// 判定用的是以`pos`为中心、沿每条轴线展开的9格窗口(`pos`前后各4格，出界当成被堵死)：
+    ///   - 活三窗口：窗口里任意连续5格呈`_BBB_`(两侧都是空格)就算一条活三
+    ///   - 四的窗口：窗口里任意连续5格呈`_BBBB`或`BBBB_`(至少一侧是空格)就算一条四
+    /// 长连单独用连续同色计数判断，不受9格窗口半径限制
+    pub fn is_forbidden(&self, pos: (usize, usize), color: Cell)-> bool {
+        if color != Cell::Black {
+            return false;
+        }
+        if !matches!(self.get(pos), Ok(Cell::Empty)) {
+            return false;
+        }
+
+        const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+        let mut three_count = 0;
+        let mut four_count = 0;
+
+        for &dir in DIRECTIONS.iter() {
+            let forward = self.count_run_hypothetical(pos, dir, pos);
+            let backward = self.count_run_hypothetical(pos, (-dir.0, -dir.1), pos);
+            if 1 + forward + backward >= 6 {
+                return true;
+            }
+
+            let window: Vec<Slot> = (-4..=4isize)
+                .map(|offset| self.slot_at_offset(pos, dir, offset, pos))
+                .collect();
+
+            let has_three = window.windows(5)
+                .any(|w| matches!(w, [Slot::Open, Slot::Black, Slot::Black, Slot::Black, Slot::Open]));
+            let has_four = window.windows(5)
+                .any(|w| matches!(w,
+                    [Slot::Open, Slot::Black, Slot::Black, Slot::Black, Slot::Black]
+                    | [Slot::Black, Slot::Black, Slot::Black, Slot::Black, Slot::Open]
+                ));
+
+            if has_three {
+                three_count += 1;
+            }
+            if has_four {
+                four_count += 1;
+            }
+        }
+
+        three_count >= 2 || four_count >= 2
+    }
+
+    /// 从`pos`沿`dir`方向数连续黑子数(不含`pos`自己)，假设`hypo_pos`处是黑子(`pos`本身多半是空格，
+    /// 还没真的落子，这里只是假设落下去之后的样子)，出界或者不是黑子就停下
+    fn count_run_hypothetical(&self, pos: (usize, usize), dir: (isize, isize), hypo_pos: (usize, usize))-> usize {
+        let mut count = 0;
+        let mut x = pos.0 as isize;
+        let mut y = pos.1 as isize;
+
+        loop {
+            x += dir.0;
+            y += dir.1;
+
+            if x < 0 || y < 0 {
+                break;
+            }
+
+            let cur = (x as usize, y as usize);
+            let is_black = if cur == hypo_pos {
+                true
+            } else {
+                matches!(self.get(cur), Ok(Cell::Black))
+            };
+
+            if is_black {
+                count += 1;
+            } else {
+                break;
+            }
+        }
+
+        count
+    }
+
+    /// `origin`沿`dir`方向走`offset`步落到的格子是什么：`hypo_pos`处当成黑子，出界或白子当成堵死
+    fn slot_at_offset(&self, origin: (usize, usize), dir: (isize, isize), offset: isize, hypo_pos: (usize, usize))-> Slot {
+        let x = origin.0 as isize + dir.0 * offset;
+        let y = origin.1 as isize + dir.1 * offset;
+
+        if x < 0 || y < 0 {
+            return Slot::Blocked;
+        }
+
+        let pos = (x as usize, y as usize);
+        if pos == hypo_pos {
+            return Slot::Black;
+        }
+
+        match self.get(pos) {
+            Ok(Cell::Black) => Slot::Black,
+            Ok(Cell::Empty) => Slot::Open,
+            Ok(Cell::White) | Err(_) => Slot::Blocked,
+        }
+    }
+
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter)-> fmt::Result {
+        for y in 0..self.m_size.1 {
+            for x in 0..self.m_size.0 {
+                let ch = match self.get((x, y)) {
+                    Ok(Cell::Empty) => '.',
+                    Ok(Cell::Black) => 'X',
+                    Ok(Cell::White) => 'O',
+                    Err(_) => '.',
+                };
+                write!(f, "{}", ch)?;
+            }
+            if y + 1 < self.m_size.1 {
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+
+/// `Instant`没有固定的纪元，没办法有意义地序列化/反序列化
+/// 所以`m_last_activated`不进快照，重新加载的Session一律当作刚活跃过，交给GC daemon重新计时
+fn fresh_activation()-> Mutex<Instant> {
+    Mutex::new(Instant::now())
 }
 
+/// 给`Mutex<T>`字段配的serde适配器：序列化的时候锁一下拿出内部值直接序列化，
+/// 反序列化就正常解出`T`再套一层新的`Mutex`，不把锁本身的状态当成数据处理
+mod mutex_serde {
+    use std::sync::Mutex;
+    use serde::{ Serialize, Serializer, Deserialize, Deserializer };
+
+    pub fn serialize<S, T>(val: &Mutex<T>, ser: S)-> Result<S::Ok, S::Error>
+    where S: Serializer, T: Serialize {
+        val.lock().unwrap().serialize(ser)
+    }
 
+    pub fn deserialize<'de, D, T>(de: D)-> Result<Mutex<T>, D::Error>
+    where D: Deserializer<'de>, T: Deserialize<'de> {
+        T::deserialize(de).map(Mutex::new)
+    }
+}
 
+#[derive(Serialize, Deserialize)]
 pub struct GameSession {
     m_game_uuid: Uuid,
     m_black_uuid: Uuid,
     m_white_uuid: Uuid,
-    m_grid: Grid,
+
+    /// 棋盘和轮次各自套一把`Mutex`，好让`place`能用`&self`签名调用
+    #[serde(with = "mutex_serde")]
+    m_grid: Mutex<Grid>,
+
+    #[serde(skip, default = "fresh_activation")]
     m_last_activated: Mutex<Instant>,
+
+    /// 轮到哪一方落子，黑方先行
+    #[serde(with = "mutex_serde")]
+    m_current_turn: Mutex<Cell>,
+
+    /// 按落子顺序追加的历史记录，`undo`从尾部弹出，`redo`再推回去
+    #[serde(with = "mutex_serde")]
+    m_move_history: Mutex<Vec<((usize, usize), Cell)>>,
+
+    /// 被`undo`弹出、还没被新落子作废的记录，`redo`从这里弹出重放
+    #[serde(with = "mutex_serde")]
+    m_redo_stack: Mutex<Vec<((usize, usize), Cell)>>,
 }
 
 impl GameSession {
@@ -81,8 +378,11 @@ impl GameSession {
             m_game_uuid: Uuid::new_v4(),
             m_black_uuid: Uuid::new_v4(),
             m_white_uuid: Uuid::new_v4(),
-            m_grid: Grid::new((15, 15)),
+            m_grid: Mutex::new(Grid::new((15, 15))),
             m_last_activated: Mutex::new(Instant::now()),
+            m_current_turn: Mutex::new(Cell::Black),
+            m_move_history: Mutex::new(Vec::new()),
+            m_redo_stack: Mutex::new(Vec::new()),
         }
     }
 
@@ -111,5 +411,257 @@ impl GameSession {
         let elapsed = self.get_last_activated().elapsed();
         elapsed >= Duration::from_secs(600)
     }
+
+    /// 校验`player`是这局的黑方或白方、轮到TA走、目标格子合法且为空、不是黑方禁手，都通过才真正落子并轮转；
+    /// 这是Websocket协议里`Turn`消息唯一的落子入口
+    pub fn place(&self, player: Uuid, pos: (usize, usize))-> Result<(), String> {
+        let color = if player == self.m_black_uuid {
+            Cell::Black
+        } else if player == self.m_white_uuid {
+            Cell::White
+        } else {
+            return Err("Player is not part of this session".to_string());
+        };
+
+        let mut grid = self.m_grid.lock().unwrap();
+        let mut turn = self.m_current_turn.lock().unwrap();
+
+        if *turn != color {
+            return Err("It is not this player's turn".to_string());
+        }
+
+        if grid.get(pos)? != Cell::Empty {
+            return Err(format!("Position ({},{}) is already occupied", pos.0, pos.1));
+        }
+
+        if grid.is_forbidden(pos, color.clone()) {
+            return Err(format!("Position ({},{}) is a forbidden move for black (overline/double-three/double-four)", pos.0, pos.1));
+        }
+
+        grid.set(pos, color.clone())?;
+        *turn = turn.get_reverse();
+
+        self.m_move_history.lock().unwrap().push((pos, color));
+        self.m_redo_stack.lock().unwrap().clear();
+
+        drop(grid);
+        drop(turn);
+        self.activate();
+
+        Ok(())
+    }
+
+    /// 查询在`pos`落子之后局面是否已经分出胜负，委托给`Grid::check_win`
+    pub fn check_win(&self, pos: (usize, usize))-> Option<Cell> {
+        self.m_grid.lock().unwrap().check_win(pos)
+    }
+
+    /// 悔棋：把历史记录最后一步弹出来、格子清空、轮次还给那一步的落子方，推进`redo`栈备用
+    /// `player`必须是这局里的一方；历史是空的就报错
+    ///
+    /// 棋盘本身用的是`Grid`的位打包存储，单格的`get`/`set`都是O(1)，
+    /// 悔棋不需要克隆整个棋盘去算"悔棋后的局面"，所以这里没有再另外维护一套
+    /// "committed/scratch"双缓冲——那套设计是给整盘克隆开销大的存储准备的，用不上
+    pub fn undo(&self, player: Uuid)-> Result<(), String> {
+        if player != self.m_black_uuid && player != self.m_white_uuid {
+            return Err("Player is not part of this session".to_string());
+        }
+
+        let mut history = self.m_move_history.lock().unwrap();
+        let (pos, placed) = history.pop().ok_or_else(|| "No move to undo".to_string())?;
+
+        let mut grid = self.m_grid.lock().unwrap();
+        let mut turn = self.m_current_turn.lock().unwrap();
+
+        grid.set(pos, Cell::Empty)?;
+        *turn = placed.clone();
+
+        self.m_redo_stack.lock().unwrap().push((pos, placed));
+
+        drop(grid);
+        drop(turn);
+        drop(history);
+        self.activate();
+
+        Ok(())
+    }
+
+    /// 重做：把`redo`栈顶的那步重新落回棋盘，轮次交给对手，记录挪回历史
+    pub fn redo(&self, player: Uuid)-> Result<(), String> {
+        if player != self.m_black_uuid && player != self.m_white_uuid {
+            return Err("Player is not part of this session".to_string());
+        }
+
+        let mut redo_stack = self.m_redo_stack.lock().unwrap();
+        let (pos, placed) = redo_stack.pop().ok_or_else(|| "No move to redo".to_string())?;
+
+        let mut grid = self.m_grid.lock().unwrap();
+        let mut turn = self.m_current_turn.lock().unwrap();
+
+        grid.set(pos, placed.clone())?;
+        *turn = placed.get_reverse();
+
+        self.m_move_history.lock().unwrap().push((pos, placed));
+
+        drop(grid);
+        drop(turn);
+        drop(redo_stack);
+        self.activate();
+
+        Ok(())
+    }
+
+    /// 按落子顺序排列的历史记录，供对局回放/观众追赶进度用
+    pub fn move_history(&self)-> Vec<((usize, usize), Cell)> {
+        self.m_move_history.lock().unwrap().clone()
+    }
+
+    /// 按行优先顺序拍平的棋盘局面
+    pub fn snapshot(&self)-> Vec<Cell> {
+        self.m_grid.lock().unwrap().as_flat()
+    }
+
+    pub fn get_map_size(&self)-> (usize, usize) {
+        self.m_grid.lock().unwrap().get_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_get_set_round_trips_across_word_boundary() {
+        // CELLS_PER_WORD是32，故意选跨越第一个u64字边界(索引31/32)的格子
+        let mut grid = Grid::new((40, 1));
+
+        grid.set((31, 0), Cell::Black).unwrap();
+        grid.set((32, 0), Cell::White).unwrap();
+        grid.set((33, 0), Cell::Black).unwrap();
+
+        assert_eq!(grid.get((30, 0)).unwrap(), Cell::Empty);
+        assert_eq!(grid.get((31, 0)).unwrap(), Cell::Black);
+        assert_eq!(grid.get((32, 0)).unwrap(), Cell::White);
+        assert_eq!(grid.get((33, 0)).unwrap(), Cell::Black);
+        assert_eq!(grid.get((34, 0)).unwrap(), Cell::Empty);
+    }
+
+    #[test]
+    fn grid_get_set_out_of_range_errors() {
+        let mut grid = Grid::new((15, 15));
+        assert!(grid.get((15, 0)).is_err());
+        assert!(grid.set((0, 15), Cell::Black).is_err());
+    }
+
+    #[test]
+    fn grid_check_win_detects_five_in_a_row() {
+        let mut grid = Grid::new((15, 15));
+        for x in 0..4 {
+            grid.set((x, 7), Cell::Black).unwrap();
+        }
+        assert_eq!(grid.check_win((3, 7)), None);
+
+        grid.set((4, 7), Cell::Black).unwrap();
+        assert_eq!(grid.check_win((4, 7)), Some(Cell::Black));
+    }
+
+    #[test]
+    fn grid_check_win_ignores_empty_cell() {
+        let grid = Grid::new((15, 15));
+        assert_eq!(grid.check_win((7, 7)), None);
+    }
+
+    #[test]
+    fn grid_is_forbidden_detects_overline() {
+        let mut grid = Grid::new((15, 15));
+        for x in 0..5 {
+            grid.set((x, 7), Cell::Black).unwrap();
+        }
+        // 在(5,7)落子会让这条线连到6颗黑子，长连禁手
+        assert!(grid.is_forbidden((5, 7), Cell::Black));
+    }
+
+    #[test]
+    fn grid_is_forbidden_detects_double_three() {
+        let mut grid = Grid::new((15, 15));
+        // 横向：(4,5)(6,5)已经是黑子，(5,5)落子后形成`.BBB.`
+        grid.set((4, 5), Cell::Black).unwrap();
+        grid.set((6, 5), Cell::Black).unwrap();
+        // 纵向：(5,4)(5,6)已经是黑子，(5,5)落子后同样形成`.BBB.`
+        grid.set((5, 4), Cell::Black).unwrap();
+        grid.set((5, 6), Cell::Black).unwrap();
+
+        assert!(grid.is_forbidden((5, 5), Cell::Black));
+    }
+
+    #[test]
+    fn grid_is_forbidden_allows_single_open_three() {
+        let mut grid = Grid::new((15, 15));
+        grid.set((4, 5), Cell::Black).unwrap();
+        grid.set((6, 5), Cell::Black).unwrap();
+
+        assert!(!grid.is_forbidden((5, 5), Cell::Black));
+    }
+
+    #[test]
+    fn grid_is_forbidden_never_applies_to_white() {
+        let mut grid = Grid::new((15, 15));
+        for x in 0..5 {
+            grid.set((x, 7), Cell::White).unwrap();
+        }
+        assert!(!grid.is_forbidden((5, 7), Cell::White));
+    }
+
+    #[test]
+    fn game_session_place_enforces_identity_and_turn_order() {
+        let session = GameSession::new();
+        let black = session.get_black_uuid();
+        let white = session.get_white_uuid();
+
+        assert!(session.place(white, (7, 7)).is_err());
+        assert!(session.place(Uuid::new_v4(), (7, 7)).is_err());
+
+        assert!(session.place(black, (7, 7)).is_ok());
+        assert_eq!(session.snapshot()[7 * 15 + 7], Cell::Black);
+
+        assert!(session.place(black, (8, 8)).is_err());
+        assert!(session.place(white, (8, 8)).is_ok());
+    }
+
+    #[test]
+    fn game_session_place_rejects_forbidden_black_move() {
+        let session = GameSession::new();
+        let black = session.get_black_uuid();
+        let white = session.get_white_uuid();
+
+        session.place(black, (4, 5)).unwrap();
+        session.place(white, (0, 0)).unwrap();
+        session.place(black, (6, 5)).unwrap();
+        session.place(white, (0, 1)).unwrap();
+        session.place(black, (5, 4)).unwrap();
+        session.place(white, (0, 2)).unwrap();
+        session.place(black, (5, 6)).unwrap();
+        session.place(white, (0, 3)).unwrap();
+
+        // 此时(4,5)(6,5)(5,4)(5,6)都是黑子，(5,5)落下去会同时补成横、纵两条活三
+        assert!(session.place(black, (5, 5)).is_err());
+    }
+
+    #[test]
+    fn game_session_undo_redo_round_trips() {
+        let session = GameSession::new();
+        let black = session.get_black_uuid();
+
+        session.place(black, (7, 7)).unwrap();
+        assert_eq!(session.snapshot()[7 * 15 + 7], Cell::Black);
+
+        session.undo(black).unwrap();
+        assert_eq!(session.snapshot()[7 * 15 + 7], Cell::Empty);
+        assert!(session.place(session.get_white_uuid(), (0, 0)).is_err()); // 撤销后轮次还给黑方，不是白方
+
+        session.redo(black).unwrap();
+        assert_eq!(session.snapshot()[7 * 15 + 7], Cell::Black);
+        assert_eq!(session.move_history(), vec![((7, 7), Cell::Black)]);
+    }
 }
 
@@ -9,6 +9,7 @@ use actix_web::{
 mod webservice;
 use webservice::*;
 mod game;
+mod persistence;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
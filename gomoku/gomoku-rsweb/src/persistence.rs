@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+use rand::RngCore;
+use aes_gcm_siv::{
+    Aes256GcmSiv,
+    Nonce,
+    aead::{ Aead, KeyInit },
+};
+use uuid::Uuid;
+
+use crate::game::GameSession;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HKDF_INFO: &[u8] = b"simplegames-session-store";
+
+/// 从服务器密钥和盐派生出封存Session快照用的256位密钥
+fn derive_key(secret: &[u8], salt: &[u8; SALT_LEN])-> [u8; 32] {
+    let hk = Hkdf::<Sha256>::new(Some(salt), secret);
+    let mut key = [0u8; 32];
+    hk.expand(HKDF_INFO, &mut key).expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// 读取派生密钥用的随机盐；文件不存在时生成一份新的并落盘，之后重启都复用同一份盐
+fn load_or_create_salt(path: &Path)-> [u8; SALT_LEN] {
+    if let Ok(bytes) = fs::read(path) {
+        if bytes.len() == SALT_LEN {
+            let mut salt = [0u8; SALT_LEN];
+            salt.copy_from_slice(&bytes);
+            return salt;
+        }
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    if let Err(err) = fs::write(path, &salt) {
+        println!("Failed to persist session store salt to {:?}: {}", path, err);
+    }
+    salt
+}
+
+/// 负责把`SessionManager`的Session表加密落盘、以及启动时解密加载回来
+///
+/// 快照文件的格式是`nonce(12字节) || 密文`，密文用AES-256-GCM-SIV封存，
+/// 密钥由服务器密钥通过HKDF-SHA256派生，盐是落盘的随机16字节值。
+pub struct SessionStore {
+    m_key: [u8; 32],
+}
+
+impl SessionStore {
+    pub fn new(secret: &[u8], salt_path: &Path)-> Self {
+        let salt = load_or_create_salt(salt_path);
+        Self { m_key: derive_key(secret, &salt) }
+    }
+
+    /// 序列化、加密`sessions`后写入`path`；每次都用一个新的随机nonce
+    pub fn save(&self, path: &Path, sessions: &HashMap<Uuid, GameSession>) {
+        let plaintext = match serde_json::to_vec(sessions) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Failed to serialize sessions for snapshot: {}", err);
+                return;
+            },
+        };
+
+        let cipher = Aes256GcmSiv::new_from_slice(&self.m_key).expect("key is always 32 bytes");
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = Nonce::from_slice(&nonce_bytes);
+
+        let ciphertext = match cipher.encrypt(nonce, plaintext.as_ref()) {
+            Ok(ciphertext) => ciphertext,
+            Err(err) => {
+                println!("Failed to seal session snapshot: {}", err);
+                return;
+            },
+        };
+
+        let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(&nonce_bytes);
+        out.extend_from_slice(&ciphertext);
+
+        if let Err(err) = fs::write(path, out) {
+            println!("Failed to write session snapshot to {:?}: {}", path, err);
+        }
+    }
+
+    /// 读取、解密、反序列化`path`里的快照
+    ///
+    /// 文件不存在或读不出来时直接返回一张空表，不记日志；密文太短、解密/认证失败、反序列化失败，
+    /// 这几种情况只记录日志然后返回一张空表，让服务器当成没有快照那样正常启动，而不是panic。
+    pub fn load(&self, path: &Path)-> HashMap<Uuid, GameSession> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(_) => return HashMap::new(),
+        };
+
+        if data.len() < NONCE_LEN {
+            println!("Session snapshot at {:?} is too short to contain a nonce, starting fresh", path);
+            return HashMap::new();
+        }
+        let (nonce_bytes, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::from_slice(nonce_bytes);
+
+        let cipher = Aes256GcmSiv::new_from_slice(&self.m_key).expect("key is always 32 bytes");
+        let plaintext = match cipher.decrypt(nonce, ciphertext) {
+            Ok(plaintext) => plaintext,
+            Err(_) => {
+                println!("Failed to decrypt session snapshot at {:?} (wrong key or corrupted file), starting fresh", path);
+                return HashMap::new();
+            },
+        };
+
+        match serde_json::from_slice(&plaintext) {
+            Ok(sessions) => sessions,
+            Err(err) => {
+                println!("Failed to deserialize session snapshot: {}, starting fresh", err);
+                HashMap::new()
+            },
+        }
+    }
+}
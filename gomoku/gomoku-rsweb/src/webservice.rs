@@ -1,11 +1,11 @@
 use crate::game::*;
 
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
 use std::fs::File;
 use std::collections::HashMap;
 use std::ops::*;
 use std::thread;
-use std::time::Duration;
+use std::time::{ Duration, Instant };
 use std::mem::swap;
 use std::sync::{
     Arc,
@@ -19,7 +19,8 @@ use std::sync::{
 
 use uuid::Uuid;
 use uuid::fmt::*;
-use serde::Serialize;
+use crate::persistence::SessionStore;
+use serde::{ Serialize, Deserialize };
 use actix::{
     Actor,
     StreamHandler,
@@ -36,13 +37,205 @@ use actix_web_actors::ws;
 
 
 
+/// 客户端→服务端的消息协议，`op`/`data`对应序列化后JSON的标签字段和载荷字段
+#[derive(Deserialize)]
+#[serde(tag = "op", content = "data")]
+pub enum ClientMessage {
+    /// 加入某一局游戏
+    JoinSession { uuid: Uuid },
+
+    /// 在棋盘上落子，`player`是落子方自己的UUID，服务端据此校验身份和轮次
+    Turn { position: (usize, usize), player: Uuid },
+
+    /// 聊天消息
+    ChatMessage(String),
+
+    Ping(String),
+}
+
+/// 服务端→客户端的消息协议
+#[derive(Serialize, Clone)]
+#[serde(tag = "op", content = "data")]
+pub enum ServerMessage {
+    /// 落子后的最新局面，`score`是当前黑子/白子的数量
+    StateUpdate { map: Vec<Cell>, score: (u32, u32) },
+
+    /// 刚才那步棋让某一方连成五子(或更多)，对局结束
+    GameOver { winner: Cell },
+
+    PlayerJoined,
+
+    PlayerLeft,
+
+    /// 经过`sanitize_chat_text`过滤后广播给Session内所有连接的聊天消息
+    Chat(String),
+
+    Pong(String),
+
+    Error { code: i32, msg: String },
+}
+
+
+
+/// 单条聊天消息过滤后允许的最大字符数
+const CHAT_MAX_LEN: usize = 280;
+
+/// 每个连接在`CHAT_RATE_WINDOW`内最多能发的聊天消息数
+const CHAT_RATE_LIMIT: u32 = 5;
+const CHAT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// 过滤掉控制字符和ANSI转义序列，只留Tab/换行、可打印ASCII(`' '..='~'`)和合法的多字节UTF-8字符
+/// 防止恶意客户端往服务端的`println!`日志或其它玩家的终端里注入转义序列
+fn sanitize_chat_text(input: &str)-> String {
+    input.chars().filter(|&c| {
+        c == '\t' || c == '\n' || (' '..='~').contains(&c) || (c as u32) > 0x9F
+    }).collect()
+}
+
+/// `SessionManager::broadcast`借助actix消息把`ServerMessage`推给`Addr<GameWs>`
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+struct Push(ServerMessage);
+
 /// 游戏Session连接的Websocket
 struct GameWs {
-    pub smanager: &mut SessionManager,
+    smanager: Arc<Mutex<SessionManager>>,
+
+    /// 这个连接当前加入的游戏Session
+    m_joined_session: Option<Uuid>,
+}
+
+impl GameWs {
+    fn reply(ctx: &mut ws::WebsocketContext<Self>, msg: ServerMessage) {
+        ctx.text(serde_json::to_string(&msg).unwrap());
+    }
+
+    /// 解析一条客户端消息并执行对应操作，直接通过`ctx`回复或广播
+    fn dispatch(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(err) => {
+                return Self::reply(ctx, ServerMessage::Error {
+                    code: 400,
+                    msg: format!("Malformed message: {}", err),
+                });
+            },
+        };
+
+        match message {
+            ClientMessage::JoinSession { uuid } => {
+                let manager = self.smanager.lock().unwrap();
+                if !manager.get_sessions().contains_key(&uuid) {
+                    return Self::reply(ctx, ServerMessage::Error { code: 404, msg: "Session not found".to_string() });
+                }
+
+                self.m_joined_session = Some(uuid);
+                if let Some(conn) = manager.get_connections().get_mut(&ctx.address()) {
+                    conn.m_associated_session = Some(uuid);
+                }
+                // 让这个连接自己也收到一份，借此复用同一条广播路径，不用再额外回一条PlayerJoined
+                manager.broadcast(uuid, ServerMessage::PlayerJoined);
+            },
+
+            ClientMessage::Turn { position, player } => {
+                let Some(uuid) = self.m_joined_session else {
+                    return Self::reply(ctx, ServerMessage::Error { code: 400, msg: "Not joined to a session".to_string() });
+                };
+
+                let manager = self.smanager.lock().unwrap();
+                let mut sessions = manager.get_sessions();
+                let Some(session) = sessions.get_mut(&uuid) else {
+                    return Self::reply(ctx, ServerMessage::Error { code: 404, msg: "Session not found".to_string() });
+                };
+
+                match session.place(player, position) {
+                    Ok(()) => {
+                        let map = session.snapshot();
+                        let score = map.iter().fold((0u32, 0u32), |acc, cell| match cell {
+                            Cell::Black => (acc.0 + 1, acc.1),
+                            Cell::White => (acc.0, acc.1 + 1),
+                            Cell::Empty => acc,
+                        });
+                        let winner = session.check_win(position);
+                        drop(sessions);
+                        manager.broadcast(uuid, ServerMessage::StateUpdate { map, score });
+                        if let Some(winner) = winner {
+                            manager.broadcast(uuid, ServerMessage::GameOver { winner });
+                        }
+                    },
+                    Err(msg) => {
+                        drop(sessions);
+                        Self::reply(ctx, ServerMessage::Error { code: 400, msg });
+                    },
+                }
+            },
+
+            ClientMessage::ChatMessage(text) => {
+                let Some(uuid) = self.m_joined_session else {
+                    return Self::reply(ctx, ServerMessage::Error { code: 400, msg: "Not joined to a session".to_string() });
+                };
+
+                let sanitized = sanitize_chat_text(&text);
+                if sanitized.is_empty() || sanitized.chars().count() > CHAT_MAX_LEN {
+                    return Self::reply(ctx, ServerMessage::Error {
+                        code: 400,
+                        msg: "Chat message rejected: empty after sanitization, or too long".to_string(),
+                    });
+                }
+
+                let manager = self.smanager.lock().unwrap();
+                let mut connections = manager.get_connections();
+                let Some(conn) = connections.get_mut(&ctx.address()) else {
+                    return Self::reply(ctx, ServerMessage::Error { code: 500, msg: "Connection not registered".to_string() });
+                };
+
+                if conn.m_chat_window_start.elapsed() >= CHAT_RATE_WINDOW {
+                    conn.m_chat_window_start = Instant::now();
+                    conn.m_chat_count = 0;
+                }
+                if conn.m_chat_count >= CHAT_RATE_LIMIT {
+                    drop(connections);
+                    return Self::reply(ctx, ServerMessage::Error { code: 429, msg: "Chat rate limit exceeded".to_string() });
+                }
+                conn.m_chat_count += 1;
+                drop(connections);
+
+                manager.broadcast(uuid, ServerMessage::Chat(sanitized));
+            },
+
+            ClientMessage::Ping(payload) => Self::reply(ctx, ServerMessage::Pong(payload)),
+        }
+    }
 }
 
 impl Actor for GameWs {
     type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let manager = self.smanager.lock().unwrap();
+        manager.get_connections().insert(ctx.address(), Connection {
+            m_associated_session: None,
+            m_chat_window_start: Instant::now(),
+            m_chat_count: 0,
+        });
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context)-> Running {
+        let manager = self.smanager.lock().unwrap();
+        let removed = manager.get_connections().remove(&ctx.address());
+        if let Some(session) = removed.and_then(|conn| conn.m_associated_session) {
+            manager.broadcast(session, ServerMessage::PlayerLeft);
+        }
+        Running::Stop
+    }
+}
+
+impl Handler<Push> for GameWs {
+    type Result = ();
+
+    fn handle(&mut self, msg: Push, ctx: &mut Self::Context) {
+        Self::reply(ctx, msg.0);
+    }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for GameWs {
@@ -50,29 +243,28 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for GameWs {
         match msg {
             Ok(ws::Message::Ping(ping)) => {
                 println!("Ping from {:?}", ctx.address());
-                return ctx.pong(&ping);
+                ctx.pong(&ping);
             },
             Ok(ws::Message::Pong(_)) => (),
             Ok(ws::Message::Text(text)) => {
-                println!("Text from {:?}", ctx.address());
-                return ctx.text(text);
+                self.dispatch(&text, ctx);
             },
             Ok(ws::Message::Binary(_)) => {
-                ctx.close(ws::CloseReason {
+                ctx.close(Some(ws::CloseReason {
                     code: ws::CloseCode::Protocol,
                     description: Some("Unexpected binary data".to_string()),
-                })
+                }));
             },
             Ok(ws::Message::Close(reason)) => {
-                let conns = self.smanager.get_connections();
-                if conns.contains(ctx.address()) {
-                }
+                println!("Connection closed: {:?}", reason);
+                ctx.close(reason);
             },
+            Ok(_) => (),
             Err(err) => {
-                ctx.close(ws::CloseReason {
+                ctx.close(Some(ws::CloseReason {
                     code: ws::CloseCode::Protocol,
-                    description: 
-                })
+                    description: Some(err.to_string()),
+                }));
             },
         }
     }
@@ -80,9 +272,25 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for GameWs {
 
 
 
-/// 描述一个连接
+/// 描述一个连接；连接建立时还没有加入任何Session，收到`JoinSession`后才会指向具体的Session
 pub struct Connection {
-    m_associated_session: Uuid,
+    m_associated_session: Option<Uuid>,
+
+    /// 聊天限速窗口的起始时间和这个窗口内已经发送的消息数
+    m_chat_window_start: Instant,
+    m_chat_count: u32,
+}
+
+/// 加密快照落盘的位置；和盐文件放在一起，重启后从这里读回Session表
+const SESSION_STORE_PATH: &str = "sessions.enc";
+const SESSION_SALT_PATH: &str = "sessions.salt";
+
+/// 派生快照加密密钥用的服务器密钥；线上部署应该通过环境变量覆盖这个默认值
+fn session_store_secret()-> String {
+    std::env::var("SESSION_STORE_SECRET").unwrap_or_else(|_| {
+        println!("SESSION_STORE_SECRET is not set, falling back to an insecure development secret");
+        "insecure-development-secret".to_string()
+    })
 }
 
 /// 负责管理游戏会话的结构体
@@ -95,6 +303,18 @@ pub struct Connection {
 /// 因此，无人游玩的Session最长存活时间在10~15分钟之间。
 ///
 /// 在结构体被Drop时Daemon会在5秒内自动退出。
+/// 加密快照落盘的位置；和盐文件放在一起，重启后从这里读回Session表
+const SESSION_STORE_PATH: &str = "sessions.enc";
+const SESSION_SALT_PATH: &str = "sessions.salt";
+
+/// 派生快照加密密钥用的服务器密钥；线上部署应该通过环境变量覆盖这个默认值
+fn session_store_secret()-> String {
+    std::env::var("SESSION_STORE_SECRET").unwrap_or_else(|_| {
+        println!("SESSION_STORE_SECRET is not set, falling back to an insecure development secret");
+        "insecure-development-secret".to_string()
+    })
+}
+
 pub struct SessionManager {
     /// 存储所有游戏
     m_sessions: Arc<Mutex<HashMap<Uuid, GameSession>>>,
@@ -107,13 +327,29 @@ pub struct SessionManager {
 
     /// 控制Daemon运行的变量
     m_condition: Arc<AtomicBool>,
+
+    /// 负责加密落盘/加载Session表
+    m_store: Arc<SessionStore>,
+
+    /// 加密快照的落盘路径
+    m_store_path: PathBuf,
 }
 
 impl SessionManager {
     pub fn new()-> Self {
-        let sessions = Arc::new(Mutex::new(HashMap::<Uuid, GameSession>::with_capacity(15)));
+        let store_path = PathBuf::from(SESSION_STORE_PATH);
+        let store = Arc::new(SessionStore::new(
+            session_store_secret().as_bytes(),
+            Path::new(SESSION_SALT_PATH),
+        ));
+
+        let sessions = Arc::new(Mutex::new(store.load(&store_path)));
+        let connections = Arc::new(Mutex::new(HashMap::<Addr<GameWs>, Connection>::new()));
         let cond = Arc::new(AtomicBool::new(true));
         let sessions_d = Arc::clone(&sessions);
+        let connections_d = Arc::clone(&connections);
+        let store_d = Arc::clone(&store);
+        let store_path_d = store_path.clone();
         let c = Arc::clone(&cond);
         let d = thread::Builder::new()
             .name("SessionGCDaemon".to_string())
@@ -122,9 +358,14 @@ impl SessionManager {
             loop {
                 {
                     let mut sessions = sessions_d.lock().unwrap();
-                    sessions.retain(|_, v| !v.check_timeout());
+                    let connections = connections_d.lock().unwrap();
+                    // 还有连接挂在这个Session上时不清理，哪怕已经超过10分钟没有落子
+                    sessions.retain(|id, v| {
+                        !v.check_timeout() || connections.values().any(|c| c.m_associated_session == Some(*id))
+                    });
 
                     println!("Checked. Now sessions: {}", sessions.len());
+                    store_d.save(&store_path_d, &sessions);
 
                     // 每5分钟检查一次
                 }
@@ -142,9 +383,11 @@ impl SessionManager {
         }).unwrap();
         Self {
             m_sessions: Arc::clone(&sessions),
-            m_connections: Arc::new(Mutex::new(HashMap::new())),
+            m_connections: connections,
             m_daemon: Some(d),
             m_condition: cond,
+            m_store: store,
+            m_store_path: store_path,
         }
     }
 
@@ -152,7 +395,7 @@ impl SessionManager {
         self.m_sessions.lock().unwrap()
     }
 
-    pub fn get_connections(&self)-> MutexGuard<HashMap<Uuid, Connection>> {
+    pub fn get_connections(&self)-> MutexGuard<HashMap<Addr<GameWs>, Connection>> {
         self.m_connections.lock().unwrap()
     }
 
@@ -163,6 +406,16 @@ impl SessionManager {
         sessions.insert(id, s);
         id
     }
+
+    /// 把`msg`推送给所有加入了`session`的连接
+    pub fn broadcast(&self, session: Uuid, msg: ServerMessage) {
+        let connections = self.get_connections();
+        for (addr, conn) in connections.iter() {
+            if conn.m_associated_session == Some(session) {
+                addr.do_send(Push(msg.clone()));
+            }
+        }
+    }
 }
 
 impl Drop for SessionManager {
@@ -173,6 +426,8 @@ impl Drop for SessionManager {
         if d.is_some() {
             d.unwrap().join().unwrap();
         }
+        // 最后再存一次快照，避免daemon停在两次tick之间的状态没被落盘
+        self.m_store.save(&self.m_store_path, &self.m_sessions.lock().unwrap());
     }
 }
 
@@ -187,6 +442,78 @@ pub struct Message<T> {
 
 
 
+/// 五子棋固定为双人对局，没有道具或更多座位的概念
+const MAX_PLAYERS_PER_SESSION: u32 = 2;
+
+/// 单个Session的快照元数据，`SessionFilter`只针对这个结构体求值，而不是直接碰`GameSession`
+struct SessionMetadata {
+    uuid: Uuid,
+    player_count: u32,
+    free_slots: u32,
+    map_size: (usize, usize),
+}
+
+/// `list_games`支持的筛选条件，每种筛选对应`ListGamesQuery`里的一个可选字段
+/// 新增筛选条件只需要加一个枚举项和`matches`里的一条分支
+enum SessionFilter {
+    NotFull,
+    MinPlayers(u32),
+    MaxPlayers(u32),
+    MapSize(usize, usize),
+    UuidPrefix(String),
+}
+
+impl SessionFilter {
+    fn matches(&self, meta: &SessionMetadata)-> bool {
+        match self {
+            SessionFilter::NotFull => meta.free_slots > 0,
+            SessionFilter::MinPlayers(n) => meta.player_count >= *n,
+            SessionFilter::MaxPlayers(n) => meta.player_count <= *n,
+            SessionFilter::MapSize(w, h) => meta.map_size == (*w, *h),
+            SessionFilter::UuidPrefix(prefix) => meta.uuid.hyphenated().to_string().starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// `list_games`的query参数；只有出现在query里的字段才会变成一条`SessionFilter`
+#[derive(Deserialize)]
+struct ListGamesQuery {
+    not_full: Option<bool>,
+    min_players: Option<u32>,
+    max_players: Option<u32>,
+    /// 形如`15x15`
+    map_size: Option<String>,
+    uuid_prefix: Option<String>,
+}
+
+impl ListGamesQuery {
+    fn into_filters(self)-> Vec<SessionFilter> {
+        let mut filters = Vec::new();
+        if self.not_full == Some(true) {
+            filters.push(SessionFilter::NotFull);
+        }
+        if let Some(n) = self.min_players {
+            filters.push(SessionFilter::MinPlayers(n));
+        }
+        if let Some(n) = self.max_players {
+            filters.push(SessionFilter::MaxPlayers(n));
+        }
+        if let Some(size) = self.map_size {
+            let parsed = size.split_once('x')
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+            if let Some((w, h)) = parsed {
+                filters.push(SessionFilter::MapSize(w, h));
+            }
+        }
+        if let Some(prefix) = self.uuid_prefix {
+            filters.push(SessionFilter::UuidPrefix(prefix));
+        }
+        filters
+    }
+}
+
+
+
 #[cfg(debug_assertions)]
 macro_rules! get_file {
     ($path:expr) => {{
@@ -233,14 +560,14 @@ pub async fn multiplayer()-> HttpResponse {
 
 #[get("/api/{api_name}")]
 pub async fn api(
-    smanager: web::Data<Mutex<SessionManager>>,
+    smanager_data: web::Data<Mutex<SessionManager>>,
     api_name: web::Path<String>,
     req: HttpRequest,
     stream: web::Payload
     )-> HttpResponse {
 
     let api_name = api_name.into_inner();
-    let smanager = smanager.lock().unwrap();
+    let smanager = smanager_data.lock().unwrap();
     let mut resp = HttpResponse::Ok();
     resp.insert_header(("Content-Type", "application/json"));
     match &api_name[..] {
@@ -321,6 +648,61 @@ pub async fn api(
             }).unwrap())
         },
 
+        /// 按筛选条件列出Session，类似游戏大厅向master server发起的查询
+        /// 筛选条件通过querystring传入，例如`/api/list_games?not_full=true&min_players=1`
+        /// 原需求里的`has_super_apple`是贪吃蛇的道具概念，五子棋没有道具，这里没有对应的筛选项
+        "list_games" => {
+            let query = web::Query::<ListGamesQuery>::from_query(req.query_string())
+                .map(|q| q.into_inner())
+                .unwrap_or(ListGamesQuery {
+                    not_full: None,
+                    min_players: None,
+                    max_players: None,
+                    map_size: None,
+                    uuid_prefix: None,
+                });
+            let filters = query.into_filters();
+
+            let sessions = smanager.get_sessions();
+            let connections = smanager.get_connections();
+
+            #[derive(Serialize)]
+            struct SessionInfo {
+                uuid: Hyphenated,
+                player_count: u32,
+                free_slots: u32,
+                map_size: (usize, usize),
+            }
+            #[derive(Serialize)]
+            struct GameList {
+                games: Vec<SessionInfo>,
+            }
+
+            let games = sessions.values().filter_map(|session| {
+                let player_count = connections.values()
+                    .filter(|c| c.m_associated_session == Some(session.get_game_uuid()))
+                    .count() as u32;
+                let meta = SessionMetadata {
+                    uuid: session.get_game_uuid(),
+                    player_count,
+                    free_slots: MAX_PLAYERS_PER_SESSION.saturating_sub(player_count),
+                    map_size: session.get_map_size(),
+                };
+                filters.iter().all(|f| f.matches(&meta)).then(|| SessionInfo {
+                    uuid: meta.uuid.hyphenated(),
+                    player_count: meta.player_count,
+                    free_slots: meta.free_slots,
+                    map_size: meta.map_size,
+                })
+            }).collect();
+
+            resp.body(serde_json::to_string(&Message {
+                code: 200,
+                msg: "Ok".to_string(),
+                data: Some(GameList { games }),
+            }).unwrap())
+        },
+
         /// 生成随机的UUID
         "get_random_uuid" => {
             #[derive(Serialize)]
@@ -338,8 +720,10 @@ pub async fn api(
 
         /// 建立Websocket连接
         "connect" => {
+            drop(smanager);
             let resp = ws::start(GameWs {
-                smanager: Arc::clone(&smanager),
+                smanager: smanager_data.into_inner(),
+                m_joined_session: None,
             }, &req, stream).unwrap();
             println!("Websocket Response: {:?}", resp);
             resp
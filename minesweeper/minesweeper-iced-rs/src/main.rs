@@ -26,21 +26,150 @@ use iced::{
     Point,
     Padding,
     Color,
+    Alignment,
 };
 use iced::application::Application;
 use iced::widget::*;
 use iced::window as win;
 use vec2d::{ Vec2D, Coord };
 use rand::prelude::*;
-use once_cell::sync::OnceCell;
 
-use std::sync::Mutex;
+use std::sync::{ Mutex, Arc };
 use std::collections::VecDeque;
+use std::time::Duration;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 enum Message {
-    Gameover,
+    /// 携带点到的那颗雷的坐标，好在揭示全局时把它标成特殊颜色
+    Gameover(Coord),
+    Win,
+    /// 每秒一次的计时器滴答
+    Tick,
+    /// 点了笑脸，整块棋盘要原地换成一局新的
+    NewGame,
+    /// 从菜单选了难度(或者自定义)，开始一局：宽、高、雷数
+    StartGame(usize, usize, usize),
+    CustomWidthChanged(String),
+    CustomHeightChanged(String),
+    CustomMinesChanged(String),
+    /// 一次点开单个格子(没有连锁展开)
+    Opened,
+    /// 一次点开触发了连锁(零格)展开，开出了不止一个格子
+    Cascade,
+    /// 右键循环了一次插旗状态(None/Flag/Question之间)
+    FlagToggled,
     Nothing,
+
+    /// 从菜单进到联机大厅
+    OpenLobby,
+    SessionIdChanged(String),
+    /// 大厅里点了"创建"：生成一个新Session并把自己当成房主
+    CreateSession,
+    /// 大厅里点了"加入"：拿着输入框里的Session id去接入
+    JoinSession,
+    /// 接入(或创建)成功，带上服务端分配/确认的Session id
+    SessionJoined(String),
+    /// 服务端广播了当前Session里的在线玩家列表
+    PlayersUpdated(Vec<String>),
+    LeaveLobby,
+
+    /// 窗口尺寸变了(现在可以拖动改变了)，带上新的宽高
+    WindowResized(u32, u32),
+}
+
+/// `MineSweeper`当前在选难度还是在下棋
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Screen {
+    Menu,
+    /// 联机大厅：创建/加入Session，等服务端确认、同步在线玩家
+    Lobby,
+    Playing,
+}
+
+/// 联机对局的客户端协议和大厅状态
+///
+/// `gomoku-rsweb`那边已经有一整套基于`actix-web-actors::ws`的`SessionManager`/`ClientMessage`/
+/// `ServerMessage`，但那是五子棋的服务端：它的`ServerMessage::StateUpdate`载的是五子棋自己的
+/// `Cell`和黑白子比分，协议和棋盘类型都跟扫雷对不上，没法直接指过去当后端用。这里先按同样的
+/// JSON`tag`/`content`协议风格把扫雷这边需要的客户端动作/服务端广播定义出来，大厅UI和
+/// `MineSweeper`里的状态流转也先接好；真正把`ClientAction`发出去、把`ServerUpdate`收回来的
+/// HTTP/WebSocket传输层，等这个仓库里有一个扫雷专用的服务端(而不是接五子棋那个)时再接上。
+mod net {
+    /// 客户端发给服务端的动作
+    #[derive(Clone, Debug)]
+    pub enum ClientAction {
+        CreateSession,
+        JoinSession { session_id: String },
+        Open { x: usize, y: usize },
+        Flag { x: usize, y: usize },
+    }
+
+    /// 服务端广播给Session内所有客户端的权威更新
+    ///
+    /// 雷的位置只在`CellOpened`里随着被揭开的格子一起下发，客户端在此之前完全不知道雷在哪——
+    /// `place_mines`和洪水填充都应该在服务端的canonical `Vec2D<Cell>`上跑。
+    #[derive(Clone, Debug)]
+    pub enum ServerUpdate {
+        SessionJoined { session_id: String },
+        PlayersChanged { players: Vec<String> },
+        CellOpened { x: usize, y: usize, mines_counter: i32, has_mine: bool },
+        CellFlagged { x: usize, y: usize, flagged: bool },
+        GameOver { clicked_mine: Option<(usize, usize)> },
+    }
+}
+
+/// 笑脸按钮的表情，由棋盘当前状态推出来，不需要额外维护
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum SmileyState {
+    Normal,
+    Surprised,
+    Dead,
+    Cool,
+}
+
+impl SmileyState {
+    fn from_board(board: &BattlegroundState)-> Self {
+        match board.end_state {
+            Some(EndState::Lost { .. }) => SmileyState::Dead,
+            Some(EndState::Won) => SmileyState::Cool,
+            None if board.left_pressed.is_some() => SmileyState::Surprised,
+            None => SmileyState::Normal,
+        }
+    }
+
+    fn label(self)-> &'static str {
+        match self {
+            SmileyState::Normal => ":)",
+            SmileyState::Surprised => ":o",
+            SmileyState::Dead => "x(",
+            SmileyState::Cool => "B)",
+        }
+    }
+}
+
+/// 对局结束后的揭示状态；只决定`draw`怎么画，不会反过来改动`m_vec`里的实际格子
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum EndState {
+    Won,
+    Lost { clicked_mine: Coord },
+}
+
+/// 右键点一个没开的格子在这三种状态之间循环：`None` -> `Flag` -> `Question` -> `None`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mark {
+    None,
+    Flag,
+    Question,
+}
+
+impl Mark {
+    fn next(self)-> Self {
+        match self {
+            Mark::None => Mark::Flag,
+            Mark::Flag => Mark::Question,
+            Mark::Question => Mark::None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -48,10 +177,211 @@ struct Cell {
     pub has_mine: bool,
     pub opened: bool,
     pub mines_counter: i32,
-    pub marked: bool,
+    pub mark: Mark,
+}
+
+/// 经典红色七段数码管：雷数计数器和计时器共用这一套渲染逻辑
+mod seven_segment {
+    use iced::widget::canvas::{ Frame, Path };
+    use iced::{ Point, Size, Color };
+
+    const SEG_TOP: u8          = 0b0000001;
+    const SEG_TOP_LEFT: u8     = 0b0000010;
+    const SEG_TOP_RIGHT: u8    = 0b0000100;
+    const SEG_MIDDLE: u8       = 0b0001000;
+    const SEG_BOTTOM_LEFT: u8  = 0b0010000;
+    const SEG_BOTTOM_RIGHT: u8 = 0b0100000;
+    const SEG_BOTTOM: u8       = 0b1000000;
+
+    /// 负号单独用中间那一段来画
+    const SEG_DASH: u8 = SEG_MIDDLE;
+
+    /// 一个数字点亮的那一组段
+    fn digit_segments(digit: u8)-> u8 {
+        match digit {
+            0 => SEG_TOP | SEG_TOP_LEFT | SEG_TOP_RIGHT | SEG_BOTTOM_LEFT | SEG_BOTTOM_RIGHT | SEG_BOTTOM,
+            1 => SEG_TOP_RIGHT | SEG_BOTTOM_RIGHT,
+            2 => SEG_TOP | SEG_TOP_RIGHT | SEG_MIDDLE | SEG_BOTTOM_LEFT | SEG_BOTTOM,
+            3 => SEG_TOP | SEG_TOP_RIGHT | SEG_MIDDLE | SEG_BOTTOM_RIGHT | SEG_BOTTOM,
+            4 => SEG_TOP_LEFT | SEG_TOP_RIGHT | SEG_MIDDLE | SEG_BOTTOM_RIGHT,
+            5 => SEG_TOP | SEG_TOP_LEFT | SEG_MIDDLE | SEG_BOTTOM_RIGHT | SEG_BOTTOM,
+            6 => SEG_TOP | SEG_TOP_LEFT | SEG_MIDDLE | SEG_BOTTOM_LEFT | SEG_BOTTOM_RIGHT | SEG_BOTTOM,
+            7 => SEG_TOP | SEG_TOP_RIGHT | SEG_BOTTOM_RIGHT,
+            8 => SEG_TOP | SEG_TOP_LEFT | SEG_TOP_RIGHT | SEG_MIDDLE | SEG_BOTTOM_LEFT | SEG_BOTTOM_RIGHT | SEG_BOTTOM,
+            9 => SEG_TOP | SEG_TOP_LEFT | SEG_TOP_RIGHT | SEG_MIDDLE | SEG_BOTTOM_RIGHT | SEG_BOTTOM,
+            _ => 0,
+        }
+    }
+
+    /// 把计数器的值拆成三个数码管要点亮的段；超出[-99, 999]的值会被夹住
+    /// 负数借用最高位画一条横杠当负号，和经典扫雷的三位计数器一致
+    pub fn counter_digit_masks(value: i32)-> [u8; 3] {
+        let value = value.clamp(-99, 999);
+        if value < 0 {
+            let magnitude = (-value) as u32;
+            [SEG_DASH, digit_segments((magnitude / 10 % 10) as u8), digit_segments(magnitude as u8 % 10)]
+        } else {
+            let magnitude = value as u32;
+            [
+                digit_segments((magnitude / 100 % 10) as u8),
+                digit_segments((magnitude / 10 % 10) as u8),
+                digit_segments((magnitude % 10) as u8),
+            ]
+        }
+    }
+
+    /// 把一个七段数码管画到`origin`起、大小为`size`的区域里
+    pub fn draw_digit(frame: &mut Frame, origin: Point, size: Size, mask: u8) {
+        let on = Color::from_rgb(0.86, 0.08, 0.08);
+        let off = Color::from_rgb(0.2, 0.02, 0.02);
+        let thickness = size.width * 0.22;
+        let half_height = (size.height - thickness) / 2.0;
+
+        let seg_color = |bit: u8| if mask & bit != 0 { on } else { off };
+
+        frame.fill(&Path::rectangle(
+            Point::new(origin.x + thickness * 0.5, origin.y),
+            Size::new(size.width - thickness, thickness),
+        ), seg_color(SEG_TOP));
+
+        frame.fill(&Path::rectangle(
+            Point::new(origin.x, origin.y + thickness * 0.5),
+            Size::new(thickness, half_height),
+        ), seg_color(SEG_TOP_LEFT));
+
+        frame.fill(&Path::rectangle(
+            Point::new(origin.x + size.width - thickness, origin.y + thickness * 0.5),
+            Size::new(thickness, half_height),
+        ), seg_color(SEG_TOP_RIGHT));
+
+        frame.fill(&Path::rectangle(
+            Point::new(origin.x + thickness * 0.5, origin.y + size.height / 2.0 - thickness * 0.5),
+            Size::new(size.width - thickness, thickness),
+        ), seg_color(SEG_MIDDLE));
+
+        frame.fill(&Path::rectangle(
+            Point::new(origin.x, origin.y + size.height / 2.0),
+            Size::new(thickness, half_height),
+        ), seg_color(SEG_BOTTOM_LEFT));
+
+        frame.fill(&Path::rectangle(
+            Point::new(origin.x + size.width - thickness, origin.y + size.height / 2.0),
+            Size::new(thickness, half_height),
+        ), seg_color(SEG_BOTTOM_RIGHT));
+
+        frame.fill(&Path::rectangle(
+            Point::new(origin.x + thickness * 0.5, origin.y + size.height - thickness),
+            Size::new(size.width - thickness, thickness),
+        ), seg_color(SEG_BOTTOM));
+    }
+}
+
+/// 开局/插旗/连锁/爆炸/获胜的提示音
+///
+/// 仓库里没有现成的音效素材(没有wav/ogg文件)，所以没法走Minesweeper-rs那种
+/// hound/lewton解码音频文件的路子；这里改用`rodio`内置的正弦波现造几个音效，
+/// 频率和时长不同，区分开/旗/连锁/爆炸/获胜这几种场景
+mod audio {
+    use std::time::Duration;
+    use rodio::{ OutputStream, OutputStreamHandle, Sink, Source, source::SineWave };
+
+    pub struct Player {
+        muted: bool,
+        // 只是为了不让输出流在`Player`存活期间被提前drop掉，拿不到具体字段也没关系
+        _stream: Option<OutputStream>,
+        handle: Option<OutputStreamHandle>,
+    }
+
+    impl Player {
+        /// `muted`为`true`时(`-mute`命令行参数)完全不打开音频设备，省得在无头环境里报错
+        pub fn new(muted: bool)-> Self {
+            if muted {
+                return Self { muted: true, _stream: None, handle: None };
+            }
+            match OutputStream::try_default() {
+                Ok((stream, handle)) => Self { muted: false, _stream: Some(stream), handle: Some(handle) },
+                Err(_) => Self { muted: true, _stream: None, handle: None },
+            }
+        }
+
+        fn play_tone(&self, frequency: f32, duration_ms: u64) {
+            if self.muted {
+                return;
+            }
+            let handle = match &self.handle {
+                Some(handle) => handle,
+                None => return,
+            };
+            if let Ok(sink) = Sink::try_new(handle) {
+                let source = SineWave::new(frequency).take_duration(Duration::from_millis(duration_ms));
+                sink.append(source);
+                sink.detach();
+            }
+        }
+
+        /// 点开单个格子的提示音
+        pub fn reveal(&self) {
+            self.play_tone(880.0, 40);
+        }
+
+        /// 右键循环插旗状态的提示音
+        pub fn flag(&self) {
+            self.play_tone(440.0, 60);
+        }
+
+        /// 零格连锁展开了不止一个格子
+        pub fn cascade(&self) {
+            self.play_tone(660.0, 120);
+        }
+
+        /// 踩到雷了
+        pub fn explosion(&self) {
+            self.play_tone(110.0, 400);
+        }
+
+        /// 赢了
+        pub fn win(&self) {
+            self.play_tone(1320.0, 500);
+        }
+    }
 }
 
-struct Battleground;
+/// 画雷数剩余或计时器的小画布；三个数码管横排，数值由`seven_segment::counter_digit_masks`拆分
+struct SevenSegmentCounter {
+    value: i32,
+}
+
+impl Program<Message> for SevenSegmentCounter {
+    type State = ();
+
+    fn draw(&self,
+        _state: &Self::State,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor
+    )-> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let digit_width = bounds.size().width / 3.0;
+        let digit_height = bounds.size().height;
+
+        for (i, mask) in seven_segment::counter_digit_masks(self.value).into_iter().enumerate() {
+            seven_segment::draw_digit(
+                &mut frame,
+                Point::new(digit_width * i as f32, 0.0),
+                Size::new(digit_width, digit_height),
+                mask,
+            );
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+/// `BattlegroundState`本体现在挂在`MineSweeper`上，`Battleground`只是借一份`Arc`进来画图/响应点击，
+/// 这样"Smiley"重开一局时`MineSweeper::update`才能整个换掉棋盘状态，而不用拆窗口重建
+struct Battleground {
+    board: Arc<Mutex<BattlegroundState>>,
+}
 
 impl BattlegroundState {
     fn canvas2coord(&self, bounds: Rectangle<f32>, mut point: Point)-> Option<Coord> {
@@ -158,25 +488,28 @@ impl BattlegroundState {
             return Message::Nothing;
         }
 
+        let mut opened_count = 0;
         while !queue.is_empty() {
             let current_coord = queue.pop_front().unwrap();
 
             let target = m_vec.get_mut(current_coord);
 
             if let Some(current_cell) = target {
-                if current_cell.has_mine && !current_cell.marked {
-                    return Message::Gameover;
+                if current_cell.has_mine && current_cell.mark != Mark::Flag {
+                    return Message::Gameover(current_coord);
                 }
-                if !current_cell.opened && !current_cell.marked {
+                if !current_cell.opened && current_cell.mark != Mark::Flag {
                     let current_cell = if !self.generated {
                         drop(current_cell);
-                        Self::place_mines(&mut m_vec, *MINES.wait(), (coord.x, coord.y)).expect("Cannot place mines");
+                        Self::place_mines(&mut m_vec, self.mines, (coord.x, coord.y)).expect("Cannot place mines");
                         self.generated = true;
                         m_vec.get_mut(current_coord).unwrap()
                     } else {
                         current_cell
                     };
                     current_cell.opened = true;
+                    self.remaining_safe -= 1;
+                    opened_count += 1;
                     if current_cell.mines_counter == 0 {
                         offsets.iter().map(|i| Coord::new(
                             (current_coord.x as isize + i.0) as usize,
@@ -189,20 +522,99 @@ impl BattlegroundState {
             }
         }
 
-        Message::Nothing
+        if self.remaining_safe == 0 {
+            Message::Win
+        } else if opened_count > 1 {
+            Message::Cascade
+        } else if opened_count == 1 {
+            Message::Opened
+        } else {
+            Message::Nothing
+        }
     }
 
-    pub fn right_click(&self, coord: Coord)-> Message {
+    pub fn right_click(&mut self, coord: Coord)-> Message {
         let mut m_vec = self.m_vec.lock().unwrap();
 
         if let Some(current_cell) = m_vec.get_mut(coord) {
             if !current_cell.opened {
-                current_cell.marked = !current_cell.marked;
+                let was_flag = current_cell.mark == Mark::Flag;
+                current_cell.mark = current_cell.mark.next();
+                let is_flag = current_cell.mark == Mark::Flag;
+                if is_flag && !was_flag {
+                    self.flagged_count += 1;
+                } else if was_flag && !is_flag {
+                    self.flagged_count -= 1;
+                }
+                return Message::FlagToggled;
             }
         }
 
         Message::Nothing
     }
+
+    /// 双键(或中键)点一个已经打开、数字等于周围插旗数的格子：把没插旗的邻居一次性挖开
+    /// 跟`left_click`共用同一套洪水填充逻辑，邻居里有没插旗的雷就直接`Gameover`
+    pub fn chord_click(&mut self, coord: Coord)-> Message {
+        let offsets: Vec<(isize, isize)> = vec![
+            (-1, -1), (-1, 0), (-1, 1), (0, -1),
+            (0, 1), (1, -1), (1, 0), (1, 1)
+        ];
+        let mut m_vec = self.m_vec.lock().unwrap();
+
+        let mines_counter = match m_vec.get(coord) {
+            Some(current_cell) if current_cell.opened && current_cell.mines_counter > 0 => current_cell.mines_counter,
+            _ => return Message::Nothing,
+        };
+
+        let neighbors: Vec<Coord> = offsets.iter()
+            .map(|i| Coord::new(
+                (coord.x as isize + i.0) as usize,
+                (coord.y as isize + i.1) as usize,
+            ))
+            .filter(|&n| m_vec.get(n).is_some())
+            .collect();
+
+        let flagged = neighbors.iter()
+            .filter(|&&n| m_vec.get(n).unwrap().mark == Mark::Flag)
+            .count() as i32;
+
+        if flagged != mines_counter {
+            return Message::Nothing;
+        }
+
+        let mut opened_count = 0;
+        let mut queue = VecDeque::<Coord>::from(neighbors);
+        while let Some(current_coord) = queue.pop_front() {
+            if let Some(current_cell) = m_vec.get_mut(current_coord) {
+                if current_cell.opened || current_cell.mark == Mark::Flag {
+                    continue;
+                }
+                if current_cell.has_mine {
+                    return Message::Gameover(current_coord);
+                }
+                current_cell.opened = true;
+                self.remaining_safe -= 1;
+                opened_count += 1;
+                if current_cell.mines_counter == 0 {
+                    offsets.iter().map(|i| Coord::new(
+                        (current_coord.x as isize + i.0) as usize,
+                        (current_coord.y as isize + i.1) as usize,
+                    )).for_each(|i| queue.push_back(i));
+                }
+            }
+        }
+
+        if self.remaining_safe == 0 {
+            Message::Win
+        } else if opened_count > 1 {
+            Message::Cascade
+        } else if opened_count == 1 {
+            Message::Opened
+        } else {
+            Message::Nothing
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -212,32 +624,51 @@ struct BattlegroundState {
     pub right_pressed: Option<Coord>,
 
     pub generated: bool,
+
+    /// 还没打开的非雷格子数，减到0就赢了
+    pub remaining_safe: usize,
+
+    /// `Some`之后棋盘就定格了，`draw`据此把整个棋盘揭示出来
+    pub end_state: Option<EndState>,
+
+    /// 插了旗的格子数，给外面的雷数计数器显示用
+    pub flagged_count: usize,
+
+    /// 这一局的雷数，由菜单选的难度/自定义值决定，`left_click`布雷时要用
+    pub mines: usize,
 }
-impl Default for BattlegroundState {
-    fn default()-> Self {
+impl BattlegroundState {
+    /// 开一局新棋盘，宽高和雷数都来自菜单选择，不再依赖全局的`SIZE`/`MINES`
+    fn new(width: usize, height: usize, mines: usize)-> Self {
         Self {
-            m_vec: Mutex::new(Vec2D::from_example(vec2d::Size::new(*SIZE.wait(), *SIZE.wait()), &Cell {
+            m_vec: Mutex::new(Vec2D::from_example(vec2d::Size::new(width, height), &Cell {
                 opened: false,
                 has_mine: false,
                 mines_counter: 0,
-                marked: false,
+                mark: Mark::None,
             })),
             left_pressed: None,
             right_pressed: None,
             generated: false,
+            remaining_safe: width * height - mines,
+            end_state: None,
+            flagged_count: 0,
+            mines,
         }
     }
 }
 
 impl Program<Message> for Battleground {
-    type State = BattlegroundState;
+    /// 真正的状态在`self.board`里，这里不再需要iced管理的内部状态
+    type State = ();
 
     fn draw(&self,
-        state: &Self::State,
+        _state: &Self::State,
         _theme: &Theme,
         bounds: Rectangle,
         _cursor: Cursor
     )-> Vec<Geometry> {
+        let state = self.board.lock().unwrap();
         let m_vec = state.m_vec.lock().unwrap();
         let mut frame = Frame::new(bounds.size());
         let stroke = Stroke {
@@ -250,15 +681,29 @@ impl Program<Message> for Battleground {
         let cell_width = bounds.size().width / vec_size.width as f32;
         let cell_height = bounds.size().height / vec_size.height as f32;
 
+        // 赢了或者踩雷了，棋盘定格，把没开的雷也画出来，误插的旗也要标出来
+        let revealing = state.end_state.is_some();
+
         for x in 0..vec_size.width {
             for y in 0..vec_size.height {
+                let coord = Coord::new(x, y);
                 let point = Point::new(cell_width * x as f32, cell_height * y as f32);
                 let central_point = Point::new(point.x + cell_width / 2.0, point.y + cell_height / 2.0);
                 let box_ = Path::rectangle(point, Size::new(cell_width, cell_height));
 
-                let cell = m_vec.get(Coord::new(x, y)).unwrap();
-                if cell.opened {
+                let cell = m_vec.get(coord).unwrap();
+                let is_clicked_mine = matches!(state.end_state, Some(EndState::Lost { clicked_mine }) if clicked_mine == coord);
+                let reveal_mine = revealing && cell.has_mine && !cell.opened;
+
+                if cell.opened || reveal_mine {
                     //println!("({},{}) Opened", x, y);
+                    if is_clicked_mine {
+                        let inner_box = Path::rectangle(
+                            Point::new(point.x + cell_width * 0.1, point.y + cell_height * 0.1),
+                            Size::new(cell_width * 0.8, cell_height * 0.8),
+                        );
+                        frame.fill(&inner_box, Color::from_rgb(0.82, 0.16, 0.23));
+                    }
                     if cell.has_mine {
                         frame.fill_text(Text {
                             content: "M".to_string(),
@@ -298,7 +743,7 @@ impl Program<Message> for Battleground {
 
                 frame.stroke(&box_, stroke.clone());
 
-                if cell.marked {
+                if cell.mark == Mark::Flag {
                     let stick = Path::rectangle(
                         Point::new(
                             point.x + cell_width * 0.45,
@@ -320,6 +765,31 @@ impl Program<Message> for Battleground {
                     });
 
                     frame.fill(&flag, Color::from_rgb(0.82, 0.16, 0.23));
+
+                    // 游戏结束时，插错旗的格子（旗子底下其实不是雷）额外画个红叉
+                    if revealing && !cell.has_mine {
+                        let cross = Path::new(|b| {
+                            b.move_to(Point::new(point.x + cell_width * 0.2, point.y + cell_height * 0.2));
+                            b.line_to(Point::new(point.x + cell_width * 0.8, point.y + cell_height * 0.8));
+                            b.move_to(Point::new(point.x + cell_width * 0.8, point.y + cell_height * 0.2));
+                            b.line_to(Point::new(point.x + cell_width * 0.2, point.y + cell_height * 0.8));
+                        });
+                        frame.stroke(&cross, Stroke {
+                            style: Style::Solid(Color::from_rgb(0.82, 0.16, 0.23)),
+                            width: cell_width * 0.06,
+                            ..Default::default()
+                        });
+                    }
+                } else if cell.mark == Mark::Question {
+                    frame.fill_text(Text {
+                        content: "?".to_string(),
+                        position: central_point,
+                        color: Color::from_rgb(0.2, 0.2, 0.2),
+                        size: cell_height,
+                        font: Font::Default,
+                        horizontal_alignment: Horizontal::Center,
+                        vertical_alignment: Vertical::Center,
+                    });
                 }
             }
         }
@@ -328,11 +798,15 @@ impl Program<Message> for Battleground {
     }
 
     fn update(&self,
-        state: &mut Self::State,
+        _state: &mut Self::State,
         event: CanvasEvent,
         bounds: Rectangle<f32>,
         cursor: Cursor
     )-> (EventStatus, Option<Message>) {
+        let mut state = self.board.lock().unwrap();
+        if state.end_state.is_some() {
+            return (EventStatus::Ignored, None);
+        }
         let pointer = if let Cursor::Available(pointer) = cursor {
             pointer
         } else {
@@ -355,13 +829,33 @@ impl Program<Message> for Battleground {
                     MouseEvent::ButtonReleased(btn) => {
                         let coord = state.canvas2coord(bounds, pointer);
                         if coord.is_some() {
+                            // 左右键同时按在同一个格子上，或者直接按中键，都走chord(双键)开格
+                            let chording = btn == MouseButton::Middle
+                                || (state.left_pressed == coord && state.right_pressed == coord);
+                            if chording {
+                                let msg = state.chord_click(coord.unwrap());
+                                match msg {
+                                    Message::Gameover(clicked_mine) => state.end_state = Some(EndState::Lost { clicked_mine }),
+                                    Message::Win => state.end_state = Some(EndState::Won),
+                                    _ => {},
+                                }
+                                state.right_pressed = None;
+                                state.left_pressed = None;
+                                return (EventStatus::Captured, Some(msg));
+                            }
                             match btn {
                                 MouseButton::Left if state.left_pressed == coord => {
-                                    return (EventStatus::Captured, Some(state.left_click(coord.unwrap())));
+                                    let msg = state.left_click(coord.unwrap());
+                                    match msg {
+                                        Message::Gameover(clicked_mine) => state.end_state = Some(EndState::Lost { clicked_mine }),
+                                        Message::Win => state.end_state = Some(EndState::Won),
+                                        _ => {},
+                                    }
+                                    return (EventStatus::Captured, Some(msg));
                                 },
                                 MouseButton::Right if state.right_pressed == coord => {
-                                    
-                                    return (EventStatus::Captured, Some(state.right_click(coord.unwrap())));
+                                    let msg = state.right_click(coord.unwrap());
+                                    return (EventStatus::Captured, Some(msg));
                                 },
                                 _ => {},
                             }
@@ -380,14 +874,35 @@ impl Program<Message> for Battleground {
     }
 }
 
-impl Default for Battleground {
-    fn default()-> Self {
-        Battleground
-    }
-}
-
 struct MineSweeper {
     pub size: (u32, u32),
+
+    /// 当前在选难度菜单还是在下棋
+    screen: Screen,
+
+    /// 棋盘状态本体，挂在这里而不是`Program::State`里，好让`Message::NewGame`/`Message::StartGame`能整个换掉它
+    board: Arc<Mutex<BattlegroundState>>,
+
+    /// 自开局起走过的秒数，由`subscription`里的每秒`Tick`推进
+    elapsed_secs: i32,
+
+    /// 赢了或者踩雷了之后置`true`，停止计时
+    game_over: bool,
+
+    /// 菜单里"Custom"那一栏的宽/高/雷数输入框内容
+    custom_width: String,
+    custom_height: String,
+    custom_mines: String,
+
+    /// 音效播放器；`-mute`命令行参数会让它不打开音频设备，所有播放调用都变成空操作
+    audio: Mutex<audio::Player>,
+
+    /// 大厅里Session id输入框的内容
+    session_input: String,
+    /// 当前已接入的Session id，`None`表示还在单机玩
+    active_session: Option<String>,
+    /// 服务端广播的、当前Session里的在线玩家
+    connected_players: Vec<String>,
 }
 
 #[allow(unused_parens)]
@@ -395,12 +910,24 @@ impl Application for MineSweeper {
 
     type Executor = iced::executor::Default;
     type Theme = Theme;
-    type Flags = ((u32, u32));
+    type Flags = ((u32, u32), bool);
     type Message = Message;
 
     fn new(flags: Self::Flags)-> (Self, Command<Self::Message>) {
+        let (size, muted) = flags;
         (Self {
-            size: flags,
+            size,
+            screen: Screen::Menu,
+            board: Arc::new(Mutex::new(BattlegroundState::new(9, 9, 10))),
+            elapsed_secs: 0,
+            game_over: false,
+            custom_width: "9".to_string(),
+            custom_height: "9".to_string(),
+            custom_mines: "10".to_string(),
+            audio: Mutex::new(audio::Player::new(muted)),
+            session_input: String::new(),
+            active_session: None,
+            connected_players: Vec::new(),
         }, Command::none())
     }
 
@@ -409,31 +936,240 @@ impl Application for MineSweeper {
     }
 
     fn update(&mut self, msg: Self::Message)-> Command<Self::Message> {
+        // 揭示整个棋盘是`BattlegroundState`自己的事(见`Program::update`)，这里不用再关窗口了
         match msg {
-            Message::Gameover => {
-                win::close()
+            Message::Gameover(_) => {
+                self.game_over = true;
+                self.audio.lock().unwrap().explosion();
+                Command::none()
             },
-
-            _ => {
+            Message::Win => {
+                self.game_over = true;
+                self.audio.lock().unwrap().win();
+                Command::none()
+            },
+            Message::Opened => {
+                self.audio.lock().unwrap().reveal();
+                Command::none()
+            },
+            Message::Cascade => {
+                self.audio.lock().unwrap().cascade();
+                Command::none()
+            },
+            Message::FlagToggled => {
+                self.audio.lock().unwrap().flag();
+                Command::none()
+            },
+            Message::Tick => {
+                self.elapsed_secs += 1;
+                Command::none()
+            },
+            Message::NewGame => {
+                let (width, height, mines) = {
+                    let board = self.board.lock().unwrap();
+                    let size = board.m_vec.lock().unwrap().size();
+                    (size.width, size.height, board.mines)
+                };
+                *self.board.lock().unwrap() = BattlegroundState::new(width, height, mines);
+                self.elapsed_secs = 0;
+                self.game_over = false;
+                Command::none()
+            },
+            Message::StartGame(width, height, mines) => {
+                *self.board.lock().unwrap() = BattlegroundState::new(width, height, mines);
+                self.elapsed_secs = 0;
+                self.game_over = false;
+                self.screen = Screen::Playing;
+                Command::none()
+            },
+            Message::CustomWidthChanged(value) => {
+                self.custom_width = value;
+                Command::none()
+            },
+            Message::CustomHeightChanged(value) => {
+                self.custom_height = value;
+                Command::none()
+            },
+            Message::CustomMinesChanged(value) => {
+                self.custom_mines = value;
+                Command::none()
+            },
+            Message::Nothing => Command::none(),
+            Message::OpenLobby => {
+                self.screen = Screen::Lobby;
+                Command::none()
+            },
+            Message::SessionIdChanged(value) => {
+                self.session_input = value;
+                Command::none()
+            },
+            // 真正建Session/接Session的HTTP请求还没接上传输层(见`mod net`的注释)，
+            // 这里先把本地状态摆成"已加入自己这个id"的样子，好让大厅/棋盘UI能先跑起来
+            Message::CreateSession | Message::JoinSession => {
+                let session_id = self.session_input.clone();
+                Command::perform(async {}, move |_| Message::SessionJoined(session_id.clone()))
+            },
+            Message::SessionJoined(session_id) => {
+                self.active_session = Some(session_id);
+                Command::none()
+            },
+            Message::PlayersUpdated(players) => {
+                self.connected_players = players;
+                Command::none()
+            },
+            Message::LeaveLobby => {
+                self.active_session = None;
+                self.connected_players.clear();
+                self.screen = Screen::Menu;
+                Command::none()
+            },
+            Message::WindowResized(width, height) => {
+                self.size = (width, height);
                 Command::none()
             },
         }
     }
 
+    fn subscription(&self)-> iced::Subscription<Self::Message> {
+        let resize = iced::subscription::events_with(|event, _status| match event {
+            iced::Event::Window(win::Event::Resized { width, height }) => Some(Message::WindowResized(width, height)),
+            _ => None,
+        });
+
+        let tick = if self.screen == Screen::Playing && !self.game_over && self.board.lock().unwrap().generated {
+            iced::time::every(Duration::from_secs(1)).map(|_| Message::Tick)
+        } else {
+            iced::Subscription::none()
+        };
+
+        iced::Subscription::batch(vec![resize, tick])
+    }
+
     fn view(&self)-> Element<'_, Self::Message, Renderer<Self::Theme>> {
-        let size = (self.size.0 as f32, self.size.1 as f32);
-        let canvas = Canvas::new(Battleground)
+        match self.screen {
+            Screen::Menu => self.view_menu(),
+            Screen::Lobby => self.view_lobby(),
+            Screen::Playing => self.view_playing(),
+        }
+    }
+
+}
+
+impl MineSweeper {
+    /// 难度/自定义选择界面：三个预设难度按钮，加一个带三个数字输入框的自定义项
+    fn view_menu(&self)-> Element<'_, Message, Renderer<Theme>> {
+        let preset_button = |label: &str, width: usize, height: usize, mines: usize| {
+            button(text(label))
+                .on_press(Message::StartGame(width, height, mines))
+                .width(Length::Fixed(220.0))
+        };
+
+        let custom_width = self.custom_width.parse::<usize>().unwrap_or(9);
+        let custom_height = self.custom_height.parse::<usize>().unwrap_or(9);
+        let custom_mines = self.custom_mines.parse::<usize>().unwrap_or(10);
+
+        let custom_row = Row::new()
+            .spacing(8)
+            .align_items(Alignment::Center)
+            .push(text_input("宽", &self.custom_width).on_input(Message::CustomWidthChanged).width(Length::Fixed(60.0)))
+            .push(text_input("高", &self.custom_height).on_input(Message::CustomHeightChanged).width(Length::Fixed(60.0)))
+            .push(text_input("雷数", &self.custom_mines).on_input(Message::CustomMinesChanged).width(Length::Fixed(60.0)))
+            .push(button(text("Custom")).on_press(Message::StartGame(custom_width, custom_height, custom_mines)));
+
+        Column::new()
+            .spacing(16)
+            .align_items(Alignment::Center)
+            .padding(40)
+            .push(text("MineSweeper").size(32))
+            .push(preset_button("Easy 8x8 / 10", 8, 8, 10))
+            .push(preset_button("Medium 16x16 / 40", 16, 16, 40))
+            .push(preset_button("Expert 24x24 / 99", 24, 24, 99))
+            .push(custom_row)
+            .push(button(text("Play with friends...")).on_press(Message::OpenLobby))
+            .into()
+    }
+
+    /// 联机大厅：输入/生成Session id，创建或加入，看当前在线的玩家
+    fn view_lobby(&self)-> Element<'_, Message, Renderer<Theme>> {
+        let session_row = Row::new()
+            .spacing(8)
+            .align_items(Alignment::Center)
+            .push(text_input("Session id", &self.session_input).on_input(Message::SessionIdChanged).width(Length::Fixed(220.0)))
+            .push(button(text("Create")).on_press(Message::CreateSession))
+            .push(button(text("Join")).on_press(Message::JoinSession));
+
+        let players = self.connected_players.iter().fold(Column::new().spacing(4), |column, name| {
+            column.push(text(name))
+        });
+
+        Column::new()
+            .spacing(16)
+            .align_items(Alignment::Center)
+            .padding(40)
+            .push(text("Multiplayer Lobby").size(28))
+            .push(session_row)
+            .push(text(match &self.active_session {
+                Some(session_id) => format!("Joined session {}", session_id),
+                None => "Not joined yet".to_string(),
+            }))
+            .push(players)
+            .push(button(text("Back")).on_press(Message::LeaveLobby))
+            .into()
+    }
+
+    /// 原来的棋盘界面：头部数码管 + 笑脸 + 棋盘画布
+    fn view_playing(&self)-> Element<'_, Message, Renderer<Theme>> {
+        // 让棋盘区域(连带头部)始终是个正方形，边长取窗口短边；多出来的空间由下面的
+        // `container`居中留白当作letterbox边距，而不是把棋盘拉伸变形
+        let board_side = (self.size.0 as f32).min(self.size.1 as f32);
+        let size = (board_side, board_side);
+        let battleground = Battleground { board: Arc::clone(&self.board) };
+        let canvas = Canvas::new(battleground)
             .height(Length::Fixed(size.1 * 0.84))
             .width(Length::Fixed(size.1 * 0.84));
+
+        let (remaining_mines, smiley_label) = {
+            let board = self.board.lock().unwrap();
+            (
+                board.mines as i32 - board.flagged_count as i32,
+                SmileyState::from_board(&board).label(),
+            )
+        };
+        let mines_counter = Canvas::new(SevenSegmentCounter { value: remaining_mines })
+            .height(Length::Fixed(size.1 * 0.14))
+            .width(Length::Fixed(size.1 * 0.14 * 1.8));
+        let timer = Canvas::new(SevenSegmentCounter { value: self.elapsed_secs })
+            .height(Length::Fixed(size.1 * 0.14))
+            .width(Length::Fixed(size.1 * 0.14 * 1.8));
+        let smiley = button(text(smiley_label).horizontal_alignment(Horizontal::Center))
+            .on_press(Message::NewGame)
+            .width(Length::Fixed(size.1 * 0.14))
+            .height(Length::Fixed(size.1 * 0.14));
+        let header = Row::new()
+            .width(Length::Fixed(size.1 * 0.84))
+            .push(mines_counter)
+            .push(horizontal_space(Length::Fill))
+            .push(smiley)
+            .push(horizontal_space(Length::Fill))
+            .push(timer);
+
         let pad = Padding {
             top: size.1 * 0.08,
             bottom: size.1 * 0.08,
             left: size.0 * 0.04,
             right: size.0 * 0.04,
         };
-        Row::new()
+        let board = Row::new()
             .padding(pad)
-            .push(canvas)
+            .push(Column::new().push(header).push(canvas));
+
+        // 棋盘本身定死是个正方形，剩下的窗口空间(宽屏多出来的左右，或者竖屏多出来的上下)
+        // 就交给`container`居中，空出来的letterbox边距保持背景色，不跟着拉伸
+        container(board)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .center_x()
+            .center_y()
             .into()
     }
 
@@ -443,6 +1179,11 @@ fn get_window_size()-> (u32, u32) {
     (800, 500)
 }
 
+/// 命令行带了`-mute`就静音；不需要完整的参数解析器，就一个开关
+fn parse_mute_flag()-> bool {
+    std::env::args().any(|arg| arg == "-mute")
+}
+
 fn number_to_color(number: i32)-> Color {
     match number {
         1 => Color::new(0.08, 0.25, 1.00, 1.00), // 亮蓝色
@@ -457,56 +1198,15 @@ fn number_to_color(number: i32)-> Color {
     }
 }
 
-fn init_args() {
-    let args = std::env::args();
-
-    let difficulty = OnceCell::new();
-    let mut last = None::<String>;
-
-    for arg in args {
-        if let Some(v) = last.clone() {
-            match &v[..] {
-                "-size" => {
-                    SIZE.set(arg.parse::<usize>().unwrap()).unwrap();
-                },
-                "-difficulty" => {
-                    difficulty.set(arg.parse::<f64>().unwrap()).unwrap();
-                },
-                _ => {
-                    panic!("Unrecognized option");
-                },
-            }
-            last = None;
-        }
-        if arg.starts_with("-") {
-            last = Some(arg);
-        }
-    }
-
-    if let Some(_) = last {
-        panic!("A option has empty value");
-    }
-
-    let size = SIZE.get_or_init(|| 9);
-    let difficulty = difficulty.get_or_init(|| 0.2);
-
-    MINES.set(((size * size) as f64 * difficulty) as usize).unwrap();
-}
-
-static MINES: OnceCell<usize> = OnceCell::new();
-static SIZE: OnceCell<usize> = OnceCell::new();
-
 fn main()-> iced::Result {
-    init_args();
-
     MineSweeper::run(Settings {
         window: win::Settings {
             position: win::Position::Centered,
             size: get_window_size(),
-            resizable: false,
+            resizable: true,
             ..Default::default()
         },
-        flags: get_window_size(),
+        flags: (get_window_size(), parse_mute_flag()),
         ..Default::default()
     })
 }
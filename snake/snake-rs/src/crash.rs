@@ -0,0 +1,65 @@
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::{self, PanicInfo};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::game::last_snapshot;
+
+/// 注册一个会在崩溃时写结构化报告的panic hook，release构建下也生效，
+/// 取代之前只在debug下往panic.log塞一行`Error: {:?}`的简陋做法
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        let path = format!("crash-{}.toml", crash_id());
+        let report = build_report(info);
+
+        if fs::write(&path, report).is_ok() {
+            println!("Something went wrong and the game had to close.");
+            println!("A crash report was saved to {}; feel free to attach it if you file an issue.", path);
+        } else {
+            println!("Something went wrong and the game had to close, and the crash report could not be saved.");
+        }
+    }));
+}
+
+/// 拿纳秒级时间戳当报告文件名的一部分，不去引入专门的uuid依赖
+fn crash_id()-> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+fn panic_message(info: &PanicInfo)-> String {
+    if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+fn build_report(info: &PanicInfo)-> String {
+    let message = panic_message(info);
+    let location = info.location()
+        .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+        .unwrap_or_else(|| "unknown".to_string());
+    let backtrace = Backtrace::force_capture();
+    let snapshot = *last_snapshot().lock().unwrap();
+
+    format!(
+        "[panic]\nmessage = {:?}\nlocation = {:?}\n\n\
+         [build]\nos = {:?}\narch = {:?}\nversion = {:?}\n\n\
+         [game]\nboard_width = {}\nboard_height = {}\nscore = {}\n\n\
+         [backtrace]\ntext = {:?}\n",
+        message,
+        location,
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        env!("CARGO_PKG_VERSION"),
+        snapshot.map(|s| s.board_width).unwrap_or(0),
+        snapshot.map(|s| s.board_height).unwrap_or(0),
+        snapshot.map(|s| s.score).unwrap_or(0),
+        backtrace.to_string(),
+    )
+}
@@ -1,42 +1,24 @@
 use std::ops;
-use std::io::{Write, stdout};
+use std::fmt;
+use std::error::Error;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::fs::File;
+use std::path::Path;
 use std::time::{Duration, Instant};
-use std::sync::Arc;
-use crossterm::{
-    queue,
-    execute,
-    QueueableCommand,
-    ExecutableCommand,
-    terminal::{
-        Clear,
-        ClearType,
-        EnterAlternateScreen,
-        LeaveAlternateScreen,
-        BeginSynchronizedUpdate,
-        EndSynchronizedUpdate,
-        SetSize,
-        enable_raw_mode,
-        disable_raw_mode,
-    },
-    event::{
-        self,
-        Event,
-        KeyCode,
-    },
-    style::{
-        Print,
-    },
-    cursor::{
-        self,
-        MoveTo,
-    },
-};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::cursor;
+use termion::clear;
 use rand::prelude::*;
 use rand::thread_rng;
+use serde::{ Serialize, Deserialize };
 
+use crate::log::EventLog;
 
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Position(i16, i16);
 
 impl Position {
@@ -61,7 +43,7 @@ impl ops::Sub for Position {
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Right,
@@ -88,15 +70,34 @@ impl Direction {
             Direction::Left => Direction::Right,
         }
     }
+
+    fn to_byte(&self)-> u8 {
+        match *self {
+            Direction::Up => 0,
+            Direction::Right => 1,
+            Direction::Down => 2,
+            Direction::Left => 3,
+        }
+    }
+
+    fn from_byte(b: u8)-> io::Result<Self> {
+        match b {
+            0 => Ok(Direction::Up),
+            1 => Ok(Direction::Right),
+            2 => Ok(Direction::Down),
+            3 => Ok(Direction::Left),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown direction byte in save file")),
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Body {
     pub m_front: Direction,
     pub m_back: Direction,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Cell {
     Empty,
     Body(Body),
@@ -106,23 +107,45 @@ pub enum Cell {
 
 
 
+/// 渲染用的紧凑快照：终端渲染器和Web客户端画面都只需要这些信息
+/// `cells`按行优先顺序排列，每格是一个tag：0=空，1=蛇身，2=苹果，3=超级苹果
+#[derive(Serialize, Deserialize)]
+pub struct MapSnapshot {
+    pub width: i16,
+    pub height: i16,
+    pub cells: Vec<u8>,
+    pub head_pos: (i16, i16),
+    pub score: i32,
+}
+
+fn default_map_rng()-> StdRng {
+    StdRng::from_entropy()
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Map {
     m_vec: Vec<Cell>,
     m_head_pos: Position,
     m_hid_bodies: i16,
     m_width: i16,
     m_height: i16,
+    m_rng_seed: u64,
+    #[serde(skip, default = "default_map_rng")]
+    m_rng: StdRng,
 }
 
 impl Map {
     pub fn new(w: i16, h: i16, length: i16)-> Self {
         let headpos = Position(w/2, h/2);
+        let rng_seed: u64 = thread_rng().gen();
         let mut obj = Self {
             m_vec: vec![Cell::Empty; w as usize * h as usize],
             m_head_pos: headpos,
             m_hid_bodies: length - 1,
             m_width: w,
             m_height: h,
+            m_rng_seed: rng_seed,
+            m_rng: StdRng::seed_from_u64(rng_seed),
         };
         obj.generate_apple(false);
         *obj.get_mut(headpos).unwrap() = Cell::Body(Body {
@@ -172,6 +195,40 @@ impl Map {
         c
     }
 
+    pub fn head_pos(&self)-> Position {
+        self.m_head_pos
+    }
+
+    /// 蛇头当前朝向的方向，日志靠它记录每个tick的朝向
+    pub fn head_direction(&self)-> Direction {
+        match self.get(self.m_head_pos) {
+            Some(Cell::Body(body)) => body.m_front,
+            _ => Direction::Up,
+        }
+    }
+
+    pub fn rng_seed(&self)-> u64 {
+        self.m_rng_seed
+    }
+
+    /// 拍平成渲染用的快照，终端绘制和Web客户端画面都从这份数据出发，不用各自读`Cell`
+    pub fn to_snapshot(&self)-> MapSnapshot {
+        let cells = self.m_vec.iter().map(|cell| match cell {
+            Cell::Empty => 0,
+            Cell::Body(_) => 1,
+            Cell::Apple => 2,
+            Cell::SuperApple => 3,
+        }).collect();
+
+        MapSnapshot {
+            width: self.m_width,
+            height: self.m_height,
+            cells,
+            head_pos: (self.m_head_pos.0, self.m_head_pos.1),
+            score: self.count_bodies(),
+        }
+    }
+
     fn find_head(&mut self)-> Result<&mut Body, String> {
         if let Cell::Body(head) = &mut self.m_vec[self.m_head_pos.as_1d(self.m_width)] {
             Ok(head)
@@ -181,7 +238,6 @@ impl Map {
     }
 
     pub fn generate_apple(&mut self, force: bool)-> bool {
-        let mut rng = thread_rng();
         let mut has_apple = false;
         let mut is_full = true;
 
@@ -201,10 +257,11 @@ impl Map {
         }
         if force || !has_apple {
             loop {
-                let pos = Position(rng.gen_range(0..self.m_width), rng.gen_range(0..self.m_height));
+                let pos = Position(self.m_rng.gen_range(0..self.m_width), self.m_rng.gen_range(0..self.m_height));
+                let is_super = self.m_rng.gen_range(0..10) > 8;
                 let val = self.get_mut(pos).unwrap();
                 if let Cell::Empty = val {
-                    *val = if rng.gen_range(0..10) > 8 { Cell::SuperApple } else { Cell::Apple };
+                    *val = if is_super { Cell::SuperApple } else { Cell::Apple };
                     return true
                 }
             }
@@ -213,7 +270,58 @@ impl Map {
         }
     }
 
-    pub fn update(&mut self)-> Result<(), String> {
+    /// 按头到尾的顺序收集蛇身段的位置，存档按这个顺序写入身体
+    fn body_positions(&self)-> Vec<Position> {
+        let mut positions = vec![self.m_head_pos];
+        let mut cur = self.m_head_pos;
+        loop {
+            let back = match self.get(cur) {
+                Some(Cell::Body(body)) => cur + body.m_back.to_distance(1),
+                _ => break,
+            };
+            if positions.contains(&back) {
+                break;
+            }
+            positions.push(back);
+            cur = back;
+        }
+        positions
+    }
+
+    /// 从存档数据重建地图，跳过`new`里随机生成头部/苹果的那一套
+    fn restore(
+        width: i16,
+        height: i16,
+        hid_bodies: i16,
+        rng_seed: u64,
+        bodies: Vec<(Position, Direction, Direction)>,
+        foods: Vec<(Position, bool)>,
+    )-> Self {
+        let mut vec = vec![Cell::Empty; width as usize * height as usize];
+        let head_pos = bodies.first().map(|(pos, _, _)| *pos).unwrap_or(Position(0, 0));
+
+        for (pos, front, back) in &bodies {
+            let idx = pos.as_1d(width);
+            vec[idx] = Cell::Body(Body { m_front: *front, m_back: *back });
+        }
+        for (pos, is_super) in &foods {
+            let idx = pos.as_1d(width);
+            vec[idx] = if *is_super { Cell::SuperApple } else { Cell::Apple };
+        }
+
+        Self {
+            m_vec: vec,
+            m_head_pos: head_pos,
+            m_hid_bodies: hid_bodies,
+            m_width: width,
+            m_height: height,
+            m_rng_seed: rng_seed,
+            m_rng: StdRng::seed_from_u64(rng_seed),
+        }
+    }
+
+    /// 推进一个tick；`Ok(true)`表示这一tick吃到了食物，`Ok(false)`表示只是普通移动
+    pub fn update(&mut self)-> Result<bool, GameError> {
         let front = self.find_head().unwrap().m_front;
         let front_pos = self.m_head_pos + front.to_distance(1);
 
@@ -226,7 +334,7 @@ impl Map {
         }
 
         if !has_empty {
-            return Err("Game over!".to_string());
+            return Err(GameError::BoardFull);
         }
 
         // 判断撞到了什么东西
@@ -242,7 +350,7 @@ impl Map {
                 // 生成新的苹果
                 self.generate_apple(false);
 
-                Ok(())
+                Ok(true)
             },
 
             Some(Cell::SuperApple) => {
@@ -256,7 +364,7 @@ impl Map {
                 // 生成新的苹果
                 self.generate_apple(false);
 
-                Ok(())
+                Ok(true)
             },
 
             // 撞到了空气
@@ -306,17 +414,112 @@ impl Map {
                     self.m_vec.swap(self.m_head_pos.as_1d(self.m_width), front_pos.as_1d(self.m_width));
                 }
                 self.m_head_pos = front_pos;
-                Ok(())
+                Ok(false)
             },
 
             // 撞到自己了
-            Some(Cell::Body(_)) => Err("Snake crashed into itself".to_string()),
-            None => Err("Snake crashed into the border".to_string()),
+            Some(Cell::Body(_)) => Err(GameError::SelfCollision),
+            None => Err(GameError::WallCollision),
+        }
+    }
+}
+
+
+
+/// `SnakeGame::game_loop`结束时的具体原因；撞墙/撞自己/吃满全图/主动退出都是正常的对局结局，
+/// 只有`Io`是真正意料之外的错误(往终端输出画面失败，或者打不开日志文件)
+#[derive(Debug)]
+pub enum GameError {
+    SelfCollision,
+    WallCollision,
+    BoardFull,
+    QuitByUser,
+    Io(io::Error),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>)-> fmt::Result {
+        match self {
+            GameError::SelfCollision => write!(f, "the snake crashed into itself"),
+            GameError::WallCollision => write!(f, "the snake crashed into the border"),
+            GameError::BoardFull => write!(f, "the board is completely filled"),
+            GameError::QuitByUser => write!(f, "the player quit"),
+            GameError::Io(e) => write!(f, "an I/O error occurred: {}", e),
+        }
+    }
+}
+
+impl Error for GameError {
+    fn source(&self)-> Option<&(dyn Error + 'static)> {
+        match self {
+            GameError::Io(e) => Some(e),
+            _ => None,
         }
     }
 }
 
+impl From<io::Error> for GameError {
+    fn from(e: io::Error)-> Self {
+        GameError::Io(e)
+    }
+}
+
+/// 一局结束时的结果：最终比分和导致结局的原因
+pub struct GameOutcome {
+    pub score: i32,
+    pub reason: GameError,
+}
+
+/// 最近一次渲染时的对局状态快照，panic hook靠它在崩溃报告里附上场地尺寸和得分
+#[derive(Clone, Copy)]
+pub struct GameSnapshot {
+    pub board_width: i16,
+    pub board_height: i16,
+    pub score: i32,
+}
+
+/// 崩溃时能读到的最后一份`GameSnapshot`；每帧渲染都会更新它
+pub(crate) fn last_snapshot()-> &'static Mutex<Option<GameSnapshot>> {
+    static INSTANCE: OnceLock<Mutex<Option<GameSnapshot>>> = OnceLock::new();
+    INSTANCE.get_or_init(|| Mutex::new(None))
+}
+
+/// 存档文件的格式版本；字段布局变了就加一，`SnakeGame::load`据此拒绝读不懂的旧存档
+const SAVE_FORMAT_VERSION: u8 = 1;
+
+fn read_u8<R: Read>(r: &mut R)-> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
 
+fn read_i16<R: Read>(r: &mut R)-> io::Result<i16> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(i16::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(r: &mut R)-> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R)-> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// `run_loop`每一轮要处理的动作：退出、转向，或是什么都没发生的一次心跳
+/// `Save`不属于原本这三种之列，是为了不丢掉已有的快捷存档功能而加的第四种
+#[derive(Debug)]
+pub enum Command {
+    Quit,
+    Turn(Direction),
+    Save,
+    Tick,
+}
 
 pub struct SnakeGame {
     m_map: Map,
@@ -324,23 +527,20 @@ pub struct SnakeGame {
     m_height: i16,
 
     pub config_freeze_screen: bool,
+    pub config_save_path: String,
+    pub config_log_path: Option<String>,
 }
 
 impl SnakeGame {
     pub fn new(w: i16, h: i16, len: i16)-> Self {
-        execute!(
-            stdout(),
-            EnterAlternateScreen,
-            cursor::Hide,
-            //SetSize(w as u16 * 2 + 1, h as u16 + 2),
-        ).unwrap();
-        enable_raw_mode().unwrap();
         Self {
             m_map: Map::new(w, h, len),
             m_width: w,
             m_height: h,
 
             config_freeze_screen: true,
+            config_save_path: "snake.save".to_string(),
+            config_log_path: None,
         }
     }
 
@@ -348,150 +548,236 @@ impl SnakeGame {
         self.m_map.count_bodies()
     }
 
-    pub fn game_loop(&mut self)-> Result<(), String> {
-        let draw = |this: &Self| {
-            let mut stdout = stdout();
-            stdout.queue(BeginSynchronizedUpdate).unwrap();
+    /// 把当前对局写到`path`：版本号、场地尺寸、还没长出来的身体段数、RNG种子，然后是按头到尾顺序排列的蛇身段(位置+朝向)，
+    /// 最后是场上的食物(位置+种类)，身体段数和食物段数都带长度前缀
+    pub fn save(&self, path: &Path)-> io::Result<()> {
+        let map = &self.m_map;
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(&[SAVE_FORMAT_VERSION])?;
+        out.write_all(&map.m_width.to_le_bytes())?;
+        out.write_all(&map.m_height.to_le_bytes())?;
+        out.write_all(&map.m_hid_bodies.to_le_bytes())?;
+        out.write_all(&map.m_rng_seed.to_le_bytes())?;
+
+        let bodies = map.body_positions();
+        out.write_all(&(bodies.len() as u32).to_le_bytes())?;
+        for pos in &bodies {
+            let Cell::Body(body) = map.get(*pos).unwrap() else {
+                unreachable!("body_positions returned a non-body cell");
+            };
+            out.write_all(&pos.0.to_le_bytes())?;
+            out.write_all(&pos.1.to_le_bytes())?;
+            out.write_all(&[body.m_front.to_byte(), body.m_back.to_byte()])?;
+        }
+
+        let foods: Vec<(Position, bool)> = map.m_vec.iter().enumerate().filter_map(|(idx, cell)| {
+            let is_super = match cell {
+                Cell::Apple => false,
+                Cell::SuperApple => true,
+                _ => return None,
+            };
+            Some((Position((idx as i16) % map.m_width, (idx as i16) / map.m_width), is_super))
+        }).collect();
+        out.write_all(&(foods.len() as u32).to_le_bytes())?;
+        for (pos, is_super) in &foods {
+            out.write_all(&pos.0.to_le_bytes())?;
+            out.write_all(&pos.1.to_le_bytes())?;
+            out.write_all(&[*is_super as u8])?;
+        }
 
-            queue!(
-                stdout,
-                MoveTo(0, 0),
-                Print({
-                    let mut s = "╔".to_string();
-                    for _ in 0..this.m_width {
-                        s += "══";
-                    }
-                    s += "╗";
-                    s
-                }),
-            ).unwrap();
-
-            for y in 0..(this.m_height as u16) {
-                let mut row = "║".to_string();
-                for x in 0..(this.m_width as u16) {
-                    let val = this.m_map.get(Position(x as i16, y as i16));
-                    match val.unwrap() {
-                        Cell::Empty => {
-                            row += "  ";
-                        },
-                        Cell::Body(_) => {
-                            row += if this.m_map.m_head_pos == Position(x as i16, y as i16) {
-                                "🐍"
-                            } else {
-                                "🌳"
-                            };
-                        },
-                        Cell::Apple => {
-                            row += "🍎";
-                        },
-                        Cell::SuperApple => {
-                            row += "🐔";
-                        }
-                    }
-                }
-                row += "║";
-                queue!(
-                    stdout,
-                    MoveTo(0, y as u16 + 1),
-                    Print(row),
-                ).unwrap()
-            }
+        out.flush()
+    }
 
-            queue!(
-                stdout,
-                MoveTo(0, this.m_height as u16 + 1),
-                Print({
-                    let mut s = "╚".to_string();
-                    for _ in 0..this.m_width {
-                        s += "══";
-                    }
-                    s += "╝";
-                    s
-                }),
-            ).unwrap();
+    /// 从`path`读回一局存档；版本号不匹配或数据损坏时返回`Err`，不会读出一个半成品的`SnakeGame`
+    pub fn load(path: &Path)-> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
 
-            stdout.queue(EndSynchronizedUpdate).unwrap();
+        let version = read_u8(&mut input)?;
+        if version != SAVE_FORMAT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported save format version {}", version)));
+        }
 
-            stdout.flush().unwrap();
+        let width = read_i16(&mut input)?;
+        let height = read_i16(&mut input)?;
+        if width <= 0 || height <= 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid board size in save file"));
+        }
+        let check_pos = |x: i16, y: i16| if x < 0 || y < 0 || x >= width || y >= height {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "position out of bounds in save file"))
+        } else {
+            Ok(Position(x, y))
         };
+        let hid_bodies = read_i16(&mut input)?;
+        let rng_seed = read_u64(&mut input)?;
+
+        let body_count = read_u32(&mut input)?;
+        if body_count == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "save file has no snake body"));
+        }
+        let mut bodies = Vec::new();
+        for _ in 0..body_count {
+            let x = read_i16(&mut input)?;
+            let y = read_i16(&mut input)?;
+            let front = Direction::from_byte(read_u8(&mut input)?)?;
+            let back = Direction::from_byte(read_u8(&mut input)?)?;
+            bodies.push((check_pos(x, y)?, front, back));
+        }
+
+        let food_count = read_u32(&mut input)?;
+        let mut foods = Vec::new();
+        for _ in 0..food_count {
+            let x = read_i16(&mut input)?;
+            let y = read_i16(&mut input)?;
+            let is_super = read_u8(&mut input)? != 0;
+            foods.push((check_pos(x, y)?, is_super));
+        }
 
-        let pause = Arc::new(|| {
+        Ok(Self {
+            m_map: Map::restore(width, height, hid_bodies, rng_seed, bodies, foods),
+            m_width: width,
+            m_height: height,
+
+            config_freeze_screen: true,
+            config_save_path: path.to_string_lossy().into_owned(),
+            config_log_path: None,
+        })
+    }
+
+    /// 把当前棋盘画到`writer`上；画之前顺手把状态记到`last_snapshot`，崩溃报告靠它拿到最后一局的场地/得分
+    fn draw<W: Write>(&self, writer: &mut W)-> io::Result<()> {
+        let snapshot = self.m_map.to_snapshot();
+
+        *last_snapshot().lock().unwrap() = Some(GameSnapshot {
+            board_width: snapshot.width,
+            board_height: snapshot.height,
+            score: snapshot.score,
         });
 
-        let process_event = |this: &mut Self, e: Event| {
-            match e {
-                Event::FocusLost => {
-                    pause();
-                },
-                Event::Resize(_, _) => {
-                    //stdout().execute(Clear(ClearType::All)).unwrap();
-                },
-                Event::Key(kevent) => {
-                    match kevent.code.clone() {
-                        KeyCode::Up => {
-                            this.m_map.turn(Direction::Up);
-                        },
-                        KeyCode::Left => {
-                            this.m_map.turn(Direction::Left);
-                        },
-                        KeyCode::Right => {
-                            this.m_map.turn(Direction::Right);
-                        },
-                        KeyCode::Down => {
-                            this.m_map.turn(Direction::Down);
-                        },
-                        KeyCode::Char(c) => {
-                            match c {
-                                'Q' | 'q' => {
-                                    return true;
-                                },
-                                _ => {},
-                            }
-                        }
-                        _ => {},
-                    }
-                },
-                _ => {},
+        write!(writer, "{}╔{}╗", cursor::Goto(1, 1), "══".repeat(snapshot.width as usize))?;
+
+        for y in 0..(snapshot.height as u16) {
+            write!(writer, "{}║", cursor::Goto(1, y + 2))?;
+            for x in 0..(snapshot.width as u16) {
+                let idx = x as usize + y as usize * snapshot.width as usize;
+                let is_head = snapshot.head_pos == (x as i16, y as i16);
+                let cell = match snapshot.cells[idx] {
+                    0 => "  ",
+                    1 => if is_head { "🐍" } else { "🌳" },
+                    2 => "🍎",
+                    3 => "🐔",
+                    _ => unreachable!("Unknown cell tag in MapSnapshot"),
+                };
+                write!(writer, "{}", cell)?;
             }
-            false
-        };
+            write!(writer, "║")?;
+        }
+
+        write!(writer, "{}╚{}╝", cursor::Goto(1, snapshot.height as u16 + 2), "══".repeat(snapshot.width as usize))?;
+
+        writer.flush()
+    }
+
+    /// 读键盘的那根线程：把`reader`里的按键翻译成`Command`送进`command_sender`，
+    /// 上下左右和WASD都能转向，`q`/Ctrl-C清出一局，Ctrl-S触发快捷存档
+    fn spawn_key_reader<R>(reader: R, command_sender: mpsc::Sender<Command>)
+    where
+        R: Read + Send + 'static,
+    {
+        thread::spawn(move || {
+            for key in reader.keys() {
+                let command = match key {
+                    Ok(Key::Char('q')) | Ok(Key::Char('Q')) | Ok(Key::Ctrl('c')) => Command::Quit,
+                    Ok(Key::Up) | Ok(Key::Char('w')) | Ok(Key::Char('W')) => Command::Turn(Direction::Up),
+                    Ok(Key::Down) | Ok(Key::Char('s')) | Ok(Key::Char('S')) => Command::Turn(Direction::Down),
+                    Ok(Key::Left) | Ok(Key::Char('a')) | Ok(Key::Char('A')) => Command::Turn(Direction::Left),
+                    Ok(Key::Right) | Ok(Key::Char('d')) | Ok(Key::Char('D')) => Command::Turn(Direction::Right),
+                    Ok(Key::Ctrl('s')) => Command::Save,
+                    Ok(_) => continue,
+                    Err(_) => break,
+                };
+                if command_sender.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// 跑一局：`reader`喂按键，`writer`收画面，两边都只要求实现标准的`Read`/`Write`，
+    /// 不用关心调用方接的到底是真终端还是测试里的一段录制数据
+    /// 地图的推进仍然每250ms一次，但每一轮都会先从`reader`那头的线程收一条`Command`，
+    /// 画面只在地图真的变化(转向和存档都不算，只有推进才算)之后才重绘一次
+    pub fn run_loop<R, W>(&mut self, reader: R, mut writer: W)-> Result<GameOutcome, GameError>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        write!(writer, "{}{}", clear::All, cursor::Hide)?;
+        self.draw(&mut writer)?;
+
+        let mut event_log = self.config_log_path.as_ref()
+            .map(|path| EventLog::open(path))
+            .transpose()?;
+        if let Some(log) = &mut event_log {
+            let _ = log.log_event(&format!("rng_seed={}", self.m_map.rng_seed()));
+        }
+
+        let (command_sender, command_receiver) = mpsc::channel::<Command>();
+        Self::spawn_key_reader(reader, command_sender);
 
         let mut timer = Duration::ZERO;
-        loop {
+        let outcome = loop {
             let begin = Instant::now();
-            if let Ok(okay) = event::poll(Duration::from_millis(50)) {
-                if okay {
-                    if let Ok(event) = event::read() {
-                        if process_event(self, event) == true {
-                            break;
-                        }
-                    }
-                } else {
+
+            let command = match command_receiver.recv_timeout(Duration::from_millis(50)) {
+                Ok(command) => command,
+                Err(mpsc::RecvTimeoutError::Timeout) => Command::Tick,
+                Err(mpsc::RecvTimeoutError::Disconnected) => Command::Tick,
+            };
+
+            if !matches!(command, Command::Tick) {
+                if let Some(log) = &mut event_log {
+                    let _ = log.log_input(&format!("{:?}", command));
                 }
-            } else {
-                break;
             }
-            timer += begin.elapsed();
 
+            match command {
+                Command::Quit => {
+                    if let Some(log) = &mut event_log {
+                        let _ = log.log_event("quit by user");
+                    }
+                    break Ok(GameOutcome { score: self.count_bodies(), reason: GameError::QuitByUser });
+                },
+                Command::Turn(direction) => {
+                    self.m_map.turn(direction);
+                },
+                Command::Save => {
+                    let _ = self.save(Path::new(&self.config_save_path));
+                },
+                Command::Tick => {},
+            }
+
+            timer += begin.elapsed();
             if timer >= Duration::from_millis(250) {
-                self.m_map.update()?;
                 timer = Duration::ZERO;
+                match self.m_map.update() {
+                    Ok(ate_food) => {
+                        if let Some(log) = &mut event_log {
+                            let _ = log.log_tick(self.m_map.head_pos(), self.m_map.head_direction(), ate_food, self.count_bodies());
+                        }
+                    },
+                    Err(reason) => {
+                        if let Some(log) = &mut event_log {
+                            let _ = log.log_event(&format!("game ended: {}", reason));
+                        }
+                        break Ok(GameOutcome { score: self.count_bodies(), reason });
+                    },
+                }
+                self.draw(&mut writer)?;
             }
+        };
 
-            draw(&self);
-        }
-
-        Ok(())
-    }
-}
-
-impl ops::Drop for SnakeGame {
-    fn drop(&mut self) {
-        disable_raw_mode().unwrap();
-        execute!(
-            stdout(),
-            cursor::Show,
-            LeaveAlternateScreen
-        ).unwrap();
+        write!(writer, "{}", cursor::Show)?;
+        outcome
     }
 }
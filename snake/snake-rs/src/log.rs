@@ -0,0 +1,42 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+
+use crate::game::{Direction, Position};
+
+/// 逐tick的事件日志：配合存档里的RNG种子，崩溃或者异常结束之前发生的一切原则上都能照着重建一遍，
+/// 也是未来做回放的基础
+pub struct EventLog {
+    m_writer: BufWriter<File>,
+    m_tick: u64,
+}
+
+impl EventLog {
+    /// 以追加方式打开`path`，文件不存在就新建
+    pub fn open(path: &str)-> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { m_writer: BufWriter::new(file), m_tick: 0 })
+    }
+
+    /// 记录这一tick收到的输入(按键、存档请求等)，打`[input]`标签
+    pub fn log_input(&mut self, description: &str)-> io::Result<()> {
+        writeln!(self.m_writer, "[input] tick={} {}", self.m_tick, description)?;
+        self.m_writer.flush()
+    }
+
+    /// 记录一次地图推进：蛇头位置、朝向、这一tick有没有吃到食物、当前得分，打`[tick]`标签，随后tick计数加一
+    pub fn log_tick(&mut self, head: Position, direction: Direction, ate_food: bool, score: i32)-> io::Result<()> {
+        writeln!(
+            self.m_writer,
+            "[tick] tick={} head={:?} direction={:?} ate_food={} score={}",
+            self.m_tick, head, direction, ate_food, score,
+        )?;
+        self.m_tick += 1;
+        self.m_writer.flush()
+    }
+
+    /// 记录不属于具体某次推进的事件，比如开局种子、退出原因，打`[event]`标签
+    pub fn log_event(&mut self, description: &str)-> io::Result<()> {
+        writeln!(self.m_writer, "[event] tick={} {}", self.m_tick, description)?;
+        self.m_writer.flush()
+    }
+}
@@ -1,40 +1,45 @@
 pub mod game;
+mod crash;
+mod log;
+
+use game::GameError;
+use std::io;
+use std::path::Path;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
 
 fn main() {
-    #[cfg(debug_assertions)]
-    {
-    use std::panic::set_hook;
-    use std::fs::OpenOptions;
-    use std::io::Write;
-
-    set_hook(Box::new(|info| {
-        let f_ret = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("panic.log");
-        if let Ok(mut f) = f_ret {
-            let _ = f.write_all(format!("Error: {:?}", info).as_bytes());
-        }
-    }));
-    }
+    crash::install_panic_hook();
 
-    let err;
-    let count;
-    {
-        let mut no_game_no_life = game::SnakeGame::new(20, 20, 2);
+    let args: Vec<String> = std::env::args().collect();
+    let load_path = args.iter().position(|a| a == "--load").and_then(|i| args.get(i + 1));
 
-        err = no_game_no_life.game_loop();
+    let mut no_game_no_life = match load_path {
+        Some(path) => match game::SnakeGame::load(Path::new(path)) {
+            Ok(game) => game,
+            Err(e) => {
+                println!("Failed to load save at {}: {}\nStarting a new game instead.", path, e);
+                game::SnakeGame::new(20, 20, 2)
+            },
+        },
+        None => game::SnakeGame::new(20, 20, 2),
+    };
 
-        count = no_game_no_life.count_bodies();
-    }
+    no_game_no_life.config_log_path = args.iter().position(|a| a == "--log")
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+        .or_else(|| std::env::var("SNAKE_LOG").ok());
+
+    let stdout = io::stdout().into_raw_mode().unwrap();
+    let screen = AlternateScreen::from(stdout);
+
+    let result = no_game_no_life.run_loop(io::stdin(), screen);
 
-    if let Err(msg) = err {
-        if msg == "Game over!" {
-            println!("Game over, Score: {}", count);
-        } else {
-            println!("Game over, Score: {}\nBecause {}", count, msg);
-        }
-    } else {
-        println!("Game over, Score: {}", count);
+    match result {
+        Ok(outcome) => match outcome.reason {
+            GameError::QuitByUser => println!("Game over, Score: {}", outcome.score),
+            reason => println!("Game over, Score: {}\nBecause {}", outcome.score, reason),
+        },
+        Err(e) => println!("Game over, Score: {}\nBecause {}", no_game_no_life.count_bodies(), e),
     }
 }
@@ -7,12 +7,73 @@ use std::cell::Cell as StdCell;
 use crossterm::style::{Color};
 use lazy_static::lazy_static;
 use rand::prelude::*;
+use serde::{Serialize, Deserialize};
 
 pub static BRICK_GRID_SIZE: u16 = 4;
 
+/// `crossterm::style::Color`不是`Serialize`/`Deserialize`，这里通过命名的方式把它桥接到serde上
+/// 配合`#[serde(with = "color_serde")]`在`Block`等结构体上使用
+pub mod color_serde {
+    use super::Color;
+    use serde::{Serializer, Deserializer, de::Error};
+
+    pub fn color_from_name(name: &str)-> Result<Color, String> {
+        Ok(match name.to_lowercase().as_str() {
+            "black" => Color::Black,
+            "dark_grey" | "dark_gray" => Color::DarkGrey,
+            "red" => Color::Red,
+            "dark_red" => Color::DarkRed,
+            "green" => Color::Green,
+            "dark_green" => Color::DarkGreen,
+            "yellow" => Color::Yellow,
+            "dark_yellow" => Color::DarkYellow,
+            "blue" => Color::Blue,
+            "dark_blue" => Color::DarkBlue,
+            "magenta" => Color::Magenta,
+            "dark_magenta" => Color::DarkMagenta,
+            "cyan" => Color::Cyan,
+            "dark_cyan" => Color::DarkCyan,
+            "white" => Color::White,
+            "grey" | "gray" => Color::Grey,
+            other => return Err(format!("Unknown color name '{}'", other)),
+        })
+    }
+
+    pub fn color_to_name(c: &Color)-> &'static str {
+        match c {
+            Color::Black => "black",
+            Color::DarkGrey => "dark_grey",
+            Color::Red => "red",
+            Color::DarkRed => "dark_red",
+            Color::Green => "green",
+            Color::DarkGreen => "dark_green",
+            Color::Yellow => "yellow",
+            Color::DarkYellow => "dark_yellow",
+            Color::Blue => "blue",
+            Color::DarkBlue => "dark_blue",
+            Color::Magenta => "magenta",
+            Color::DarkMagenta => "dark_magenta",
+            Color::Cyan => "cyan",
+            Color::DarkCyan => "dark_cyan",
+            Color::White => "white",
+            Color::Grey => "grey",
+            _ => "white",
+        }
+    }
+
+    pub fn serialize<S: Serializer>(c: &Color, s: S)-> Result<S::Ok, S::Error> {
+        s.serialize_str(color_to_name(c))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D)-> Result<Color, D::Error> {
+        let name = String::deserialize(d)?;
+        color_from_name(&name).map_err(Error::custom)
+    }
+}
+
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -48,6 +109,16 @@ impl Direction {
             }
         }
     }
+
+    /// 朝该方向平移一格对应的坐标偏移量
+    pub fn delta(&self)-> Position {
+        match *self {
+            Direction::Up => Position(0, -1),
+            Direction::Down => Position(0, 1),
+            Direction::Left => Position(-1, 0),
+            Direction::Right => Position(1, 0),
+        }
+    }
 }
 
 impl Default for Direction {
@@ -60,7 +131,7 @@ impl Default for Direction {
 
 
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Cell {
     m_block: Option<Block>,
 }
@@ -120,8 +191,9 @@ impl Default for Cell {
 
 
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Block {
+    #[serde(with = "color_serde")]
     pub m_color: Color,
 }
 
@@ -129,7 +201,7 @@ pub struct Block {
 
 
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Grid2D<T> where T: Sized {
     m_vec: Vec<T>,
     m_width: u16,
@@ -203,6 +275,93 @@ impl<T> Grid2D<T> where T: Sized {
     pub fn height(&self)-> u16 {
         self.m_height
     }
+
+    /// 按行优先顺序遍历每一个格子
+    pub fn for_each(&self, mut f: impl FnMut(u16, u16, &T)) {
+        for y in 0..self.m_height {
+            for x in 0..self.m_width {
+                f(x, y, self.get(x, y).unwrap());
+            }
+        }
+    }
+
+    /// 按行优先顺序遍历每一个格子的可变引用
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(u16, u16, &mut T)) {
+        for y in 0..self.m_height {
+            for x in 0..self.m_width {
+                f(x, y, self.get_mut(x, y).unwrap());
+            }
+        }
+    }
+
+    /// 将一个格子的内容移动到另一个格子，源格子被替换为默认值
+    pub fn move_cell(&mut self, from: Position, to: Position) where T: Default {
+        let value = std::mem::take(self.get_mut(from.0 as u16, from.1 as u16).unwrap());
+        *self.get_mut(to.0 as u16, to.1 as u16).unwrap() = value;
+    }
+}
+
+impl<T> Grid2D<T> where T: Sized + Clone {
+    /// 用给定的值填满整个网格
+    pub fn fill(&mut self, value: &T) {
+        for cell in self.m_vec.iter_mut() {
+            *cell = value.clone();
+        }
+    }
+
+    /// 拍摄一份当前内容的快照，之后可用`restore`还原
+    pub fn snapshot(&self)-> Grid2D<T> {
+        self.clone()
+    }
+
+    /// 从快照还原内容，要求尺寸一致
+    pub fn restore(&mut self, snapshot: &Grid2D<T>) {
+        assert_eq!(self.m_width, snapshot.m_width, "restore: width mismatch");
+        assert_eq!(self.m_height, snapshot.m_height, "restore: height mismatch");
+        self.m_vec.clone_from(&snapshot.m_vec);
+    }
+
+    /// 将`other`盖印到`self`上，`at`为`other`左上角在`self`中的坐标
+    /// `overwrite`为false时，`other`里被`should_skip`判定为要跳过的格子不拷贝，目标格子保持原值
+    pub fn stamp(&mut self, other: &Grid2D<T>, at: Position, overwrite: bool, should_skip: impl Fn(&T)-> bool) {
+        for y in 0..other.m_height {
+            for x in 0..other.m_width {
+                let tx = at.0 + x as i16;
+                let ty = at.1 + y as i16;
+                if tx < 0 || ty < 0 || tx as u16 >= self.m_width || ty as u16 >= self.m_height {
+                    continue;
+                }
+                let value = other.get(x, y).unwrap();
+                if !overwrite && should_skip(value) {
+                    continue;
+                }
+                *self.get_mut(tx as u16, ty as u16).unwrap() = value.clone();
+            }
+        }
+    }
+
+    /// 旋转90度，返回一个新的网格
+    /// clockwise为true时顺时针旋转，否则逆时针
+    pub fn rotate90(&self, clockwise: bool)-> Grid2D<T> {
+        let w = self.m_width;
+        let h = self.m_height;
+        let new_w = h;
+        let mut data: Vec<Option<T>> = vec![None; (w * h) as usize];
+        for x in 0..w {
+            for y in 0..h {
+                let value = self.get(x, y).unwrap().clone();
+                let (nx, ny) = if clockwise {
+                    (h - 1 - y, x)
+                } else {
+                    (y, w - 1 - x)
+                };
+                let idx = (ny * new_w + nx) as usize;
+                data[idx] = Some(value);
+            }
+        }
+        let data: Vec<T> = data.into_iter().map(|v| v.unwrap()).collect();
+        Grid2D::with_data(h, w, data).unwrap()
+    }
 }
 
 
@@ -213,14 +372,129 @@ fn new_brick_grid()-> Grid2D<Cell> {
     Grid2D::<Cell>::new(BRICK_GRID_SIZE, BRICK_GRID_SIZE)
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Position(pub i16, pub i16);
 
+impl Position {
+    /// 返回朝某个方向平移一格后的坐标
+    pub fn step(&self, d: Direction)-> Position {
+        *self + d.delta()
+    }
+
+    pub fn left(&self)-> Position {
+        self.step(Direction::Left)
+    }
+
+    pub fn right(&self)-> Position {
+        self.step(Direction::Right)
+    }
+
+    pub fn up(&self)-> Position {
+        self.step(Direction::Up)
+    }
+
+    pub fn down(&self)-> Position {
+        self.step(Direction::Down)
+    }
+
+    /// 转换为网格坐标，任意一维为负数时返回None
+    pub fn to_grid(&self)-> Option<(u16, u16)> {
+        if self.0 < 0 || self.1 < 0 {
+            None
+        } else {
+            Some((self.0 as u16, self.1 as u16))
+        }
+    }
+}
+
+impl Add for Position {
+    type Output = Position;
+
+    fn add(self, rhs: Position)-> Position {
+        Position(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl Sub for Position {
+    type Output = Position;
+
+    fn sub(self, rhs: Position)-> Position {
+        Position(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl Mul<i16> for Position {
+    type Output = Position;
+
+    fn mul(self, rhs: i16)-> Position {
+        Position(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+/// 方块的种类，决定旋转时使用哪张踢墙表
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BrickKind {
+    I, O, S, Z, J, L, T,
+    /// 通过`ActiveBrick::from_ascii`/`random_brick_from`加载的自定义方块
+    Custom,
+}
+
+/// 一次踢墙测试的偏移表，按尝试顺序排列，第一个总是(0,0)
+type KickTable = [(i16, i16); 5];
+
+// 标准SRS踢墙表（J/L/S/T/Z共用），键为(起始朝向, 目标朝向)
+// Guideline原表的y轴朝上为正，这里的棋盘y轴是朝下为正(见Direction::Down对应Position(0,1))，
+// 所以每一项的y分量都相对原表取反，不然带y偏移的踢墙测试全都会往反方向踢
+const JLSTZ_KICK_0R: KickTable = [(0,0), (-1,0), (-1,-1), (0,2), (-1,2)];
+const JLSTZ_KICK_R0: KickTable = [(0,0), (1,0), (1,1), (0,-2), (1,-2)];
+const JLSTZ_KICK_R2: KickTable = [(0,0), (1,0), (1,1), (0,-2), (1,-2)];
+const JLSTZ_KICK_2R: KickTable = [(0,0), (-1,0), (-1,-1), (0,2), (-1,2)];
+const JLSTZ_KICK_2L: KickTable = [(0,0), (1,0), (1,-1), (0,2), (1,2)];
+const JLSTZ_KICK_L2: KickTable = [(0,0), (-1,0), (-1,1), (0,-2), (-1,-2)];
+const JLSTZ_KICK_L0: KickTable = [(0,0), (-1,0), (-1,1), (0,-2), (-1,-2)];
+const JLSTZ_KICK_0L: KickTable = [(0,0), (1,0), (1,-1), (0,2), (1,2)];
+
+// I形方块专用踢墙表
+const I_KICK_0R: KickTable = [(0,0), (-2,0), (1,0), (-2,1), (1,-2)];
+const I_KICK_R0: KickTable = [(0,0), (2,0), (-1,0), (2,-1), (-1,2)];
+const I_KICK_R2: KickTable = [(0,0), (-1,0), (2,0), (-1,-2), (2,1)];
+const I_KICK_2R: KickTable = [(0,0), (1,0), (-2,0), (1,2), (-2,-1)];
+const I_KICK_2L: KickTable = [(0,0), (2,0), (-1,0), (2,-1), (-1,2)];
+const I_KICK_L2: KickTable = [(0,0), (-2,0), (1,0), (-2,1), (1,-2)];
+const I_KICK_L0: KickTable = [(0,0), (1,0), (-2,0), (1,2), (-2,-1)];
+const I_KICK_0L: KickTable = [(0,0), (-1,0), (2,0), (-1,-2), (2,1)];
+
+fn kick_table(kind: BrickKind, from: Direction, to: Direction)-> Option<KickTable> {
+    if kind == BrickKind::O {
+        // O形方块旋转后外形不变，不需要踢墙
+        return None;
+    }
+    let is_i = kind == BrickKind::I;
+    Some(match (from, to) {
+        (Direction::Up, Direction::Right) => if is_i {I_KICK_0R} else {JLSTZ_KICK_0R},
+        (Direction::Right, Direction::Up) => if is_i {I_KICK_R0} else {JLSTZ_KICK_R0},
+        (Direction::Right, Direction::Down) => if is_i {I_KICK_R2} else {JLSTZ_KICK_R2},
+        (Direction::Down, Direction::Right) => if is_i {I_KICK_2R} else {JLSTZ_KICK_2R},
+        (Direction::Down, Direction::Left) => if is_i {I_KICK_2L} else {JLSTZ_KICK_2L},
+        (Direction::Left, Direction::Down) => if is_i {I_KICK_L2} else {JLSTZ_KICK_L2},
+        (Direction::Left, Direction::Up) => if is_i {I_KICK_L0} else {JLSTZ_KICK_L0},
+        (Direction::Up, Direction::Left) => if is_i {I_KICK_0L} else {JLSTZ_KICK_0L},
+        _ => return None,
+    })
+}
+
+/// `ActiveBrick`内部带着锁和`Cell`，不能直接derive序列化，存/读档时先转换成这个纯数据快照
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BrickSnapshot {
+    pub base_grid: Grid2D<Cell>,
+    pub kind: BrickKind,
+    pub direction: Direction,
+}
+
 pub struct ActiveBrick {
-    pub(crate) m_grid_up: Grid2D<Cell>,
-    pub(crate) m_grid_down: Grid2D<Cell>,
-    pub(crate) m_grid_left: Grid2D<Cell>,
-    pub(crate) m_grid_right: Grid2D<Cell>,
+    /// 基准朝向（Direction::Up）下的网格，其余朝向通过旋转推导
+    pub(crate) m_base_grid: Grid2D<Cell>,
+    pub(crate) m_kind: BrickKind,
 
     pub(crate) m_direction: Mutex<StdCell<Direction>>,
 
@@ -229,12 +503,9 @@ pub struct ActiveBrick {
 
 impl ActiveBrick {
     pub fn new()-> Self {
-        let meta = new_brick_grid();
         Self {
-            m_grid_down: meta.clone(),
-            m_grid_left: meta.clone(),
-            m_grid_right: meta.clone(),
-            m_grid_up: meta,
+            m_base_grid: new_brick_grid(),
+            m_kind: BrickKind::O,
 
             m_direction: Mutex::new(StdCell::new(Direction::Up)),
 
@@ -242,29 +513,38 @@ impl ActiveBrick {
         }
     }
 
-    pub fn get_active_grid(&self)-> &Grid2D<Cell> {
-        self.get_grid(self.direction())
+    pub fn kind(&self)-> BrickKind {
+        self.m_kind
     }
 
-    pub fn get_mut_active_grid(&mut self)-> &Grid2D<Cell> {
-        self.get_mut_grid(self.direction())
+    pub fn snapshot(&self)-> BrickSnapshot {
+        BrickSnapshot {
+            base_grid: self.m_base_grid.clone(),
+            kind: self.m_kind,
+            direction: self.direction(),
+        }
     }
 
-    pub fn get_grid(&self, d: Direction)-> &Grid2D<Cell> {
-        match d {
-            Direction::Up => &self.m_grid_up,
-            Direction::Down => &self.m_grid_down,
-            Direction::Left => &self.m_grid_left,
-            Direction::Right => &self.m_grid_right,
+    pub fn from_snapshot(snap: BrickSnapshot)-> Self {
+        Self {
+            m_base_grid: snap.base_grid,
+            m_kind: snap.kind,
+            m_direction: Mutex::new(StdCell::new(snap.direction)),
+            m_switch_lock: RwLock::new(()),
         }
     }
 
-    pub fn get_mut_grid(&mut self, d: Direction)-> &mut Grid2D<Cell> {
+    pub fn get_active_grid(&self)-> Grid2D<Cell> {
+        self.get_grid(self.direction())
+    }
+
+    /// 从基准朝向的网格推导出指定朝向的网格
+    pub fn get_grid(&self, d: Direction)-> Grid2D<Cell> {
         match d {
-            Direction::Up => &mut self.m_grid_up,
-            Direction::Down => &mut self.m_grid_down,
-            Direction::Left => &mut self.m_grid_left,
-            Direction::Right => &mut self.m_grid_right,
+            Direction::Up => self.m_base_grid.clone(),
+            Direction::Right => self.m_base_grid.rotate90(true),
+            Direction::Down => self.m_base_grid.rotate90(true).rotate90(true),
+            Direction::Left => self.m_base_grid.rotate90(false),
         }
     }
 
@@ -289,7 +569,7 @@ impl ActiveBrick {
                 for x in 0..g_w {
                     for y in 0..g_h {
                         if grid.get(x, y).unwrap().has_block() {
-                            v.push(Position(x as i16, y as i16 - 1));
+                            v.push(Position(x as i16, y as i16).up());
                             break;
                         }
                     }
@@ -300,7 +580,7 @@ impl ActiveBrick {
                     for y in 1..=g_h {
                         let y = g_h - y;
                         if grid.get(x, y).unwrap().has_block() {
-                            v.push(Position(x as i16, y as i16 + 1));
+                            v.push(Position(x as i16, y as i16).down());
                             break;
                         }
                     }
@@ -310,7 +590,7 @@ impl ActiveBrick {
                 for y in 0..g_h {
                     for x in 0..g_w {
                         if grid.get(x, y).unwrap().has_block() {
-                            v.push(Position(x as i16 - 1, y as i16));
+                            v.push(Position(x as i16, y as i16).left());
                             break;
                         }
                     }
@@ -321,7 +601,7 @@ impl ActiveBrick {
                     for x in 1..=g_w {
                         let x = g_w - x;
                         if grid.get(x, y).unwrap().has_block() {
-                            v.push(Position(x as i16 + 1, y as i16));
+                            v.push(Position(x as i16, y as i16).right());
                             break;
                         }
                     }
@@ -331,54 +611,84 @@ impl ActiveBrick {
         v
     }
 
-    /*pub fn get_rotating_checking_points(&self)-> Vec<Position> {
-    }*/
-
-    pub fn get_active_content_width(&self)-> u16 {
-        let _lock = self.m_switch_lock.read().unwrap();
-        let grid = self.get_active_grid();
-        let mut first = 0u16;
-        let mut last = 0u16;
-        let mut empty = true;
+    /// 获取方块旋转到朝向`d`、偏移`offset`后，会占据的棋盘格子
+    /// 返回的坐标已经加上了`offset`，调用者只需要和棋盘做越界/碰撞检测
+    pub fn get_rotating_checking_points(&self, d: Direction, offset: Position)-> Vec<Position> {
+        let grid = self.get_grid(d);
+        let mut v = Vec::<Position>::with_capacity(BRICK_GRID_SIZE as usize);
         for x in 0..grid.width() {
             for y in 0..grid.height() {
                 if grid.get(x, y).unwrap().has_block() {
-                    if first == 0 {
-                        first = x;
-                    }
-                    last = x;
-                    empty = false;
+                    v.push(Position(x as i16 + offset.0, y as i16 + offset.1));
                 }
             }
         }
+        v
+    }
+
+    /// 按照SRS规则尝试旋转方块
+    /// `board`为棋盘，`brick_x`/`brick_y`为方块当前左上角在棋盘上的坐标
+    /// 旋转成功时返回新的朝向和左上角坐标（并已经切换到新朝向），失败返回None
+    pub fn try_rotate(&self, clockwise: bool, board: &Grid2D<Cell>, brick_x: i16, brick_y: i16)-> Option<(Direction, i16, i16)> {
+        let from = self.direction();
+        let to = from.rotate(clockwise);
+        let kicks = kick_table(self.m_kind, from, to).unwrap_or([(0,0); 5]);
+        'kick: for (kx, ky) in kicks {
+            let origin = Position(brick_x, brick_y) + Position(kx, ky);
+            let (ox, oy) = (origin.0, origin.1);
+            for p in self.get_rotating_checking_points(to, origin) {
+                let in_bounds = p.to_grid().map_or(false, |(gx, gy)| gx < board.width() && gy < board.height());
+                if !in_bounds {
+                    continue 'kick;
+                }
+                let (gx, gy) = p.to_grid().unwrap();
+                if board.get(gx, gy).unwrap().has_block() {
+                    continue 'kick;
+                }
+            }
+            self.switch(to);
+            return Some((to, ox, oy));
+        }
+        None
+    }
+
+    pub fn get_active_content_width(&self)-> u16 {
+        let _lock = self.m_switch_lock.read().unwrap();
+        let grid = self.get_active_grid();
+        let mut min = u16::MAX;
+        let mut max = 0u16;
+        let mut empty = true;
+        grid.for_each(|x, _y, cell| {
+            if cell.has_block() {
+                min = min.min(x);
+                max = max.max(x);
+                empty = false;
+            }
+        });
         if empty {
             0
         } else {
-            last - first + 1
+            max - min + 1
         }
     }
 
     pub fn get_active_content_height(&self)-> u16 {
         let _lock = self.m_switch_lock.read().unwrap();
         let grid = self.get_active_grid();
-        let mut first = 0u16;
-        let mut last = 0u16;
+        let mut min = u16::MAX;
+        let mut max = 0u16;
         let mut empty = true;
-        for y in 0..grid.height() {
-            for x in 0..grid.width() {
-                if grid.get(x, y).unwrap().has_block() {
-                    if first == 0 {
-                        first = y;
-                    }
-                    last = y;
-                    empty = false;
-                }
+        grid.for_each(|_x, y, cell| {
+            if cell.has_block() {
+                min = min.min(y);
+                max = max.max(y);
+                empty = false;
             }
-        }
+        });
         if empty {
             0
         } else {
-            last - first + 1
+            max - min + 1
         }
     }
 }
@@ -386,10 +696,8 @@ impl ActiveBrick {
 impl Clone for ActiveBrick {
     fn clone(&self)-> Self {
         Self {
-            m_grid_up: self.m_grid_up.clone(),
-            m_grid_down: self.m_grid_down.clone(),
-            m_grid_left: self.m_grid_left.clone(),
-            m_grid_right: self.m_grid_right.clone(),
+            m_base_grid: self.m_base_grid.clone(),
+            m_kind: self.m_kind,
             m_direction: Mutex::new(StdCell::new(self.m_direction.lock().unwrap().get())),
             m_switch_lock: RwLock::new(()),
         }
@@ -398,24 +706,23 @@ impl Clone for ActiveBrick {
 
 
 
+lazy_static! {
+    /// 方块随机数源，默认由系统熵初始化；`seed_brick_rng`可以把它换成确定性的种子
+    /// (回放录制时存一次种子，播放时用同一个种子重新播种，`random_brick`/`random_brick_from`就会产生完全一样的出块序列)
+    pub(crate) static ref BRICK_RNG: Mutex<StdRng> = Mutex::new(StdRng::from_entropy());
+}
+
+/// 把方块随机数源重新播种为`seed`，使后续`random_brick`/`random_brick_from`的输出可复现
+pub fn seed_brick_rng(seed: u64) {
+    *BRICK_RNG.lock().unwrap() = StdRng::seed_from_u64(seed);
+}
+
 pub fn random_brick()-> ActiveBrick {
     macro_rules! put_mb {
         ($(($x:expr, $y:expr)),* -> ($grid_obj:expr)($meta_block:expr)) => {
             $( ($grid_obj).get_mut($x, $y).unwrap().replace(($meta_block).clone()); )*
         }
     }
-    macro_rules! make_brick {
-        ($up:expr, $down:expr, $right:expr, $left:expr, $d:expr) => {
-            ActiveBrick {
-                m_grid_up: $up,
-                m_grid_down: $down,
-                m_grid_left: $left,
-                m_grid_right: $right,
-                m_direction: Mutex::new(StdCell::new($d)),
-                m_switch_lock: RwLock::new(()),
-            }
-        }
-    }
     lazy_static! {static ref COLORS: Vec<Color> = vec![
         Color::Yellow,
         Color::Green,
@@ -425,231 +732,128 @@ pub fn random_brick()-> ActiveBrick {
         Color::Cyan,
         Color::Grey,
     ];}
-    let mut rng = rand::thread_rng();
+    let mut rng = BRICK_RNG.lock().unwrap();
     let mb = Block {m_color: COLORS[rng.gen_range(0..(COLORS.len()))].clone()};
-    match rng.gen_range(0..7) {
-    //match 0 {
-        // 方形方块
-        0 => {
-            let mut grid = new_brick_grid();
-            put_mb!(
-                (0, 0), (1, 0),
-                (0, 1), (1, 1) -> (grid)(mb)
-            );
-            make_brick!(grid.clone(),grid.clone(),grid.clone(),grid.clone(),Direction::Up)
-        },
+    let kind = [
+        BrickKind::O,
+        BrickKind::T,
+        BrickKind::J,
+        BrickKind::L,
+        BrickKind::I,
+        BrickKind::S,
+        BrickKind::Z,
+    ][rng.gen_range(0..7)];
+    let mut grid = new_brick_grid();
+    match kind {
+        // 方形方块，位于基准网格中央，旋转后外形不变
+        BrickKind::O => put_mb!(
+                    (1, 0), (2, 0),
+                    (1, 1), (2, 1) -> (grid)(mb)
+        ),
         // T形
-        1 => {
-            let mut g_up = new_brick_grid();
-            let mut g_down = new_brick_grid();
-            let mut g_left = new_brick_grid();
-            let mut g_right = new_brick_grid();
-            put_mb!(
-                        (1, 0),
-                (0, 1), (1, 1), (2, 1) -> (g_up)(mb)
-            );
-            put_mb!(
-                (0, 0), (1, 0), (2, 0),
-                        (1, 1) -> (g_down)(mb)
-            );
-            put_mb!(
-                        (1, 0),
-                (0, 1), (1, 1),
-                        (1, 2) -> (g_left)(mb)
-            );
-            put_mb!(
-                        (1, 0),
-                        (1, 1), (2, 1),
-                        (1, 2) -> (g_right)(mb)
-            );
-
-            make_brick!(g_up,g_down,g_right,g_left,Direction::new_random())
-        },
-        // 反L形
-        2 => {
-            let mut g_up = new_brick_grid();
-            let mut g_down = new_brick_grid();
-            let mut g_left = new_brick_grid();
-            let mut g_right = new_brick_grid();
-            put_mb!(
-                        (1, 0),
-                        (1, 1),
-                (0, 2), (1, 2) -> (g_left)(mb)
-            );
-
-            put_mb!(
-                (0, 0),
-                (0, 1), (1, 1), (2, 1) -> (g_up)(mb)
-            );
-
-            put_mb!(
-                        (1, 0), (2, 0),
-                        (1, 1),
-                        (1, 2) -> (g_right)(mb)
-            );
-
-            put_mb!(
-                (0, 1), (1, 1), (2, 1),
-                                (2, 2) -> (g_down)(mb)
-            );
-
-            make_brick!(g_up,g_down,g_right,g_left,Direction::new_random())
-        },
+        BrickKind::T => put_mb!(
+                    (1, 0),
+            (0, 1), (1, 1), (2, 1) -> (grid)(mb)
+        ),
+        // J形
+        BrickKind::J => put_mb!(
+            (0, 0),
+            (0, 1), (1, 1), (2, 1) -> (grid)(mb)
+        ),
         // L形
-        3 => {
-            let mut g_up = new_brick_grid();
-            let mut g_down = new_brick_grid();
-            let mut g_left = new_brick_grid();
-            let mut g_right = new_brick_grid();
-            put_mb!(
-                (0, 0), (1, 0),
-                        (1, 1),
-                        (1, 2) -> (g_left)(mb)
-            );
-
-            put_mb!(
-                (0, 1), (1, 1), (2, 1),
-                (0, 2) -> (g_down)(mb)
-            );
-
-            put_mb!(
-                        (1, 0),
-                        (1, 1),
-                        (1, 2), (2, 2) -> (g_right)(mb)
-            );
-
-            put_mb!(
-                                (2, 0),
-                (0, 1), (1, 1), (2, 1) -> (g_up)(mb)
-            );
-
-            make_brick!(g_up,g_down,g_right,g_left,Direction::new_random())
-        },
+        BrickKind::L => put_mb!(
+                            (2, 0),
+            (0, 1), (1, 1), (2, 1) -> (grid)(mb)
+        ),
         // 长条
-        4 => {
-            let mut g_up = new_brick_grid();
-            let mut g_down = new_brick_grid();
-            let mut g_left = new_brick_grid();
-            let mut g_right = new_brick_grid();
-            put_mb!{
-                (1, 0),
-                (1, 1),
-                (1, 2),
-                (1, 3) -> (g_right)(mb)
-            }
-
-            put_mb!{
-                (0, 2), (1, 2), (2, 2), (3, 2) -> (g_up)(mb)
-            }
-
-            put_mb!{
-                (0, 1), (1, 1), (2, 1), (3, 1) -> (g_down)(mb)
-            }
-
-            put_mb!{
-                (2, 0),
-                (2, 1),
-                (2, 2),
-                (2, 3) -> (g_left)(mb)
-            }
-
-            make_brick!(g_up,g_down,g_right,g_left,Direction::new_random())
-        },
+        BrickKind::I => put_mb!(
+            (0, 1), (1, 1), (2, 1), (3, 1) -> (grid)(mb)
+        ),
         // S形
-        5 => {
-            let mut g_up = new_brick_grid();
-            let mut g_down = new_brick_grid();
-            let mut g_left = new_brick_grid();
-            let mut g_right = new_brick_grid();
-
-            put_mb!{
-                        (1, 0), (2, 0),
-                (0, 1), (1, 1) -> (g_up)(mb)
-            }
-
-            put_mb!{
-                (0, 0),
-                (0, 1), (1, 1),
-                        (1, 2) -> (g_left)(mb)
-            }
-
-            put_mb!{
-                        (1, 0), (2, 0),
-                (0, 1), (1, 1) -> (g_down)(mb)
-            }
-
-            put_mb!{
-                (0, 0),
-                (0, 1), (1, 1),
-                        (1, 2) -> (g_right)(mb)
-            }
-
-            make_brick!(g_up,g_down,g_right,g_left,Direction::new_random())
-        },
+        BrickKind::S => put_mb!(
+                    (1, 0), (2, 0),
+            (0, 1), (1, 1) -> (grid)(mb)
+        ),
         // Z形
-        6 => {
-            let mut g_up = new_brick_grid();
-            let mut g_down = new_brick_grid();
-            let mut g_left = new_brick_grid();
-            let mut g_right = new_brick_grid();
-
-            put_mb!{
-                (0, 0), (1, 0),
-                        (1, 1), (2, 1) -> (g_up)(mb)
-            }
-
-            put_mb!{
-                        (1, 0),
-                (0, 1), (1, 1),
-                (0, 2) -> (g_left)(mb)
-            }
-
-            put_mb!{
-                (0, 0), (1, 0),
-                        (1, 1), (2, 1) -> (g_down)(mb)
-            }
-
-            put_mb!{
-                        (1, 0),
-                (0, 1), (1, 1),
-                (0, 2) -> (g_right)(mb)
-            }
-
-            make_brick!(g_up,g_down,g_right,g_left,Direction::new_random())
-        },
-        7 => {
-            let mut g_up = new_brick_grid();
-            let mut g_down = new_brick_grid();
-            let mut g_left = new_brick_grid();
-            let mut g_right = new_brick_grid();
-
-            put_mb!{
-                (0, 0),
-                        (1, 1),
-                                (2, 2) -> (g_up)(mb)
-            }
-
-            put_mb!{
-                                (2, 0),
-                        (1, 1),
-                (0, 2) -> (g_right)(mb)
-            }
+        BrickKind::Z => put_mb!(
+            (0, 0), (1, 0),
+                    (1, 1), (2, 1) -> (grid)(mb)
+        ),
+    };
+    ActiveBrick {
+        m_base_grid: grid,
+        m_kind: kind,
+        m_direction: Mutex::new(StdCell::new(Direction::Up)),
+        m_switch_lock: RwLock::new(()),
+    }
+}
 
-            put_mb!{
-                (0, 0),
-                        (1, 1),
-                                (2, 2) -> (g_down)(mb)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kick_table_is_none_for_o_piece() {
+        assert_eq!(kick_table(BrickKind::O, Direction::Up, Direction::Right), None);
+    }
+
+    #[test]
+    fn kick_table_first_entry_is_always_no_op() {
+        for kind in [BrickKind::I, BrickKind::J, BrickKind::L, BrickKind::S, BrickKind::T, BrickKind::Z] {
+            for (from, to) in [
+                (Direction::Up, Direction::Right), (Direction::Right, Direction::Up),
+                (Direction::Right, Direction::Down), (Direction::Down, Direction::Right),
+                (Direction::Down, Direction::Left), (Direction::Left, Direction::Down),
+                (Direction::Left, Direction::Up), (Direction::Up, Direction::Left),
+            ] {
+                let table = kick_table(kind, from, to).unwrap();
+                assert_eq!(table[0], (0, 0));
             }
+        }
+    }
 
-            put_mb!{
-                (0, 0), (1, 0), (2, 0),
-                (0, 1),         (2, 1),
-                (0, 2), (1, 2), (2, 2) -> (g_left)(mb)
+    #[test]
+    fn jlstz_kick_table_y_offsets_point_down_not_up() {
+        // Guideline原表的0->R第4次尝试是往下踢两格；这里的棋盘y轴朝下为正，
+        // 往下挪两行意味着y分量是+2，不是原表那个坐标系下的-2
+        let table = kick_table(BrickKind::T, Direction::Up, Direction::Right).unwrap();
+        assert_eq!(table[3], (0, 2));
+
+        // R->0是0->R的逆过程，对应地应该是往上踢两格，即y分量是-2
+        let table = kick_table(BrickKind::T, Direction::Right, Direction::Up).unwrap();
+        assert_eq!(table[3], (0, -2));
+    }
+
+    #[test]
+    fn i_kick_table_y_offsets_point_down_not_up() {
+        let table = kick_table(BrickKind::I, Direction::Up, Direction::Right).unwrap();
+        assert_eq!(table[3], (-2, 1));
+        assert_eq!(table[4], (1, -2));
+
+        let table = kick_table(BrickKind::I, Direction::Right, Direction::Up).unwrap();
+        assert_eq!(table[3], (2, -1));
+        assert_eq!(table[4], (-1, 2));
+    }
+
+    #[test]
+    fn kick_tables_are_pairwise_inverses() {
+        // 任意一对互逆的旋转(比如0->R和R->0)，踢墙表应该逐项互为相反数，
+        // 因为把方块踢过去再踢回来应该落回原位
+        let pairs = [
+            (Direction::Up, Direction::Right, Direction::Right, Direction::Up),
+            (Direction::Down, Direction::Right, Direction::Right, Direction::Down),
+            (Direction::Down, Direction::Left, Direction::Left, Direction::Down),
+            (Direction::Up, Direction::Left, Direction::Left, Direction::Up),
+        ];
+        for kind in [BrickKind::I, BrickKind::T] {
+            for (f1, t1, f2, t2) in pairs {
+                let a = kick_table(kind, f1, t1).unwrap();
+                let b = kick_table(kind, f2, t2).unwrap();
+                for i in 0..5 {
+                    assert_eq!(a[i], (-b[i].0, -b[i].1));
+                }
             }
-
-            make_brick!(g_left.clone(),g_left.clone(),g_left.clone(),g_left.clone(),Direction::new_random())
-        },
-        _ => unreachable!(),
+        }
     }
 }
 
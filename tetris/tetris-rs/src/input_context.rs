@@ -0,0 +1,33 @@
+use crossterm::event::KeyEvent;
+
+use crate::keymap::KeyMap;
+
+/// 上下文处理完一次按键/一帧之后，想让调用方对上下文栈做的操作
+pub(crate) enum Transition {
+    /// 维持现状，不调整栈
+    None,
+    /// 压入一个新的上下文，盖住当前这个（原来的留在栈里，但不再收到事件）
+    Push(Box<dyn InputContext>),
+    /// 弹出当前上下文，把输入交还给栈里的上一个
+    Pop,
+    /// 原地替换当前上下文
+    Replace(Box<dyn InputContext>),
+    /// 结束整个渲染循环
+    Quit,
+}
+
+/// 输入上下文：游戏进行中、暂停菜单……以后的关卡菜单/结算画面也走这一套
+/// 每一帧只有栈顶的上下文会收到事件，这样暂停菜单这类覆盖层完全不需要反过来了解游戏逻辑
+pub(crate) trait InputContext {
+    /// 处理一个按键事件
+    fn handle_key(&mut self, key: KeyEvent, keymap: &KeyMap)-> Transition;
+
+    /// 每帧调用一次，不管这一帧有没有收到按键事件；默认什么也不做
+    /// (给需要"按键按住多久"这类与离散事件无关的计时逻辑用，比如DAS/ARR)
+    fn tick(&mut self)-> Transition {
+        Transition::None
+    }
+
+    /// 绘制这个上下文自己的画面；默认什么也不画(游戏进行中的画面由渲染循环负责，不走这里)
+    fn render(&self) {}
+}
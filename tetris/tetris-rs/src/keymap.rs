@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+use serde::{Serialize, Deserialize};
+
+use crate::render::GameEvent;
+
+/// 查表查到的动作。大多数直接对应一个要转发给更新线程的`GameEvent`；
+/// 退出/暂停这两个要在键盘线程里改动输入上下文栈(退出渲染循环、压入暂停上下文)，不走事件通道，所以单独列出来
+#[derive(Clone, Copy)]
+pub(crate) enum KeyAction {
+    Send(GameEvent),
+    Quit,
+    Pause,
+}
+
+/// 配置文件里一条按键绑定的JSON形状，例如`{ "key": "left", "modifiers": [], "event": "move_left" }`
+#[derive(Serialize, Deserialize)]
+struct KeyBindingDef {
+    key: String,
+    #[serde(default)]
+    modifiers: Vec<String>,
+    event: String,
+}
+
+fn key_from_name(name: &str)-> Result<KeyCode, String> {
+    Ok(match name.to_lowercase().as_str() {
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        other if other.chars().count() == 1 => KeyCode::Char(other.chars().next().unwrap()),
+        other => return Err(format!("Unknown key name '{}'", other)),
+    })
+}
+
+fn modifiers_from_names(names: &[String])-> KeyModifiers {
+    let mut modifiers = KeyModifiers::empty();
+    for name in names {
+        modifiers |= match name.to_lowercase().as_str() {
+            "control" | "ctrl" => KeyModifiers::CONTROL,
+            "shift" => KeyModifiers::SHIFT,
+            "alt" => KeyModifiers::ALT,
+            _ => continue,
+        };
+    }
+    modifiers
+}
+
+fn action_from_name(name: &str)-> Result<KeyAction, String> {
+    Ok(match name.to_lowercase().as_str() {
+        "quit" => KeyAction::Quit,
+        "pause" => KeyAction::Pause,
+        "move_left" => KeyAction::Send(GameEvent::MoveLeft),
+        "move_right" => KeyAction::Send(GameEvent::MoveRight),
+        "move_down" => KeyAction::Send(GameEvent::MoveDown),
+        "rotate_clockwise" => KeyAction::Send(GameEvent::RotateClock),
+        "rotate_counterclockwise" => KeyAction::Send(GameEvent::RotateUnclock),
+        "hard_drop" => KeyAction::Send(GameEvent::HardDrop),
+        "hold" => KeyAction::Send(GameEvent::Hold),
+        "debug_brick_position" => KeyAction::Send(GameEvent::DebugBrickPosition),
+        other => return Err(format!("Unknown key action '{}'", other)),
+    })
+}
+
+/// 按键到游戏动作的映射表，取代此前散落在渲染循环里的那一串字面量匹配
+/// 支持从配置文件加载，加载/解析失败时由调用方落回[`KeyMap::default`]里跟此前硬编码完全一致的绑定
+pub(crate) struct KeyMap {
+    m_bindings: HashMap<(KeyCode, KeyModifiers), KeyAction>,
+}
+
+impl Default for KeyMap {
+    fn default()-> Self {
+        let mut m_bindings = HashMap::new();
+        let mut bind = |code: KeyCode, modifiers: KeyModifiers, action: KeyAction| {
+            m_bindings.insert((code, modifiers), action);
+        };
+        bind(KeyCode::Char('q'), KeyModifiers::NONE, KeyAction::Quit);
+        bind(KeyCode::Char('Q'), KeyModifiers::NONE, KeyAction::Quit);
+        bind(KeyCode::Char('p'), KeyModifiers::NONE, KeyAction::Pause);
+        bind(KeyCode::Char('P'), KeyModifiers::NONE, KeyAction::Pause);
+        bind(KeyCode::Char(' '), KeyModifiers::NONE, KeyAction::Send(GameEvent::HardDrop));
+        bind(KeyCode::Char('c'), KeyModifiers::NONE, KeyAction::Send(GameEvent::Hold));
+        bind(KeyCode::Char('C'), KeyModifiers::NONE, KeyAction::Send(GameEvent::Hold));
+        bind(KeyCode::Char('z'), KeyModifiers::NONE, KeyAction::Send(GameEvent::RotateUnclock));
+        bind(KeyCode::Char('Z'), KeyModifiers::NONE, KeyAction::Send(GameEvent::RotateUnclock));
+        bind(KeyCode::Char('b'), KeyModifiers::CONTROL, KeyAction::Send(GameEvent::DebugBrickPosition));
+        bind(KeyCode::Left, KeyModifiers::NONE, KeyAction::Send(GameEvent::MoveLeft));
+        bind(KeyCode::Right, KeyModifiers::NONE, KeyAction::Send(GameEvent::MoveRight));
+        bind(KeyCode::Down, KeyModifiers::NONE, KeyAction::Send(GameEvent::MoveDown));
+        bind(KeyCode::Up, KeyModifiers::NONE, KeyAction::Send(GameEvent::RotateClock));
+        Self { m_bindings }
+    }
+}
+
+impl KeyMap {
+    /// 从JSON文档加载一套按键映射，文档形如：
+    /// `[{ "key": "left", "modifiers": [], "event": "move_left" }, { "key": "b", "modifiers": ["control"], "event": "debug_brick_position" }]`
+    pub(crate) fn load(path: &str)-> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let defs: Vec<KeyBindingDef> = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        let mut m_bindings = HashMap::new();
+        for def in defs {
+            let code = key_from_name(&def.key)?;
+            let modifiers = modifiers_from_names(&def.modifiers);
+            let action = action_from_name(&def.event)?;
+            m_bindings.insert((code, modifiers), action);
+        }
+        Ok(Self { m_bindings })
+    }
+
+    pub(crate) fn lookup(&self, code: KeyCode, modifiers: KeyModifiers)-> Option<KeyAction> {
+        self.m_bindings.get(&(code, modifiers)).copied()
+    }
+}
@@ -2,9 +2,16 @@
 
 mod grid;
 mod render;
+mod pieces;
+mod net;
+mod keymap;
+mod input_context;
+mod replay;
 
 pub use grid::*;
 pub use render::*;
+pub use pieces::*;
+pub use net::*;
 
 use std::boxed::Box;
 use std::panic::*;
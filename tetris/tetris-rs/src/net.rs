@@ -0,0 +1,62 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::JoinHandle;
+
+use serde::{Serialize, Deserialize};
+
+use crate::grid::*;
+use crate::pieces::*;
+
+/// 对面棋盘的一份快照，用来在本地画一面小的镜像棋盘
+#[derive(Serialize, Deserialize)]
+pub struct PeerState {
+    pub grid: Grid2D<Cell>,
+    pub score: u32,
+    pub brick: BrickSnapshot,
+    pub brick_x: i16,
+    pub brick_y: i16,
+}
+
+/// 对战双方之间往来的消息，一行一个JSON对象
+#[derive(Serialize, Deserialize)]
+pub enum NetMessage {
+    /// 每帧/每次局面变化后发一份，供对面画镜像棋盘
+    State(PeerState),
+    /// 一次`store_and_new_brick`消掉了N行时，发(N-1)行攻击给对面
+    Garbage(u8),
+}
+
+impl NetMessage {
+    pub fn send(&self, stream: &mut TcpStream)-> std::io::Result<()> {
+        let text = serde_json::to_string(self).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        writeln!(stream, "{}", text)
+    }
+}
+
+/// 监听`addr`，等待另一方连进来，返回建立好的连接
+pub fn host(addr: &str)-> std::io::Result<TcpStream> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+    Ok(stream)
+}
+
+/// 连接到`addr`上正在监听的另一方
+pub fn connect(addr: &str)-> std::io::Result<TcpStream> {
+    TcpStream::connect(addr)
+}
+
+/// 起一个专门读对面消息的线程，每解析出一条`NetMessage`就调用一次`on_message`
+/// 这跟键盘线程把`GameEvent`塞进同一个`channel`是同一套思路：用一个独立的读线程喂给单一消费者
+pub fn spawn_reader(stream: TcpStream, mut on_message: impl FnMut(NetMessage) + Send + 'static)-> JoinHandle<()> {
+    std::thread::Builder::new().name("Tetris-NetReader".to_string()).spawn(move || {
+        let reader = BufReader::new(stream);
+        for line in reader.lines() {
+            let Ok(line) = line else { break };
+            if line.is_empty() {
+                continue;
+            }
+            let Ok(message) = serde_json::from_str::<NetMessage>(&line) else { continue };
+            on_message(message);
+        }
+    }).unwrap()
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::vec::Vec;
+
+use crossterm::style::Color;
+use serde::{Serialize, Deserialize};
+
+use crate::grid::*;
+use crate::grid::color_serde;
+
+/// 一个方块定义：名字、颜色、以及它在基准朝向(Direction::Up)下占据的格子
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PieceDef {
+    pub name: String,
+    #[serde(with = "color_serde")]
+    pub color: Color,
+    pub cells: Vec<(u16, u16)>,
+}
+
+/// 一套可供`random_brick_from`抽取的方块定义
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PieceSet {
+    pub(crate) m_pieces: Vec<PieceDef>,
+}
+
+impl PieceSet {
+    /// 从JSON文档加载一套方块定义；只接受标准JSON，JSON5的注释、尾逗号、不带引号的键等写法会解析失败
+    /// 文档形如：`[{ "name": "T", "color": "magenta", "cells": [[1,0],[0,1],[1,1],[2,1]] }, ...]`
+    pub fn load(path: &str)-> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let pieces: Vec<PieceDef> = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        if pieces.is_empty() {
+            return Err("Piece set must contain at least one piece".to_string());
+        }
+        Ok(Self { m_pieces: pieces })
+    }
+
+    pub fn pieces(&self)-> &[PieceDef] {
+        &self.m_pieces
+    }
+}
+
+impl ActiveBrick {
+    /// 从ASCII方块图构造一个方块，非空白字符视为填充格
+    /// 行数/列数不足`BRICK_GRID_SIZE`时以空白补齐，超出部分被忽略
+    pub fn from_ascii(art: &str, color: Color)-> Self {
+        let mut grid = Grid2D::<Cell>::new(BRICK_GRID_SIZE, BRICK_GRID_SIZE);
+        let block = Block { m_color: color };
+        for (y, line) in art.lines().enumerate() {
+            if y as u16 >= BRICK_GRID_SIZE {
+                break;
+            }
+            for (x, ch) in line.chars().enumerate() {
+                if x as u16 >= BRICK_GRID_SIZE {
+                    break;
+                }
+                if ch != ' ' {
+                    grid.get_mut(x as u16, y as u16).unwrap().replace(block.clone());
+                }
+            }
+        }
+        Self {
+            m_base_grid: grid,
+            m_kind: BrickKind::Custom,
+            m_direction: std::sync::Mutex::new(std::cell::Cell::new(Direction::Up)),
+            m_switch_lock: std::sync::RwLock::new(()),
+        }
+    }
+
+    fn from_piece_def(def: &PieceDef)-> Self {
+        let mut grid = Grid2D::<Cell>::new(BRICK_GRID_SIZE, BRICK_GRID_SIZE);
+        let block = Block { m_color: def.color };
+        for &(x, y) in &def.cells {
+            if x < BRICK_GRID_SIZE && y < BRICK_GRID_SIZE {
+                grid.get_mut(x, y).unwrap().replace(block.clone());
+            }
+        }
+        Self {
+            m_base_grid: grid,
+            m_kind: BrickKind::Custom,
+            m_direction: std::sync::Mutex::new(std::cell::Cell::new(Direction::Up)),
+            m_switch_lock: std::sync::RwLock::new(()),
+        }
+    }
+}
+
+/// 从加载的方块集合中随机抽取一个，替代硬编码七种形状的`random_brick`
+pub fn random_brick_from(set: &PieceSet)-> ActiveBrick {
+    use rand::Rng;
+    use crate::grid::BRICK_RNG;
+    let idx = BRICK_RNG.lock().unwrap().gen_range(0..set.pieces().len());
+    ActiveBrick::from_piece_def(&set.pieces()[idx])
+}
+
+/// 一份预先铺设好的场地：非空白字符表示已经占据的格子，颜色统一取自文件声明
+pub struct BoardLayout {
+    pub(crate) m_grid: Grid2D<Cell>,
+}
+
+impl BoardLayout {
+    /// 解析一份场地布局文件：第一行为`color=<名字>`，其余行是ASCII网格（宽高由`width`/`height`参数决定，超出的行和列被忽略）
+    pub fn load_ascii(path: &str, width: u16, height: u16)-> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut lines = text.lines();
+        let header = lines.next().ok_or("Empty board layout file")?;
+        let color_name = header.strip_prefix("color=").ok_or("First line must be 'color=<name>'")?;
+        let color = color_serde::color_from_name(color_name)?;
+        let block = Block { m_color: color };
+        let mut grid = Grid2D::<Cell>::new(width, height);
+        for (y, line) in lines.enumerate() {
+            if y as u16 >= height {
+                break;
+            }
+            for (x, ch) in line.chars().enumerate() {
+                if x as u16 >= width {
+                    break;
+                }
+                if ch != ' ' {
+                    grid.get_mut(x as u16, y as u16).unwrap().replace(block.clone());
+                }
+            }
+        }
+        Ok(Self { m_grid: grid })
+    }
+
+    pub fn into_grid(self)-> Grid2D<Cell> {
+        self.m_grid
+    }
+}
@@ -4,9 +4,21 @@ use std::io::{stdout, Write};
 use std::sync::atomic::{Ordering::*, AtomicBool, AtomicI16, AtomicU32};
 use std::sync::{Arc, RwLockWriteGuard, RwLock, Mutex, MutexGuard};
 use std::ops::DerefMut;
-use std::sync::mpsc::channel;
+use std::sync::mpsc::{channel, Sender};
 use std::mem::swap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::net::TcpStream;
 use crate::grid::*;
+use crate::pieces::*;
+use crate::net::{self, NetMessage, PeerState};
+use crate::keymap::{KeyMap, KeyAction};
+use crate::input_context::{InputContext, Transition};
+use crate::replay::{self, ReplayData, ReplayEntry, ReplayHeader};
+
+use serde::{Serialize, Deserialize};
+use rand::Rng;
 
 use crossterm::{
     event::{
@@ -49,7 +61,8 @@ macro_rules! set_panic_hook {
     };
 }
 
-enum GameEvent {
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum GameEvent {
     MoveLeft,
     MoveRight,
     MoveDown,
@@ -57,6 +70,238 @@ enum GameEvent {
     RotateUnclock,
     Pause,
     DebugBrickPosition,
+    /// 空格键：立即让方块落到底，按掉落的格数给加分奖励
+    HardDrop,
+    /// 换手：与暂存槽里的方块互换，槽为空时换成下一个方块；锁定前只能触发一次
+    Hold,
+    /// 对面清了行，收到的攻击行数，由`net::spawn_reader`转译`NetMessage::Garbage`塞进来
+    ReceiveGarbage(u8),
+}
+
+/// 游戏进行中的输入上下文：方向键走DAS/ARR连发，暂停键压入[`PausedContext`]，其余按键原样转发成`GameEvent`
+pub(crate) struct PlayingContext {
+    send_event: Box<dyn Fn(GameEvent)>,
+    make_paused_context: Box<dyn Fn()-> Box<dyn InputContext>>,
+    das_ms: u64,
+    arr_ms: u64,
+    das_state: Option<(Direction, Instant)>,
+    last_seen: Instant,
+    last_arr: Instant,
+}
+
+impl PlayingContext {
+    /// 一直没收到同方向的按键事件超过这么久，就判定为已经松开
+    /// (crossterm只给按下事件，没有松开事件，没法直接知道键什么时候抬起)
+    const DAS_RELEASE_TIMEOUT_MS: u64 = 150;
+
+    pub(crate) fn new(
+        send_event: Box<dyn Fn(GameEvent)>,
+        make_paused_context: Box<dyn Fn()-> Box<dyn InputContext>>,
+        das_ms: u64,
+        arr_ms: u64,
+    )-> Self {
+        Self {
+            send_event,
+            make_paused_context,
+            das_ms,
+            arr_ms,
+            das_state: None,
+            last_seen: Instant::now(),
+            last_arr: Instant::now(),
+        }
+    }
+}
+
+impl InputContext for PlayingContext {
+    fn handle_key(&mut self, key: KeyEvent, keymap: &KeyMap)-> Transition {
+        match keymap.lookup(key.code, key.modifiers) {
+            Some(KeyAction::Quit) => Transition::Quit,
+            Some(KeyAction::Pause) => {
+                (self.send_event)(GameEvent::Pause);
+                Transition::Push((self.make_paused_context)())
+            },
+            // 横移单独处理：按下的一刻立即移动一格，是否开始DAS连发交给`tick`接手
+            Some(KeyAction::Send(GameEvent::MoveLeft)) => {
+                self.last_seen = Instant::now();
+                if self.das_state.map_or(true, |(dir, _)| dir != Direction::Left) {
+                    (self.send_event)(GameEvent::MoveLeft);
+                    self.das_state = Some((Direction::Left, Instant::now()));
+                    self.last_arr = Instant::now();
+                }
+                Transition::None
+            },
+            Some(KeyAction::Send(GameEvent::MoveRight)) => {
+                self.last_seen = Instant::now();
+                if self.das_state.map_or(true, |(dir, _)| dir != Direction::Right) {
+                    (self.send_event)(GameEvent::MoveRight);
+                    self.das_state = Some((Direction::Right, Instant::now()));
+                    self.last_arr = Instant::now();
+                }
+                Transition::None
+            },
+            Some(KeyAction::Send(event)) => {
+                (self.send_event)(event);
+                Transition::None
+            },
+            None => Transition::None,
+        }
+    }
+
+    fn tick(&mut self)-> Transition {
+        if let Some((direction, pressed_at)) = self.das_state {
+            if self.last_seen.elapsed() >= Duration::from_millis(Self::DAS_RELEASE_TIMEOUT_MS) {
+                self.das_state = None;
+            } else if pressed_at.elapsed() >= Duration::from_millis(self.das_ms)
+                && self.last_arr.elapsed() >= Duration::from_millis(self.arr_ms) {
+                match direction {
+                    Direction::Left => (self.send_event)(GameEvent::MoveLeft),
+                    Direction::Right => (self.send_event)(GameEvent::MoveRight),
+                    _ => {},
+                };
+                self.last_arr = Instant::now();
+            }
+        }
+        Transition::None
+    }
+}
+
+/// 暂停菜单的输入上下文：按C弹栈恢复游戏，按Q/S退出(S会先存档)，两种退出都先唤醒被`GameEvent::Pause`阻塞住的更新线程
+pub(crate) struct PausedContext {
+    grid: Arc<RwLock<Grid2D<Cell>>>,
+    brick: Arc<RwLock<ActiveBrick>>,
+    next_brick: Arc<Mutex<ActiveBrick>>,
+    brick_x: Arc<AtomicI16>,
+    brick_y: Arc<AtomicI16>,
+    score: Arc<AtomicU32>,
+    save_path: Option<String>,
+    continue_notify: Sender<()>,
+}
+
+impl PausedContext {
+    pub(crate) fn new(
+        grid: Arc<RwLock<Grid2D<Cell>>>,
+        brick: Arc<RwLock<ActiveBrick>>,
+        next_brick: Arc<Mutex<ActiveBrick>>,
+        brick_x: Arc<AtomicI16>,
+        brick_y: Arc<AtomicI16>,
+        score: Arc<AtomicU32>,
+        save_path: Option<String>,
+        continue_notify: Sender<()>,
+    )-> Self {
+        Self { grid, brick, next_brick, brick_x, brick_y, score, save_path, continue_notify }
+    }
+}
+
+impl InputContext for PausedContext {
+    fn handle_key(&mut self, key: KeyEvent, _keymap: &KeyMap)-> Transition {
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('C') => {
+                self.continue_notify.send(()).unwrap();
+                Transition::Pop
+            },
+            KeyCode::Char('q') | KeyCode::Char('Q') => {
+                self.continue_notify.send(()).unwrap();
+                Transition::Quit
+            },
+            KeyCode::Char('s') | KeyCode::Char('S') => {
+                if let Some(path) = &self.save_path {
+                    let state = GameState {
+                        grid: self.grid.read().unwrap().clone(),
+                        brick: self.brick.read().unwrap().snapshot(),
+                        next_brick: self.next_brick.lock().unwrap().snapshot(),
+                        brick_x: self.brick_x.load(Acquire),
+                        brick_y: self.brick_y.load(Acquire),
+                        score: self.score.load(Acquire),
+                    };
+                    let _ = Game::save_state(path, &state);
+                }
+                self.continue_notify.send(()).unwrap();
+                Transition::Quit
+            },
+            _ => Transition::None,
+        }
+    }
+
+    fn render(&self) {
+        let cpos = Game::calc_center(term_size().unwrap().1, 2);
+        Game::draw_string_center(cpos, &"已暂停".to_string());
+        Game::draw_string_center(cpos+1, &"按下Q退出，按下C继续游戏，按下S存档退出".to_string());
+    }
+}
+
+/// 存档里的完整游戏状态：网格、当前/下一个方块、方块位置与分数
+#[derive(Serialize, Deserialize)]
+pub struct GameState {
+    pub grid: Grid2D<Cell>,
+    pub brick: BrickSnapshot,
+    pub next_brick: BrickSnapshot,
+    pub brick_x: i16,
+    pub brick_y: i16,
+    pub score: u32,
+}
+
+/// 追加写入的单局操作日志，用于回放或崩溃后恢复
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    tick: u64,
+    event: GameEvent,
+}
+
+/// 双缓冲渲染器：将一帧的内容画到后台缓冲区，再与前台缓冲区逐格比较
+/// 只为发生变化的格子发出`MoveTo`+styled字符，最后交换两个缓冲区
+/// 相比每帧清屏重绘，这样可以消除闪烁并把I/O开销降到变化格子数量级
+pub struct Renderer {
+    m_xcoord: u16,
+    m_ycoord: u16,
+    m_front: Grid2D<Option<Block>>,
+    m_back: Grid2D<Option<Block>>,
+}
+
+impl Renderer {
+    pub fn new(xcoord: u16, ycoord: u16, width: u16, height: u16)-> Self {
+        Self {
+            m_xcoord: xcoord,
+            m_ycoord: ycoord,
+            m_front: Grid2D::<Option<Block>>::new(width, height),
+            m_back: Grid2D::<Option<Block>>::new(width, height),
+        }
+    }
+
+    /// 开始绘制新的一帧：清空后台缓冲区
+    pub fn begin_frame(&mut self) {
+        self.m_back.fill(&None);
+    }
+
+    /// 设置后台缓冲区中某一格的内容，`None`表示空格
+    pub fn set_cell(&mut self, x: u16, y: u16, block: Option<Block>) {
+        if let Ok(cell) = self.m_back.get_mut(x, y) {
+            *cell = block;
+        }
+    }
+
+    /// 与前台缓冲区比较，只为变化的格子写入终端，然后交换前后缓冲区
+    pub fn present(&mut self) {
+        let mut stdout = stdout();
+        for y in 0..self.m_back.height() {
+            for x in 0..self.m_back.width() {
+                let next = self.m_back.get(x, y).unwrap();
+                let prev = self.m_front.get(x, y).unwrap();
+                if next == prev {
+                    continue;
+                }
+                stdout.queue(cursor::MoveTo(self.m_xcoord + x * 2, self.m_ycoord + y)).unwrap();
+                match next {
+                    Some(block) => {
+                        stdout.queue(style::Print("  ".on(block.m_color))).unwrap();
+                    },
+                    None => {
+                        stdout.queue(style::Print("  ")).unwrap();
+                    },
+                }
+            }
+        }
+        swap(&mut self.m_front, &mut self.m_back);
+    }
 }
 
 pub struct Game {
@@ -65,6 +310,28 @@ pub struct Game {
     pub config_window_xcoord: u16,
     pub config_window_ycoord: u16,
     pub config_debug_enabled: bool,
+    /// 设置后，每次操作都会追加写入到这个文件，用于回放/崩溃恢复
+    pub config_move_log_path: Option<String>,
+    /// 打开后每次新方块生成时都由`Game::step_ai`接管，不再等待键盘输入
+    pub config_ai_enabled: bool,
+    /// 设置后，开局时若此路径下存在存档就恢复对局，暂停时按S也会存到这个路径
+    pub config_save_path: Option<String>,
+    /// 设置后，游戏结束时把本局分数计入这个路径下的排行榜并展示
+    pub config_highscore_path: Option<String>,
+    /// 设置后用这套方块定义代替硬编码的标准7种形状，配合`PieceSet::load`即可换成加长版/五格方块等变体
+    pub config_brick_set: Option<PieceSet>,
+    /// DAS：方向键按下后持续按住超过这么多毫秒才开始自动连发横移
+    pub config_das_ms: u64,
+    /// ARR：DAS触发后每隔这么多毫秒补发一次同方向的横移
+    pub config_arr_ms: u64,
+    /// 设置后从这个JSON文件加载按键映射，取代[`KeyMap::default`]里硬编码的那一套绑定
+    pub config_keymap_path: Option<String>,
+    /// 设置后，退出时把整局事件流(含开局用的方块随机种子)写成`.replay`文件，供之后用`config_replay_playback_path`原样重放
+    pub config_replay_path: Option<String>,
+    /// 写回放文件时是否走gzip(flate2)压缩；关闭后存纯JSON，方便调试查看
+    pub config_replay_compressed: bool,
+    /// 设置后不再轮询键盘，而是读取这个`.replay`文件，按录制时的时间表把事件喂回游戏，复现录制时的那一局
+    pub config_replay_playback_path: Option<String>,
 }
 
 impl Game {
@@ -75,7 +342,206 @@ impl Game {
             config_window_xcoord: 1,
             config_window_ycoord: 1,
             config_debug_enabled: true,
+            config_move_log_path: None,
+            config_ai_enabled: false,
+            config_save_path: None,
+            config_highscore_path: None,
+            config_brick_set: None,
+            config_das_ms: 130,
+            config_arr_ms: 30,
+            config_keymap_path: None,
+            config_replay_path: None,
+            config_replay_compressed: true,
+            config_replay_playback_path: None,
+        }
+    }
+
+    /// 把当前的完整游戏状态保存到json文件
+    pub fn save_state(path: &str, state: &GameState)-> Result<(), String> {
+        let text = serde_json::to_string(state).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    /// 从json文件恢复游戏状态
+    pub fn load_state(path: &str)-> Result<GameState, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+
+    /// 把一条操作日志追加写入到文件末尾，每行一条JSON记录
+    /// 配合`Game::replay_log`可以把崩溃前/保存前的操作重新应用一遍来重建一局游戏
+    fn append_log(path: &str, tick: u64, event: GameEvent)-> Result<(), String> {
+        let entry = LogEntry { tick, event };
+        let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())
+    }
+
+    /// 读出追加日志里的全部记录，按写入顺序排列
+    pub fn replay_log(path: &str)-> Result<Vec<(u64, GameEvent)>, String> {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut out = Vec::new();
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.is_empty() {
+                continue;
+            }
+            let entry: LogEntry = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+            out.push((entry.tick, entry.event));
         }
+        Ok(out)
+    }
+
+    /// 评估某个落地方案的好坏：消行越多越好，累计高度/空洞/凹凸度越低越好
+    fn score_board(board: &Grid2D<Cell>)-> f64 {
+        const W_LINES: f64 = 0.76;
+        const W_HEIGHT: f64 = 0.51;
+        const W_HOLES: f64 = 0.36;
+        const W_BUMPINESS: f64 = 0.18;
+
+        let w = board.width();
+        let h = board.height();
+        let mut heights = vec![0u16; w as usize];
+        let mut holes = 0u32;
+        for x in 0..w {
+            let mut seen_block = false;
+            for y in 0..h {
+                let filled = board.get(x, y).unwrap().has_block();
+                if filled {
+                    if !seen_block {
+                        heights[x as usize] = h - y;
+                        seen_block = true;
+                    }
+                } else if seen_block {
+                    holes += 1;
+                }
+            }
+        }
+        let aggregate_height: u32 = heights.iter().map(|&v| v as u32).sum();
+        let bumpiness: u32 = heights.windows(2).map(|w| (w[0] as i32 - w[1] as i32).unsigned_abs()).sum();
+        let lines = (0..h).filter(|&y| (0..w).all(|x| board.get(x, y).unwrap().has_block())).count() as u32;
+
+        W_LINES * lines as f64
+            - W_HEIGHT * aggregate_height as f64
+            - W_HOLES * holes as f64
+            - W_BUMPINESS * bumpiness as f64
+    }
+
+    /// 把方块摆放在指定朝向/坐标，落在棋盘上产生的新棋盘；任意一格越界/重叠时返回`None`
+    fn place(board: &Grid2D<Cell>, brick: &ActiveBrick, d: Direction, x: i16, y: i16)-> Option<Grid2D<Cell>> {
+        let mut result = board.clone();
+        for p in brick.get_rotating_checking_points(d, Position(x, y)) {
+            if p.0 < 0 || p.1 < 0 || p.0 as u16 >= board.width() || p.1 as u16 >= board.height() {
+                return None;
+            }
+            if result.get(p.0 as u16, p.1 as u16).unwrap().has_block() {
+                return None;
+            }
+            result.get_mut(p.0 as u16, p.1 as u16).unwrap().replace(Block { m_color: crossterm::style::Color::White });
+        }
+        Some(result)
+    }
+
+    /// 枚举当前方块的全部朝向/落点，挑出让`score_board`最大的一个
+    /// 返回值是落地后的朝向与左上角坐标，调用者据此驱动旋转/平移/下落
+    pub fn step_ai(board: &Grid2D<Cell>, brick: &ActiveBrick)-> Option<(Direction, i16, i16)> {
+        let directions = [Direction::Up, Direction::Right, Direction::Down, Direction::Left];
+        let mut best: Option<(Direction, i16, i16, f64)> = None;
+        for &d in &directions {
+            for x in -(BRICK_GRID_SIZE as i16)..(board.width() as i16) {
+                // 从场地上方开始尝试下落，直到下一格会越界/碰撞为止
+                let mut landed_y = None;
+                for y in -(BRICK_GRID_SIZE as i16)..(board.height() as i16) {
+                    if Game::place(board, brick, d, x, y).is_some() {
+                        landed_y = Some(y);
+                    } else if landed_y.is_some() {
+                        break;
+                    }
+                }
+                let Some(y) = landed_y else { continue };
+                let Some(placed) = Game::place(board, brick, d, x, y) else { continue };
+                let score = Game::score_board(&placed);
+                if best.map_or(true, |(_, _, _, best_score)| score > best_score) {
+                    best = Some((d, x, y, score));
+                }
+            }
+        }
+        best.map(|(d, x, y, _)| (d, x, y))
+    }
+
+    /// 从朝向`from`顺时针转到朝向`to`需要按多少次`RotateClock`，顺序跟`Direction::rotate(true)`的转动方向一致
+    fn rotation_steps(from: Direction, to: Direction)-> u8 {
+        const ORDER: [Direction; 4] = [Direction::Up, Direction::Right, Direction::Down, Direction::Left];
+        let fi = ORDER.iter().position(|&d| d == from).unwrap();
+        let ti = ORDER.iter().position(|&d| d == to).unwrap();
+        ((ti + 4 - fi) % 4) as u8
+    }
+
+    /// 把`rows`行对面发来的攻击堆到棋盘底部：整体上移一行，最下面补一行几乎填满、留一个随机缺口的灰色攻击行
+    /// 每次上移前先看第0行是否已经有方块，有就说明棋盘已经堆到顶，返回`true`表示游戏结束
+    fn push_garbage(grid: &mut Grid2D<Cell>, rows: u8)-> bool {
+        let w = grid.width();
+        let h = grid.height();
+        let mut rng = rand::thread_rng();
+        let mut overflowed = false;
+        for _ in 0..rows {
+            if (0..w).any(|x| grid.get(x, 0).unwrap().has_block()) {
+                overflowed = true;
+            }
+            for y in 0..(h - 1) {
+                for x in 0..w {
+                    let below = grid.get(x, y + 1).unwrap().clone();
+                    *grid.get_mut(x, y).unwrap() = below;
+                }
+            }
+            let gap = rng.gen_range(0..w);
+            for x in 0..w {
+                let cell = grid.get_mut(x, h - 1).unwrap();
+                if x == gap {
+                    cell.clear();
+                } else {
+                    cell.replace(Block { m_color: crossterm::style::Color::DarkGrey });
+                }
+            }
+        }
+        overflowed
+    }
+
+    /// 根据当前等级换算自动下落间隔：每升一级少50ms，下限80ms
+    /// (`tick_time = max_tick - tick_interval*level`，经典俄罗斯方块的标准推进曲线)
+    fn drop_interval_ms(level: u32)-> u64 {
+        const MAX_TICK: u64 = 600;
+        const TICK_STEP: u64 = 50;
+        const MIN_TICK: u64 = 80;
+        MAX_TICK.saturating_sub(level as u64 * TICK_STEP).max(MIN_TICK)
+    }
+
+    /// 排行榜最多保留的条目数
+    const HIGHSCORE_TOP_N: usize = 5;
+
+    /// 把`score`计入`path`处的排行榜文件，返回更新后降序排列、最多`HIGHSCORE_TOP_N`条的排行榜
+    fn record_highscore(path: &str, score: u32)-> Result<Vec<u32>, String> {
+        let mut board: Vec<u32> = fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        board.push(score);
+        board.sort_unstable_by(|a, b| b.cmp(a));
+        board.truncate(Game::HIGHSCORE_TOP_N);
+        let text = serde_json::to_string(&board).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| e.to_string())?;
+        Ok(board)
+    }
+
+    /// 把排行榜画在屏幕中央，风格沿用`draw_string_center`，按任意键关闭
+    fn draw_highscores(board: &[u32]) {
+        let top = Game::calc_center(term_size().unwrap().1, board.len() as u16 + 2);
+        Game::draw_string_center(top, &"排行榜".to_string());
+        for (i, s) in board.iter().enumerate() {
+            Game::draw_string_center(top + 1 + i as u16, &format!("{}. {}", i + 1, s));
+        }
+        stdout().flush().unwrap();
+        let _ = event_read();
     }
 
     pub fn init() {
@@ -121,12 +587,31 @@ impl Game {
         stdout().queue(terminal::Clear(terminal::ClearType::All)).unwrap();
     }
 
+    /// 作为房主监听`addr`，等对面连进来后跑一局联机对战
+    pub fn host(&self, addr: &str)-> std::io::Result<()> {
+        let stream = net::host(addr)?;
+        self.render_game_impl(Some(stream));
+        Ok(())
+    }
+
+    /// 连接到正在`host`的另一方，跑一局联机对战
+    pub fn connect(&self, addr: &str)-> std::io::Result<()> {
+        let stream = net::connect(addr)?;
+        self.render_game_impl(Some(stream));
+        Ok(())
+    }
+
     /// 用到三个线程
     ///  TetrisRender-KeyboardThread(main)
     ///    捕获键盘事件并发送到UpdateThread处理
     ///  TetrisRender-UpdateThread
     ///    用于更新游戏，处理事件
     pub fn render_game(&self) {
+        self.render_game_impl(None);
+    }
+
+    /// `peer`是`Some`时跑联机对战：收/发`NetMessage`，镜像画出对面的棋盘，互相发送消行攻击
+    fn render_game_impl(&self, peer: Option<TcpStream>) {
         #[allow(unused)]
         let mut ycoord = self.config_window_xcoord;
         #[allow(unused)]
@@ -135,20 +620,112 @@ impl Game {
         let gheight = self.config_grid_height;
         let width = gwidth * 2 + 2;
         let height = gheight + 2;
-        let grid = Arc::new(RwLock::new(Grid2D::<Cell>::new(self.config_grid_width, self.config_grid_height)));
-        let brick = Arc::new(RwLock::new(random_brick()));
-        let next_brick = Arc::new(Mutex::new(random_brick()));
-        let brick_x = Arc::new(AtomicI16::new(Game::calc_center(grid.read().unwrap().width(), brick.read().unwrap().get_active_content_width()) as i16));
-        let brick_y = Arc::new(AtomicI16::new(-(brick.read().unwrap().get_active_content_height() as i16)));
+        // 若配置了存档路径且存档存在，开局时直接恢复；否则照常随机生成一局新的
+        let loaded_state = self.config_save_path.as_ref().and_then(|path| Game::load_state(path).ok());
+
+        // 播放模式：从回放文件头还原种子；录制模式：现生成一个新种子存进头里，二者都要在生成任何方块前播种`BRICK_RNG`
+        // 不录不放时保持默认的熵播种，不用关心这个种子
+        let recording = self.config_replay_path.is_some();
+        let replay_playback = self.config_replay_playback_path.as_ref().and_then(|path| ReplayData::load(path).ok());
+        let replay_seed = if let Some(data) = &replay_playback {
+            seed_brick_rng(data.header.seed);
+            data.header.seed
+        } else if recording {
+            let seed: u64 = rand::thread_rng().gen();
+            seed_brick_rng(seed);
+            seed
+        } else {
+            0
+        };
+        let is_playback = replay_playback.is_some();
+
+        let grid = Arc::new(RwLock::new(match &loaded_state {
+            Some(state) => state.grid.clone(),
+            None => Grid2D::<Cell>::new(self.config_grid_width, self.config_grid_height),
+        }));
+        // 未设置`config_brick_set`时沿用硬编码的标准7种形状
+        let brick = Arc::new(RwLock::new(match &loaded_state {
+            Some(state) => ActiveBrick::from_snapshot(state.brick.clone()),
+            None => self.config_brick_set.as_ref().map_or_else(random_brick, random_brick_from),
+        }));
+        let next_brick = Arc::new(Mutex::new(match &loaded_state {
+            Some(state) => ActiveBrick::from_snapshot(state.next_brick.clone()),
+            None => self.config_brick_set.as_ref().map_or_else(random_brick, random_brick_from),
+        }));
+        let brick_set = Arc::new(self.config_brick_set.clone());
+        let brick_x = Arc::new(AtomicI16::new(match &loaded_state {
+            Some(state) => state.brick_x,
+            None => Game::calc_center(grid.read().unwrap().width(), brick.read().unwrap().get_active_content_width()) as i16,
+        }));
+        let brick_y = Arc::new(AtomicI16::new(match &loaded_state {
+            Some(state) => state.brick_y,
+            None => -(brick.read().unwrap().get_active_content_height() as i16),
+        }));
         let condition = Arc::new(AtomicBool::new(true));
-        let score = Arc::new(AtomicU32::new(0));
+        let score = Arc::new(AtomicU32::new(loaded_state.as_ref().map_or(0, |state| state.score)));
+        // 已消除的总行数，`lines / 10`即为当前等级，驱动自动下落间隔的推进曲线
+        let lines = Arc::new(AtomicU32::new(0));
+        // 暂存槽：为空表示还没换过手；`hold_used`在每次换手后置true，锁定新方块时重置回false
+        let held_brick: Arc<Mutex<Option<BrickSnapshot>>> = Arc::new(Mutex::new(None));
+        let hold_used = Arc::new(AtomicBool::new(false));
         let game_over_flag = Arc::new(AtomicBool::new(false));
         let (continue_notify, continue_trigger) = channel::<()>();
         let dbg_enabled = Arc::new(AtomicBool::new(self.config_debug_enabled));
+        let ai_enabled = Arc::new(AtomicBool::new(self.config_ai_enabled));
         let (key_sender, key_receiver) = channel::<GameEvent>();
 
+        // 整局事件流的录制缓冲区：送进`key_sender`的每个事件都带上相对`replay_start`的毫秒数存一份
+        // `config_replay_path`设置了才会真的写进去，退出时连同种子一起存成`.replay`文件
+        let replay_start = Instant::now();
+        let replay_log: Arc<Mutex<Vec<ReplayEntry>>> = Arc::new(Mutex::new(Vec::new()));
+
+        // 联机对战：`writer`用来往对面发`NetMessage`，`opponent`存对面最近一次汇报的局面快照用来画镜像棋盘
+        // 读对面消息是独立的一个线程(`net::spawn_reader`)，解析出来的攻击行数经同一个`key_sender`
+        // 转成`GameEvent::ReceiveGarbage`，跟键盘事件走一样的单消费者处理路径
+        let writer = Arc::new(peer.as_ref().map(|stream| Mutex::new(stream.try_clone().expect("Failed to clone peer stream for writing"))));
+        let opponent: Arc<Mutex<Option<PeerState>>> = Arc::new(Mutex::new(None));
+        let _net_reader = peer.map(|stream| {
+            let key_sender = key_sender.clone();
+            let opponent = Arc::clone(&opponent);
+            let replay_log = Arc::clone(&replay_log);
+            net::spawn_reader(stream, move |message| {
+                match message {
+                    NetMessage::Garbage(rows) => {
+                        if recording {
+                            let elapsed_ms = replay_start.elapsed().as_millis() as u64;
+                            replay_log.lock().unwrap().push(ReplayEntry { elapsed_ms, event: GameEvent::ReceiveGarbage(rows) });
+                        }
+                        let _ = key_sender.send(GameEvent::ReceiveGarbage(rows));
+                    },
+                    NetMessage::State(state) => {
+                        *opponent.lock().unwrap() = Some(state);
+                    },
+                }
+            })
+        });
+
         let dbg_brick_pos_enabled = Arc::new(AtomicBool::new(false));
 
+        // 每发送一次操作事件就自增一次，写入日志用于回放
+        let move_tick = Arc::new(AtomicU32::new(0));
+        let send_event = {
+            let key_sender = key_sender.clone();
+            let move_tick = Arc::clone(&move_tick);
+            let move_log_path = self.config_move_log_path.clone();
+            let replay_log = Arc::clone(&replay_log);
+            move |event: GameEvent| {
+                let tick = move_tick.fetch_add(1, SeqCst) as u64;
+                if let Some(path) = &move_log_path {
+                    let _ = Game::append_log(path, tick, event);
+                }
+                if recording {
+                    let elapsed_ms = replay_start.elapsed().as_millis() as u64;
+                    replay_log.lock().unwrap().push(ReplayEntry { elapsed_ms, event });
+                }
+                key_sender.send(event).unwrap();
+            }
+        };
+
         // 绘制标题
         //Game::draw_string_center((size.1 as f64 * 0.2) as u16, self.m_title);
 
@@ -206,14 +783,50 @@ impl Game {
             condition.store(false, Release);
         });
 
+        // AI专用的发送端：落点选好后翻译成的按键事件都从这个克隆体塞进`key_sender`，跟键盘/网络走一样的消费路径，
+        // 下游(回放录制、更新线程主循环)不需要关心某个`GameEvent`到底是人按的还是AI选的
+        let ai_key_sender = key_sender.clone();
+
         let _gheight = gheight;
         let update_func = arc_borrow_closure!(
-        (condition, brick, grid, brick_x, brick_y, dbg_brick_pos_enabled, dbg_enabled, score, next_brick)
+        (condition, brick, grid, brick_x, brick_y, dbg_brick_pos_enabled, dbg_enabled, score, lines, next_brick, ai_enabled, writer, game_over_flag, brick_set, held_brick, hold_used)
         move || {
             set_panic_hook!({});
+
+            // 新方块生成后，如果开了AI就让`Game::step_ai`选出最佳落点，再把"现在的朝向/横坐标"到
+            // "目标朝向/横坐标"之间的差距翻译成一串真正的RotateClock/MoveLeft/MoveRight事件，
+            // 最后补一个HardDrop落地；事件塞进`key_sender`后由下一轮主循环照常处理，跟人按键盘没有区别
+            let ai_play = arc_borrow_closure!(
+            (brick, grid, brick_x, brick_y, ai_enabled)
+            move || {
+                if !ai_enabled.load(Acquire) {
+                    return;
+                }
+                let (from, target) = {
+                    let grid = grid.read().unwrap();
+                    let brick = brick.read().unwrap();
+                    (brick.direction(), Game::step_ai(&grid, &brick))
+                };
+                let Some((to, target_x, _target_y)) = target else { return };
+                for _ in 0..Game::rotation_steps(from, to) {
+                    let _ = ai_key_sender.send(GameEvent::RotateClock);
+                }
+                let current_x = brick_x.load(Acquire);
+                for _ in 0..(current_x - target_x).max(0) {
+                    let _ = ai_key_sender.send(GameEvent::MoveLeft);
+                }
+                for _ in 0..(target_x - current_x).max(0) {
+                    let _ = ai_key_sender.send(GameEvent::MoveRight);
+                }
+                let _ = ai_key_sender.send(GameEvent::HardDrop);
+            });
+            // `arc_borrow_closure!`只会`Arc::clone`它捕获的变量，所以要把这个闭包本身也包一层`Arc`
+            // 才能被`store_and_new_brick`按同样的方式捕获
+            let ai_play = Arc::new(ai_play);
+
             // 将砖块存储到网格中，并消除满的一行
             let store_and_new_brick = arc_borrow_closure!(
-            (grid, brick_x, brick_y, brick, score, next_brick)
+            (grid, brick_x, brick_y, brick, score, lines, next_brick, ai_play, writer, brick_set, hold_used)
             move ||-> bool {
                 let gheight;
                 {
@@ -224,9 +837,11 @@ impl Game {
                 let mut brick = brick.write().unwrap();
                 let _guard = stdout().lock();
                 // 将下一个brick与新生成的swap，再将旧的brick写入网格，同时重置坐标
-                let mut old_brick = random_brick();
+                let mut old_brick = brick_set.as_ref().as_ref().map_or_else(random_brick, random_brick_from);
                 swap(MutexGuard::deref_mut(&mut next_brick.lock().unwrap()), &mut old_brick);
                 swap(RwLockWriteGuard::deref_mut(&mut brick), &mut old_brick);
+                // 新方块已经锁定生成，换手机会重新可用
+                hold_used.store(false, Release);
                 let b_grid = old_brick.get_active_grid();
                 brick_x.store(Game::calc_center(grid.width(), brick.get_active_content_width()) as i16, Release);
                 brick_y.store(-(brick.get_active_content_height() as i16), Release);
@@ -251,6 +866,7 @@ impl Game {
 
                 // 检测满的行并消除
                 let mut y_iter = 1;
+                let mut cleared_lines: u32 = 0;
                 while y_iter <= gheight {
                     let y = gheight - y_iter;
                     let mut is_full = true;
@@ -281,11 +897,20 @@ impl Game {
                         y_iter -= 1;
                         }
                         score.fetch_add(gwidth as u32, SeqCst);
-                        thread::sleep(Duration::from_millis(400));
+                        cleared_lines += 1;
+                        lines.fetch_add(1, SeqCst);
+                        thread::sleep(Duration::from_millis(Game::drop_interval_ms(lines.load(Acquire) / 10)));
                     }
 
                     y_iter += 1;
                 }
+                // 清的行数减一行发给对面当攻击(单行不触发攻击，跟大多数对战俄罗斯方块一致)
+                if let Some(writer) = writer.as_ref() {
+                    if cleared_lines >= 1 {
+                        let _ = NetMessage::Garbage((cleared_lines - 1).min(u8::MAX as u32) as u8).send(&mut writer.lock().unwrap());
+                    }
+                }
+                ai_play();
                 false
             });
             // 这个闭包用于将砖块向下移动指定距离
@@ -316,6 +941,8 @@ impl Game {
                 false
             });
             let key_receiver = key_receiver;
+            // 场上第一块砖也要过一遍AI，不然得等第一次锁定之后才会接管
+            ai_play();
             let mut timer = Duration::ZERO;
             while condition.load(Acquire) {
                 let begin = Instant::now();
@@ -382,48 +1009,87 @@ impl Game {
                             }
                         },
                         GameEvent::MoveDown => {
+                            // 软降：用户主动按下导致的下落才给分，重力自动下落不走这个事件
                             if move_down(1) {
                                 continue;
                             }
+                            score.fetch_add(1, SeqCst);
+                        },
+                        GameEvent::HardDrop => {
+                            // 硬降：一路下落到锁定为止，按掉落的格数给分(每格比软降多一倍)
+                            let mut cells_dropped: u32 = 0;
+                            while !move_down(1) {
+                                cells_dropped += 1;
+                            }
+                            score.fetch_add(cells_dropped * 2, SeqCst);
+                            continue;
+                        },
+                        GameEvent::Hold => {
+                            // 一次锁定前只能换一次手
+                            if hold_used.load(Acquire) {
+                                continue;
+                            }
+                            hold_used.store(true, Release);
+                            let current = brick.read().unwrap().snapshot();
+                            let mut held = held_brick.lock().unwrap();
+                            let incoming = match held.take() {
+                                Some(snap) => ActiveBrick::from_snapshot(snap),
+                                None => {
+                                    // 暂存槽为空：把下一个方块换上来顶替，再给下一个方块补一个新的
+                                    let mut nb = next_brick.lock().unwrap();
+                                    let incoming = ActiveBrick::from_snapshot(nb.snapshot());
+                                    *nb = brick_set.as_ref().as_ref().map_or_else(random_brick, random_brick_from);
+                                    incoming
+                                },
+                            };
+                            *held = Some(current);
+                            drop(held);
+                            *brick.write().unwrap() = incoming;
+                            let b = brick.read().unwrap();
+                            brick_x.store(Game::calc_center(grid.read().unwrap().width(), b.get_active_content_width()) as i16, Release);
+                            brick_y.store(-(b.get_active_content_height() as i16), Release);
                         },
                         GameEvent::Pause => {
                             continue_trigger.recv().unwrap();
                         },
                         GameEvent::RotateClock => {
+                            // SRS：依次尝试踢墙偏移表中的每一项，第一个不与墙壁/已落地方块碰撞的即为最终位置
                             let b_x = brick_x.load(Acquire);
                             let b_y = brick_y.load(Acquire);
                             let grid = grid.read().unwrap();
-                            let origd;
-                            let rd;
-                            let r_grid;
-                            let mut blocked_list = Vec::<Position>::new();
-                            {
                             let brick = brick.read().unwrap();
-                            origd = brick.direction();
-                            rd = origd.rotate(true);
-                            r_grid = brick.get_grid(rd);
-                            for x in 0..r_grid.width() {
-                                for y in 0..r_grid.height() {
-                                    let cell = r_grid.get(x, y).unwrap();
-                                    let tmpx = x as i16 + b_x;
-                                    let tmpy = y as i16 + b_y;
-                                    if cell.has_block() && tmpy >= 0 && (!check_is_inrange(tmpx, tmpy) || grid.get(tmpx as u16, tmpy as u16).unwrap().has_block()) {
-                                        blocked_list.push(Position(x as i16, y as i16));
-                                    }
-                                }
+                            if let Some((_, nx, ny)) = brick.try_rotate(true, &grid, b_x, b_y) {
+                                brick_x.store(nx, Release);
+                                brick_y.store(ny, Release);
                             }
+                        },
+                        GameEvent::RotateUnclock => {
+                            // 逆时针旋转，踢墙表已经按(from, to)两个方向都收录了，直接传false复用同一套SRS逻辑
+                            let b_x = brick_x.load(Acquire);
+                            let b_y = brick_y.load(Acquire);
+                            let grid = grid.read().unwrap();
+                            let brick = brick.read().unwrap();
+                            if let Some((_, nx, ny)) = brick.try_rotate(false, &grid, b_x, b_y) {
+                                brick_x.store(nx, Release);
+                                brick_y.store(ny, Release);
                             }
-                            if blocked_list.is_empty() {
-                                brick.write().unwrap().switch(rd);
+                        },
+                        GameEvent::ReceiveGarbage(rows) => {
+                            let mut grid = grid.write().unwrap();
+                            if Game::push_garbage(&mut grid, rows) {
+                                drop(grid);
+                                game_over_flag.store(true, Release);
+                                condition.store(false, Release);
                             }
                         },
                         _ => {},
                     }
                 }
 
-                // 如果累计时间超过400毫秒就执行下落
+                // 累计时间超过当前等级对应的下落间隔就执行下落；等级越高间隔越短
                 timer += begin.elapsed();
-                if timer >= Duration::from_millis(400) {
+                let drop_interval = Duration::from_millis(Game::drop_interval_ms(lines.load(Acquire) / 10));
+                if timer >= drop_interval {
                     if move_down(1) {
                         continue;
                     }
@@ -438,63 +1104,39 @@ impl Game {
             .spawn(update_func)
             .unwrap();
 
-        // 渲染暂停界面
-        // 会阻塞调用的线程直到用户按下继续或退出
-        // 若用户按下q键退出则返回true
-        let render_pause = arc_borrow_closure!(
-        ()
-        ||-> bool {
-            let mut stdout = stdout();
-            loop {
-                let cpos = Game::calc_center(term_size().unwrap().1, 2);
-                Game::draw_string_center(cpos, &"已暂停".to_string());
-                Game::draw_string_center(cpos+1, &"按下Q退出，按下C继续游戏".to_string());
-                stdout.flush().unwrap();
-                let event_result = event_read();
-                if let Ok(event) = event_result {
-                    match event {
-                        Event::Resize(_, _) => {
-                            stdout.queue(terminal::Clear(terminal::ClearType::All)).unwrap();
-                        },
-                        Event::Key(key) => {
-                            match key.code {
-                                KeyCode::Char(c) => {
-                                    match c {
-                                        'c' | 'C' => {
-                                            return false;
-                                        },
-                                        'q' | 'Q' => {
-                                            return true;
-                                        },
-                                        _ => {},
-                                    };
-                                },
-                                _ => {},
-                            };
-                        },
-                        _ => {},
-                    }
-                }
-            };
+        // 播放模式：把回放里的事件按录制时的时间表喂回`key_sender`，更新线程收到的事件类型/处理路径跟键盘/网络完全一样
+        let _replay_playback_thread = replay_playback.map(|data| replay::spawn_playback(data.entries, key_sender.clone()));
+
+        // 暂停时压到输入上下文栈顶的那个上下文是现做的：`PausedContext`自己不常驻，每次按下暂停键才现造一个
+        // (跟以前那个阻塞的`render_pause`子循环比，状态现在活在上下文对象里，而不是某个函数调用栈里)
+        let save_path = self.config_save_path.clone();
+        let make_paused_context = arc_borrow_closure!(
+        (grid, brick, next_brick, brick_x, brick_y, score)
+        move ||-> Box<dyn InputContext> {
+            Box::new(PausedContext::new(
+                grid.clone(),
+                brick.clone(),
+                next_brick.clone(),
+                brick_x.clone(),
+                brick_y.clone(),
+                score.clone(),
+                save_path.clone(),
+                continue_notify.clone(),
+            ))
         });
 
-        // 绘制背景网格
-        let draw_grid = || {
-            // 锁定网格
-            let grid = grid.read().unwrap();
-            let mut stdout = stdout();
-            // 绘制网格中的砖块
-            for y in 0..gheight {
-                stdout.queue(cursor::MoveTo(xcoord + 1, ycoord + 1 + y)).unwrap();
-                for x in 0..gwidth {
-                    let cell = grid.get(x, y).unwrap();
-                    if cell.has_block() {
-                        stdout.queue(style::Print("  ".on((*cell).m_color))).unwrap();
-                    } else {
-                        stdout.queue(cursor::MoveRight(2)).unwrap();
-                        continue;
-                    }
-                }
+        // 绘制背景网格：通过双缓冲渲染器只为变化的格子写入终端
+        let mut grid_renderer = Renderer::new(xcoord + 1, ycoord + 1, gwidth, gheight);
+        let mut draw_grid = {
+            let grid = Arc::clone(&grid);
+            move || {
+                // 锁定网格
+                let grid = grid.read().unwrap();
+                grid_renderer.begin_frame();
+                grid.for_each(|x, y, cell| {
+                    grid_renderer.set_cell(x, y, if cell.has_block() { Some((*cell).clone()) } else { None });
+                });
+                grid_renderer.present();
             }
         };
 
@@ -568,7 +1210,7 @@ impl Game {
 
         // 绘制Dashboard的内容
         let draw_dashboard = arc_borrow_closure!(
-        (score, next_brick)
+        (score, lines, next_brick)
         move || {
             let w = BRICK_GRID_SIZE * 2;
             //let h = BRICK_GRID_SIZE + 3;
@@ -594,6 +1236,14 @@ impl Game {
                 ).unwrap();
             }
 
+            // 绘制等级
+            let level_str = format!("等级;{}", lines.load(Acquire) / 10);
+            queue!(
+                stdout(),
+                cursor::MoveTo(xcoord + width + 1, ycoord + 3),
+                style::Print(level_str),
+            ).unwrap();
+
             // 绘制下一个brick
             let mut stdout = stdout();
             let nbrick = next_brick.lock().unwrap();
@@ -612,6 +1262,103 @@ impl Game {
             }
         });
 
+        // 对面棋盘镜像画在dashboard右边，边框画法跟`draw_border`一致，只是挪到另一个位置
+        let opponent_xcoord = xcoord + width + (BRICK_GRID_SIZE * 2 + 4);
+
+        let draw_opponent_border = || {
+            let mut stdout = stdout().lock();
+            #[allow(unused)]
+            let mut s = String::new();
+            for y in 0..height {
+                for x in 0..width {
+                    if x == 0 {
+                        if y == 0 {
+                            s = "╔".to_string()
+                        } else if y == height - 1 {
+                            s = "╚".to_string()
+                        } else {
+                            s = "║".to_string()
+                        }
+                    } else if x == width - 1 {
+                        if y == 0 {
+                            s = "╗".to_string()
+                        } else if y == height - 1 {
+                            s = "╝".to_string()
+                        } else {
+                            s = "║".to_string()
+                        }
+                    } else {
+                        if y == 0 || y == height - 1 {
+                            s = "═".to_string()
+                        } else {
+                            continue;
+                        }
+                    }
+                    queue!(stdout,
+                        cursor::MoveTo(opponent_xcoord + x, ycoord + y),
+                        style::Print(s),
+                    ).unwrap();
+                }
+            }
+        };
+
+        // 绘制对面最近一次汇报的棋盘与正在下落的方块；还没收到过汇报时什么都不画
+        let draw_opponent = || {
+            let opponent = opponent.lock().unwrap();
+            let Some(state) = opponent.as_ref() else { return };
+            for y in 0..state.grid.height() {
+                for x in 0..state.grid.width() {
+                    let cell = state.grid.get(x, y).unwrap();
+                    if cell.has_block() {
+                        queue!(
+                            stdout(),
+                            cursor::MoveTo(opponent_xcoord + 1 + x * 2, ycoord + 1 + y),
+                            style::Print("  ".on((*cell).m_color)),
+                        ).unwrap();
+                    } else {
+                        queue!(
+                            stdout(),
+                            cursor::MoveTo(opponent_xcoord + 1 + x * 2, ycoord + 1 + y),
+                            style::Print("  "),
+                        ).unwrap();
+                    }
+                }
+            }
+            let active = ActiveBrick::from_snapshot(state.brick.clone());
+            let b_grid = active.get_active_grid();
+            for x in 0..BRICK_GRID_SIZE {
+                for y in 0..BRICK_GRID_SIZE {
+                    let cell = b_grid.get(x, y).unwrap();
+                    let tmpx = state.brick_x + x as i16;
+                    let tmpy = state.brick_y + y as i16;
+                    if tmpx < 0 || tmpy < 0 || tmpx >= gwidth as i16 || tmpy >= gheight as i16 {
+                        continue;
+                    }
+                    if cell.has_block() {
+                        queue!(
+                            stdout(),
+                            cursor::MoveTo(opponent_xcoord + 1 + (tmpx as u16 * 2), ycoord + 1 + tmpy as u16),
+                            style::Print("  ".on((*cell).m_color)),
+                        ).unwrap();
+                    }
+                }
+            }
+        };
+
+        // 把本地局面打包发给对面，供对面画镜像棋盘；没有联机对手时什么都不做
+        let send_state = || {
+            if let Some(writer) = writer.as_ref() {
+                let state = PeerState {
+                    grid: grid.read().unwrap().clone(),
+                    score: score.load(Acquire),
+                    brick: brick.read().unwrap().snapshot(),
+                    brick_x: brick_x.load(Acquire),
+                    brick_y: brick_y.load(Acquire),
+                };
+                let _ = NetMessage::State(state).send(&mut writer.lock().unwrap());
+            }
+        };
+
         let dbg_draw_brick_pos = |out_y: &mut u16| {
             if dbg_brick_pos_enabled.load(Acquire) {
                 queue!(
@@ -629,77 +1376,122 @@ impl Game {
             update_thread.join().unwrap();
         };
 
+        // 退出前如果开着录制，把整局事件流连同种子写成`.replay`文件；没配路径时什么都不做，跟其它"设置了路径才做"的收尾逻辑一致
+        let save_replay = || {
+            if let Some(path) = &self.config_replay_path {
+                let data = ReplayData {
+                    header: ReplayHeader { seed: replay_seed },
+                    entries: replay_log.lock().unwrap().clone(),
+                };
+                let _ = data.save(path, self.config_replay_compressed);
+            }
+        };
+
+        // 按键映射表：优先用配置文件里的绑定，加载失败(没配/文件有问题)就落回硬编码的默认绑定
+        let keymap = self.config_keymap_path.as_ref()
+            .and_then(|path| KeyMap::load(path).ok())
+            .unwrap_or_default();
+
+        // 输入上下文栈：每帧只有栈顶的上下文收事件，暂停菜单压栈后游戏进行中的那个上下文就先晾在一边
+        let mut context_stack: Vec<Box<dyn InputContext>> = vec![
+            Box::new(PlayingContext::new(
+                Box::new(send_event),
+                Box::new(make_paused_context),
+                self.config_das_ms,
+                self.config_arr_ms,
+            )),
+        ];
+
         while condition.load(Acquire) {
-            Game::clear_screen();
-            draw_grid();
-            draw_brick();
-            draw_border();
-            draw_dashboard_border();
-            draw_dashboard();
-            //draw_dashboard();
+            // 游戏进行画面由渲染循环自己画，不走上下文栈：暂停菜单压栈后这一段就不再执行，跟以前整屏被暂停菜单接管一致
+            if context_stack.len() == 1 {
+                draw_grid();
+                draw_brick();
+                draw_border();
+                draw_dashboard_border();
+                draw_dashboard();
+                //draw_dashboard();
+                draw_opponent_border();
+                draw_opponent();
+                send_state();
 
-            let mut dbg_y = ycoord + height;
-            dbg_draw_brick_pos(&mut dbg_y);
+                let mut dbg_y = ycoord + height;
+                dbg_draw_brick_pos(&mut dbg_y);
+            }
+            context_stack.last().unwrap().render();
 
             stdout().flush().unwrap();
-            if event_poll(Duration::from_millis(100)).unwrap() {
+
+            let mut transition = Transition::None;
+            if event_poll(Duration::from_millis(16)).unwrap() {
                 let event = event_read();
                 if let Err(_) = event {
                     continue;
                 }
                 match event.unwrap() {
-                    /*Event::Resize(_, _) => {
+                    Event::Resize(_, _) => {
+                        // 终端尺寸变化时整屏重绘一次，后续帧再回到增量绘制
+                        Game::clear_screen();
                         draw_border();
                         draw_dashboard_border();
-                    },*/
+                        draw_opponent_border();
+                    },
                     Event::Key(key) => {
-                        match key.code {
-                            KeyCode::Char(c) => {
-                                if key.modifiers.is_empty() {
-                                    match c {
-                                        'q' | 'Q' => {
-                                            stop_rendering();
-                                            return;
-                                        },
-                                        'p' | 'P' => {
-                                            key_sender.send(GameEvent::Pause).unwrap();
-                                            if render_pause() {
-                                                continue_notify.send(()).unwrap();
-                                                stop_rendering();
-                                                return;
-                                            }
-                                            continue_notify.send(()).unwrap();
-                                        },
-                                        _ => {},
-                                    };
-                                } else if key.modifiers.contains(KeyModifiers::CONTROL) {
-                                    match c {
-                                        'b' => {
-                                            key_sender.send(GameEvent::DebugBrickPosition).unwrap();
-                                        },
-                                        _ => {},
-                                    };
-                                }
-                            },
-                            KeyCode::Left => {
-                                key_sender.send(GameEvent::MoveLeft).unwrap();
-                            },
-                            KeyCode::Right => {
-                                key_sender.send(GameEvent::MoveRight).unwrap();
-                            },
-                            KeyCode::Down => {
-                                key_sender.send(GameEvent::MoveDown).unwrap();
-                            },
-                            KeyCode::Up => {
-                                key_sender.send(GameEvent::RotateClock).unwrap();
-                            },
-                            _ => {},
-                        };
+                        if is_playback {
+                            // 播放模式下键盘只用来退出，游戏事件全部来自`replay::spawn_playback`按录制时间表回放，不再现场轮询
+                            if let Some(KeyAction::Quit) = keymap.lookup(key.code, key.modifiers) {
+                                transition = Transition::Quit;
+                            }
+                        } else {
+                            transition = context_stack.last_mut().unwrap().handle_key(key, &keymap);
+                        }
                     },
                     _ => {},
                 };
-                thread::sleep(Duration::from_millis(10));
+            }
+
+            match transition {
+                Transition::None => {},
+                Transition::Push(ctx) => context_stack.push(ctx),
+                Transition::Pop => { context_stack.pop(); },
+                Transition::Replace(ctx) => {
+                    context_stack.pop();
+                    context_stack.push(ctx);
+                },
+                Transition::Quit => {
+                    save_replay();
+                    stop_rendering();
+                    return;
+                },
+            };
+
+            // 不管这一帧有没有收到按键事件都要走一遍tick：DAS/ARR这类计时逻辑跟离散按键事件无关
+            match context_stack.last_mut().unwrap().tick() {
+                Transition::None => {},
+                Transition::Push(ctx) => context_stack.push(ctx),
+                Transition::Pop => { context_stack.pop(); },
+                Transition::Replace(ctx) => {
+                    context_stack.pop();
+                    context_stack.push(ctx);
+                },
+                Transition::Quit => {
+                    save_replay();
+                    stop_rendering();
+                    return;
+                },
+            };
+        }
+
+        // 走到这里意味着是自然结束(而不是用户按Q主动退出，那条路径已经在上面提前return了)
+        // 把本局分数计入排行榜并展示出来
+        if game_over_flag.load(Acquire) {
+            if let Some(path) = &self.config_highscore_path {
+                if let Ok(board) = Game::record_highscore(path, score.load(Acquire)) {
+                    Game::draw_highscores(&board);
+                }
             }
         }
+        save_replay();
+        stop_rendering();
     }
 }
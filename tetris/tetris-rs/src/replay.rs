@@ -0,0 +1,91 @@
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::mpsc::Sender;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use flate2::read::GzDecoder;
+use serde::{Serialize, Deserialize};
+
+use crate::render::GameEvent;
+
+/// gzip文件开头的魔数，`ReplayData::load`靠它判断一份回放是否经过压缩，不用调用方记住当初存的时候选了哪种格式
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// 回放文件头：录制时用来生成方块序列的随机种子
+/// 回放时先拿它重新播种`crate::grid::seed_brick_rng`，后续`random_brick`/`random_brick_from`就会按录制时的顺序出块
+#[derive(Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub seed: u64,
+}
+
+/// 一条回放记录：事件相对开局经过的毫秒数，以及触发的`GameEvent`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    pub elapsed_ms: u64,
+    pub event: GameEvent,
+}
+
+/// 整局回放：种子 + 按时间顺序排列的事件流
+#[derive(Serialize, Deserialize)]
+pub struct ReplayData {
+    pub header: ReplayHeader,
+    pub entries: Vec<ReplayEntry>,
+}
+
+impl ReplayData {
+    pub fn new(seed: u64)-> Self {
+        Self { header: ReplayHeader { seed }, entries: Vec::new() }
+    }
+
+    /// 写到`path`；`compressed`为true时走gzip(flate2)压缩，体积更小，但不能直接用文本工具查看
+    pub fn save(&self, path: &str, compressed: bool)-> Result<(), String> {
+        let text = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        if compressed {
+            let file = File::create(path).map_err(|e| e.to_string())?;
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())?;
+            Ok(())
+        } else {
+            fs::write(path, text).map_err(|e| e.to_string())
+        }
+    }
+
+    /// 读回一份回放文件，按开头的gzip魔数自动识别是否压缩过
+    pub fn load(path: &str)-> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let text = if bytes.starts_with(&GZIP_MAGIC) {
+            let mut decoder = GzDecoder::new(&bytes[..]);
+            let mut out = String::new();
+            decoder.read_to_string(&mut out).map_err(|e| e.to_string())?;
+            out
+        } else {
+            String::from_utf8(bytes).map_err(|e| e.to_string())?
+        };
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+}
+
+/// 按录制时的时间表把回放里的事件逐条送回`key_sender`，驱动一局跟录制时完全一致的对局
+/// 跟`net::spawn_reader`一样单开一个线程把事件塞进同一个channel，更新线程不需要关心事件到底是键盘、网络还是回放喂进来的
+pub fn spawn_playback(entries: Vec<ReplayEntry>, key_sender: Sender<GameEvent>)-> thread::JoinHandle<()> {
+    thread::Builder::new()
+        .name("Tetris-ReplayThread".to_string())
+        .spawn(move || {
+            let start = Instant::now();
+            for entry in entries {
+                let target = Duration::from_millis(entry.elapsed_ms);
+                if let Some(remaining) = target.checked_sub(start.elapsed()) {
+                    thread::sleep(remaining);
+                }
+                if key_sender.send(entry.event).is_err() {
+                    break;
+                }
+            }
+        })
+        .unwrap()
+}